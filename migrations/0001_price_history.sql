@@ -0,0 +1,49 @@
+-- Venue prices as received, in the requested quote currency
+CREATE TABLE IF NOT EXISTS price_updates (
+    id BIGSERIAL PRIMARY KEY,
+    source TEXT NOT NULL,
+    pair TEXT NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    bid DOUBLE PRECISION NOT NULL,
+    ask DOUBLE PRECISION NOT NULL,
+    bid_size DOUBLE PRECISION,
+    ask_size DOUBLE PRECISION,
+    timestamp_ms BIGINT NOT NULL,
+    native_quote TEXT NOT NULL,
+    fx_rate DOUBLE PRECISION NOT NULL,
+    trade_size DOUBLE PRECISION
+);
+CREATE INDEX IF NOT EXISTS price_updates_pair_time ON price_updates (pair, timestamp_ms);
+
+-- Closed OHLCV candles, per venue and composite
+CREATE TABLE IF NOT EXISTS candles (
+    pair TEXT NOT NULL,
+    venue TEXT NOT NULL,
+    interval TEXT NOT NULL,
+    open_time_ms BIGINT NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    volume DOUBLE PRECISION NOT NULL,
+    ticks BIGINT NOT NULL,
+    PRIMARY KEY (pair, venue, interval, open_time_ms)
+);
+
+-- Best cross-venue opportunity whenever it changed
+CREATE TABLE IF NOT EXISTS opportunities (
+    id BIGSERIAL PRIMARY KEY,
+    pair TEXT NOT NULL,
+    timestamp_ms BIGINT NOT NULL,
+    buy_source TEXT NOT NULL,
+    buy_price DOUBLE PRECISION NOT NULL,
+    sell_source TEXT NOT NULL,
+    sell_price DOUBLE PRECISION NOT NULL,
+    spread_percent DOUBLE PRECISION NOT NULL,
+    net_spread_percent DOUBLE PRECISION NOT NULL,
+    buy_fee_bps DOUBLE PRECISION NOT NULL,
+    sell_fee_bps DOUBLE PRECISION NOT NULL,
+    executable_size DOUBLE PRECISION,
+    expected_profit_quote DOUBLE PRECISION
+);
+CREATE INDEX IF NOT EXISTS opportunities_pair_time ON opportunities (pair, timestamp_ms);
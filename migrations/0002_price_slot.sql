@@ -0,0 +1,2 @@
+-- Solana slot of on-chain prices (Orca, Raydium)
+ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS slot BIGINT;
@@ -1,69 +1,423 @@
-use crate::state::PriceUpdate;
+use crate::connectors::now_ms;
+use crate::fees::FeeModel;
+use crate::orderbook::{BookStore, Side};
+use crate::state::{PriceUpdate, VenueMonitor, VenueStatus};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast::Sender;
 
+/// Default max age of a venue price before it is ignored
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(15);
+/// Default capacity of the per-pair price and feed channels
+const DEFAULT_CHANNEL_CAPACITY: usize = 5000;
+
+/// Tunables shared by the engine of every pair
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Opportunities are sized until the marginal net spread drops below this
+    pub target_spread_percent: f64,
+    /// Prices older than this, by venue timestamp or by receive time, are stale
+    pub max_age: Duration,
+    /// Per-venue overrides of `max_age`, keyed by lowercase venue name
+    pub venue_max_age: HashMap<String, Duration>,
+    /// Capacity of the per-pair price and feed channels
+    pub channel_capacity: usize,
+}
+
+impl EngineConfig {
+    /// Accept prices of any age, for replayed traffic whose venue timestamps are in the past
+    pub fn without_age_limits(mut self) -> Self {
+        self.max_age = Duration::from_millis(u64::MAX);
+        self.venue_max_age.clear();
+        self
+    }
+
+    pub fn max_age(&self, venue: &str) -> Duration {
+        self.venue_max_age
+            .get(&venue.to_lowercase())
+            .copied()
+            .unwrap_or(self.max_age)
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            target_spread_percent: 0.0,
+            max_age: DEFAULT_MAX_AGE,
+            venue_max_age: HashMap::new(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ArbitrageOpportunity {
     pub pair: String,
     pub best_buy_source: String,
-    pub best_buy_price: f64,
+    pub best_buy_price: f64, // Ask on the buy venue
     pub best_sell_source: String,
-    pub best_sell_price: f64,
-    pub spread_percent: f64,
+    pub best_sell_price: f64,    // Bid on the sell venue
+    pub spread_percent: f64,     // Gross
+    pub net_spread_percent: f64, // After taker/pool fees on both legs
+    pub buy_fee_bps: f64,
+    pub sell_fee_bps: f64,
+    // Depth-aware sizing (None when either venue reports no size at all)
+    pub executable_size: Option<f64>, // Base amount tradable while every unit clears the target net spread
+    pub expected_profit_quote: Option<f64>, // Sell proceeds - buy cost for that amount, net of fees
+    pub buy_levels: Vec<(f64, f64)>,  // Asks walked on the buy venue: [price, size filled]
+    pub sell_levels: Vec<(f64, f64)>, // Bids walked on the sell venue: [price, size filled]
+}
+
+/// Result of walking one venue's asks against another venue's bids
+#[derive(Debug, Default)]
+struct Execution {
+    size: f64,
+    cost: f64,
+    proceeds: f64,
+    buy_levels: Vec<(f64, f64)>,
+    sell_levels: Vec<(f64, f64)>,
+}
+
+/// Add `size` at `price` to the walked levels, merging fills of the same level
+fn record_fill(levels: &mut Vec<(f64, f64)>, price: f64, size: f64) {
+    match levels.last_mut() {
+        Some((last_price, filled)) if *last_price == price => *filled += size,
+        _ => levels.push((price, size)),
+    }
+}
+
+/// Buy cost per unit after a taker fee
+fn with_buy_fee(price: f64, fee_bps: f64) -> f64 {
+    price * (1.0 + fee_bps / 10_000.0)
+}
+
+/// Sell proceeds per unit after a taker fee
+fn with_sell_fee(price: f64, fee_bps: f64) -> f64 {
+    price * (1.0 - fee_bps / 10_000.0)
+}
+
+/// Take size level by level while the marginal sell price (net of `sell_fee_bps`)
+/// stays at least `target_spread_percent` above the marginal buy price (net of
+/// `buy_fee_bps`). Walked levels keep the venue prices, cost and proceeds include fees.
+fn walk_books(
+    asks: &[(f64, f64)],
+    bids: &[(f64, f64)],
+    buy_fee_bps: f64,
+    sell_fee_bps: f64,
+    target_spread_percent: f64,
+) -> Execution {
+    let mut execution = Execution::default();
+    let (mut i, mut j) = (0, 0);
+    let mut ask_left = asks.first().map_or(0.0, |l| l.1);
+    let mut bid_left = bids.first().map_or(0.0, |l| l.1);
+
+    while i < asks.len() && j < bids.len() {
+        let (ask, bid) = (asks[i].0, bids[j].0);
+        let (buy_price, sell_price) = (
+            with_buy_fee(ask, buy_fee_bps),
+            with_sell_fee(bid, sell_fee_bps),
+        );
+        if sell_price < buy_price * (1.0 + target_spread_percent / 100.0) {
+            break;
+        }
+
+        let size = ask_left.min(bid_left);
+        execution.size += size;
+        execution.cost += size * buy_price;
+        execution.proceeds += size * sell_price;
+        record_fill(&mut execution.buy_levels, ask, size);
+        record_fill(&mut execution.sell_levels, bid, size);
+
+        ask_left -= size;
+        bid_left -= size;
+        if ask_left <= f64::EPSILON {
+            i += 1;
+            ask_left = asks.get(i).map_or(0.0, |l| l.1);
+        }
+        if bid_left <= f64::EPSILON {
+            j += 1;
+            bid_left = bids.get(j).map_or(0.0, |l| l.1);
+        }
+    }
+
+    execution
 }
 
 /// This struct wraps both the arbitrage opportunity and all latest prices
 #[derive(Debug, Clone, Serialize)]
 pub struct ArbitrageFeed {
-    pub prices: Vec<PriceUpdate>,          // All prices
-    pub opportunity: ArbitrageOpportunity, // Calculated arbitrage
+    pub prices: Vec<PriceUpdate>,                  // All prices
+    pub opportunity: Option<ArbitrageOpportunity>, // Needs two fresh venues
+    pub venues: HashMap<String, VenueStatus>,      // Connection state + reconnect counters
+    pub stale_sources: Vec<String>,                // Sources in `prices` too old to trade on
+}
+
+/// Last price of a venue and when we received it
+struct MarketEntry {
+    update: PriceUpdate,
+    received: Instant,
 }
 
 pub struct ArbitrageEngine {
-    market_state: HashMap<String, PriceUpdate>,
+    market_state: HashMap<String, MarketEntry>,
     tx: Sender<ArbitrageFeed>,
+    monitor: Arc<VenueMonitor>,
+    books: Arc<BookStore>,
+    fees: Arc<FeeModel>,
+    config: EngineConfig,
 }
 
 impl ArbitrageEngine {
-    pub fn new(tx: Sender<ArbitrageFeed>) -> Self {
+    pub fn new(
+        tx: Sender<ArbitrageFeed>,
+        monitor: Arc<VenueMonitor>,
+        books: Arc<BookStore>,
+        fees: Arc<FeeModel>,
+        config: EngineConfig,
+    ) -> Self {
         Self {
             market_state: HashMap::new(),
             tx,
+            monitor,
+            books,
+            fees,
+            config,
         }
     }
 
-    pub fn process_price(&mut self, update: PriceUpdate) {
-        self.market_state.insert(update.source.clone(), update);
+    /// Too old by the venue's own timestamp or by the time we received it
+    fn is_stale(&self, entry: &MarketEntry) -> bool {
+        let max_age = self.config.max_age(&entry.update.source);
+        entry.received.elapsed() > max_age
+            || now_ms().saturating_sub(entry.update.timestamp) > max_age.as_millis() as u64
+    }
 
-        if self.market_state.len() >= 2 {
-            let best_buy = self
-                .market_state
-                .values()
-                .min_by(|a, b| a.price.partial_cmp(&b.price).unwrap())
-                .unwrap();
-            let best_sell = self
+    /// Levels on one side of a venue: its book if it streams one, else its top-of-book size.
+    /// Books stay in the venue's native quote, so their prices get the update's FX rate.
+    fn levels(&self, update: &PriceUpdate, side: Side) -> Option<Vec<(f64, f64)>> {
+        if let Some(book) = self.books.get(&update.source, &update.pair) {
+            return Some(
+                book.levels(side)
+                    .iter()
+                    .map(|&(price, size)| (price * update.fx_rate, size))
+                    .collect(),
+            );
+        }
+
+        match side {
+            Side::Bid => update.bid_size.map(|size| vec![(update.bid, size)]),
+            Side::Ask => update.ask_size.map(|size| vec![(update.ask, size)]),
+        }
+    }
+
+    /// Fold in a venue price, returning the feed it produced (also sent to subscribers).
+    /// The feed goes out even when too few venues are fresh to make an opportunity.
+    pub fn process_price(&mut self, update: PriceUpdate) -> ArbitrageFeed {
+        let entry = MarketEntry {
+            update,
+            received: Instant::now(),
+        };
+        self.market_state.insert(entry.update.source.clone(), entry);
+
+        // Stale venues stay visible in the feed but never make an opportunity
+        let mut stale_sources: Vec<String> = self
+            .market_state
+            .values()
+            .filter(|entry| self.is_stale(entry))
+            .map(|entry| entry.update.source.clone())
+            .collect();
+        stale_sources.sort();
+
+        let fresh: Vec<&PriceUpdate> = self
+            .market_state
+            .values()
+            .map(|entry| &entry.update)
+            .filter(|update| !stale_sources.contains(&update.source))
+            .collect();
+
+        let feed = ArbitrageFeed {
+            prices: self
                 .market_state
                 .values()
-                .max_by(|a, b| a.price.partial_cmp(&b.price).unwrap())
-                .unwrap();
-            let spread_percent = ((best_sell.price - best_buy.price) / best_buy.price) * 100.0;
-
-            let arb = ArbitrageOpportunity {
-                pair: best_buy.pair.clone(),
-                best_buy_source: best_buy.source.clone(),
-                best_buy_price: best_buy.price,
-                best_sell_source: best_sell.source.clone(),
-                best_sell_price: best_sell.price,
-                spread_percent,
-            };
-
-            let feed = ArbitrageFeed {
-                prices: self.market_state.values().cloned().collect(),
-                opportunity: arb,
-            };
-
-            let _ = self.tx.send(feed);
+                .map(|entry| entry.update.clone())
+                .collect(),
+            opportunity: self.best_opportunity(&fresh),
+            venues: self.monitor.snapshot(),
+            stale_sources,
+        };
+
+        let _ = self.tx.send(feed.clone());
+        feed
+    }
+
+    /// Executable spread: buy at one venue's ask, sell at another venue's bid,
+    /// ranked after paying the taker fee on both legs
+    fn best_opportunity(&self, fresh: &[&PriceUpdate]) -> Option<ArbitrageOpportunity> {
+        let mut best: Option<(&PriceUpdate, &PriceUpdate, f64)> = None;
+        for &buy in fresh {
+            for &sell in fresh {
+                if buy.source == sell.source || buy.ask <= 0.0 {
+                    continue;
+                }
+
+                let buy_price = with_buy_fee(buy.ask, self.fees.taker_bps(&buy.source, &buy.pair));
+                let sell_price =
+                    with_sell_fee(sell.bid, self.fees.taker_bps(&sell.source, &sell.pair));
+                let net_spread_percent = ((sell_price - buy_price) / buy_price) * 100.0;
+                if best.is_none_or(|(_, _, s)| net_spread_percent > s) {
+                    best = Some((buy, sell, net_spread_percent));
+                }
+            }
         }
+
+        let (best_buy, best_sell, net_spread_percent) = best?;
+        let spread_percent = ((best_sell.bid - best_buy.ask) / best_buy.ask) * 100.0;
+        let buy_fee_bps = self.fees.taker_bps(&best_buy.source, &best_buy.pair);
+        let sell_fee_bps = self.fees.taker_bps(&best_sell.source, &best_sell.pair);
+
+        let (executable_size, expected_profit_quote, buy_levels, sell_levels) = match (
+            self.levels(best_buy, Side::Ask),
+            self.levels(best_sell, Side::Bid),
+        ) {
+            (Some(asks), Some(bids)) => {
+                let e = walk_books(
+                    &asks,
+                    &bids,
+                    buy_fee_bps,
+                    sell_fee_bps,
+                    self.config.target_spread_percent,
+                );
+                (
+                    Some(e.size),
+                    Some(e.proceeds - e.cost),
+                    e.buy_levels,
+                    e.sell_levels,
+                )
+            }
+            _ => (None, None, Vec::new(), Vec::new()),
+        };
+
+        Some(ArbitrageOpportunity {
+            pair: best_buy.pair.clone(),
+            best_buy_source: best_buy.source.clone(),
+            best_buy_price: best_buy.ask,
+            best_sell_source: best_sell.source.clone(),
+            best_sell_price: best_sell.bid,
+            spread_percent,
+            net_spread_percent,
+            buy_fee_bps,
+            sell_fee_bps,
+            executable_size,
+            expected_profit_quote,
+            buy_levels,
+            sell_levels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::FeeSchedule;
+    use tokio::sync::broadcast;
+
+    fn engine(fees: FeeModel) -> ArbitrageEngine {
+        let (tx, _) = broadcast::channel(16);
+        ArbitrageEngine::new(
+            tx,
+            Arc::new(VenueMonitor::default()),
+            Arc::new(BookStore::default()),
+            Arc::new(fees),
+            EngineConfig::default(),
+        )
+    }
+
+    fn quote(source: &str, bid: f64, ask: f64, timestamp: u64) -> PriceUpdate {
+        PriceUpdate::quote(source, "SOL/USDC", bid, 1.0, ask, 1.0, timestamp)
+    }
+
+    #[test]
+    fn walk_stops_where_the_spread_closes() {
+        let asks = [(100.0, 1.0), (101.0, 2.0), (103.0, 5.0)];
+        let bids = [(104.0, 1.5), (102.0, 2.0), (100.0, 5.0)];
+        let e = walk_books(&asks, &bids, 0.0, 0.0, 0.0);
+
+        // 1 @ 100 -> 104, 0.5 @ 101 -> 104, 1.5 @ 101 -> 102, then 103 > 102
+        assert_eq!(e.size, 3.0);
+        assert_eq!(e.cost, 100.0 + 0.5 * 101.0 + 1.5 * 101.0);
+        assert_eq!(e.proceeds, 104.0 * 1.5 + 102.0 * 1.5);
+        assert_eq!(e.buy_levels, [(100.0, 1.0), (101.0, 2.0)]);
+        assert_eq!(e.sell_levels, [(104.0, 1.5), (102.0, 1.5)]);
+
+        // 50 bps a leg close it one level earlier: 101 * 1.005 > 102 * 0.995
+        let e = walk_books(&asks, &bids, 50.0, 50.0, 0.0);
+        assert_eq!(e.size, 1.5);
+        assert!((e.cost - (100.0 + 0.5 * 101.0) * 1.005).abs() < 1e-9);
+
+        // A target spread stops where the marginal unit no longer clears it
+        let e = walk_books(&asks, &bids, 0.0, 0.0, 2.0);
+        assert_eq!(e.size, 1.5);
+    }
+
+    #[test]
+    fn ranks_by_spread_after_fees() {
+        let mut fees = FeeModel::empty();
+        fees.set_schedule("Pricey", FeeSchedule::flat(60.0, 60.0));
+        let mut net = engine(fees);
+        let now = now_ms();
+        net.process_price(quote("Cheap", 99.5, 100.0, now));
+        // Best ask, but 60 bps of taker fee make it the dearer buy
+        net.process_price(quote("Pricey", 99.4, 99.9, now));
+        let feed = net.process_price(quote("Rich", 101.0, 101.5, now));
+
+        let opportunity = feed.opportunity.unwrap();
+        assert_eq!(opportunity.best_buy_source, "Cheap");
+        assert_eq!(opportunity.best_sell_source, "Rich");
+        assert_eq!(opportunity.spread_percent, 1.0);
+        assert_eq!(opportunity.net_spread_percent, 1.0);
+
+        // Ranked on gross spread it would be the buy
+        let mut gross = engine(FeeModel::empty());
+        gross.process_price(quote("Cheap", 99.5, 100.0, now));
+        gross.process_price(quote("Pricey", 99.4, 99.9, now));
+        let feed = gross.process_price(quote("Rich", 101.0, 101.5, now));
+        assert_eq!(feed.opportunity.unwrap().best_buy_source, "Pricey");
+    }
+
+    #[test]
+    fn stale_venues_are_reported_but_never_traded() {
+        let mut engine = engine(FeeModel::empty());
+        let now = now_ms();
+        engine.process_price(quote("Cheap", 99.0, 100.0, now));
+        engine.process_price(quote("Rich", 101.0, 102.0, now));
+        // Best bid by far, but a minute old
+        let feed = engine.process_price(quote("Old", 110.0, 111.0, now - 60_000));
+
+        assert_eq!(feed.prices.len(), 3);
+        assert_eq!(feed.stale_sources, ["Old"]);
+        let opportunity = feed.opportunity.expect("two fresh venues");
+        assert_eq!(opportunity.best_buy_source, "Cheap");
+        assert_eq!(opportunity.best_sell_source, "Rich");
+        assert_eq!(opportunity.executable_size, Some(1.0));
+    }
+
+    #[test]
+    fn feed_goes_out_without_two_fresh_venues() {
+        let mut engine = engine(FeeModel::empty());
+        let mut rx = engine.tx.subscribe();
+        let now = now_ms();
+        engine.process_price(quote("Old", 99.0, 100.0, now - 60_000));
+        let feed = engine.process_price(quote("Fresh", 101.0, 102.0, now));
+
+        assert!(feed.opportunity.is_none());
+        assert_eq!(feed.stale_sources, ["Old"]);
+        assert_eq!(feed.prices.len(), 2);
+
+        // Subscribers see the degraded feeds too
+        assert_eq!(rx.try_recv().unwrap().stale_sources, ["Old"]);
+        assert_eq!(rx.try_recv().unwrap().prices.len(), 2);
     }
 }
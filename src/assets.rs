@@ -0,0 +1,350 @@
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tokio::time::{Duration, timeout};
+
+/// Registry shipped with the binary, see `assets.toml`
+const BUILTIN: &str = include_str!("../assets.toml");
+/// SPL mint layout: mint_authority (COption<Pubkey>), supply (u64), then decimals
+const MINT_DECIMALS_OFFSET: usize = 44;
+/// Longest the startup mint read may take before the file's decimals are kept
+const MINT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+/// How a venue spells a canonical pair
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VenueSymbols {
+    pub format: String,
+    /// Used instead of `format` when either asset code is longer than 3 characters
+    pub long_format: Option<String>,
+    /// Case of the whole symbol, as formatted when unset
+    pub case: Option<Case>,
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    #[serde(default)]
+    pub quote_aliases: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Asset {
+    pub mint: Option<String>,
+    /// Read from the mint account at startup, the file value is the fallback
+    pub decimals: Option<u8>,
+}
+
+/// On-chain pool pricing `base/quote`, also serving the `serves` quotes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pool {
+    pub venue: String,
+    pub base: String,
+    pub quote: String,
+    #[serde(default)]
+    pub serves: Vec<String>,
+    /// Pool account (Orca whirlpool, Raydium pool id). Raydium prices every pool of
+    /// the mints when unset.
+    pub address: Option<String>,
+}
+
+impl Pool {
+    /// Canonical pairs this pool can price
+    pub fn pairs(&self) -> Vec<String> {
+        std::iter::once(&self.quote)
+            .chain(&self.serves)
+            .map(|quote| format!("{}/{}", self.base, quote))
+            .collect()
+    }
+
+    fn serves(&self, pair: &str) -> bool {
+        pair.split_once('/').is_some_and(|(base, quote)| {
+            base == self.base && (quote == self.quote || self.serves.iter().any(|q| q == quote))
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AssetFile {
+    #[serde(default)]
+    venues: HashMap<String, VenueSymbols>,
+    #[serde(default)]
+    assets: HashMap<String, Asset>,
+    #[serde(default)]
+    pools: Vec<Pool>,
+}
+
+/// Venue symbol conventions, Solana assets and DEX pools, loaded from a data file
+/// so adding a pair doesn't take a code change
+#[derive(Debug)]
+pub struct AssetRegistry {
+    venues: HashMap<String, VenueSymbols>, // Lowercase venue name
+    assets: HashMap<String, Asset>,        // Canonical code, e.g. "SOL"
+    pools: Vec<Pool>,
+}
+
+impl AssetRegistry {
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN).expect("Invalid built-in assets.toml")
+    }
+
+    /// The file at `path`, the built-in registry when unset
+    pub fn load(path: Option<&str>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::builtin());
+        };
+        let raw = std::fs::read_to_string(path).with_context(|| format!("Reading {}", path))?;
+        Self::from_toml(&raw).with_context(|| format!("Loading {}", path))
+    }
+
+    pub fn from_toml(raw: &str) -> anyhow::Result<Self> {
+        let file: AssetFile = toml::from_str(raw)?;
+        let registry = Self {
+            venues: file
+                .venues
+                .into_iter()
+                .map(|(name, symbols)| (name.to_lowercase(), symbols))
+                .collect(),
+            assets: file.assets,
+            pools: file
+                .pools
+                .into_iter()
+                .map(|pool| Pool {
+                    venue: pool.venue.to_lowercase(),
+                    ..pool
+                })
+                .collect(),
+        };
+
+        let errors = registry.problems();
+        if !errors.is_empty() {
+            bail!("Invalid asset registry:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(registry)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        for (venue, symbols) in &self.venues {
+            for format in std::iter::once(&symbols.format).chain(&symbols.long_format) {
+                if !format.contains("{base}") || !format.contains("{quote}") {
+                    errors.push(format!(
+                        "venues.{}: format {:?} needs {{base}} and {{quote}}",
+                        venue, format
+                    ));
+                }
+            }
+        }
+
+        for (code, asset) in &self.assets {
+            if let Some(mint) = &asset.mint
+                && let Err(e) = Pubkey::from_str(mint)
+            {
+                errors.push(format!("assets.{}.mint {:?}: {}", code, mint, e));
+            }
+        }
+
+        for (i, pool) in self.pools.iter().enumerate() {
+            let name = format!("pools[{}] ({} {}/{})", i, pool.venue, pool.base, pool.quote);
+            for code in [&pool.base, &pool.quote] {
+                match self.assets.get(code) {
+                    Some(asset) if asset.mint.is_some() => {}
+                    _ => errors.push(format!("{}: {} is not an asset with a mint", name, code)),
+                }
+            }
+            if let Some(address) = &pool.address
+                && let Err(e) = Pubkey::from_str(address)
+            {
+                errors.push(format!("{}: address {:?}: {}", name, address, e));
+            }
+            for other in &self.pools[..i] {
+                if other.venue == pool.venue && pool.pairs().iter().any(|p| other.serves(p)) {
+                    errors.push(format!("{}: pair already served by another pool", name));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Venue-native symbol of a canonical pair, `None` if the venue has no symbol format
+    pub fn venue_symbol(&self, venue: &str, pair: &str) -> Option<String> {
+        let symbols = self.venues.get(&venue.to_lowercase())?;
+        let (base, quote) = pair.split_once('/')?;
+
+        let base = symbols.aliases.get(base).map_or(base, String::as_str);
+        let quote = symbols
+            .quote_aliases
+            .get(quote)
+            .or_else(|| symbols.aliases.get(quote))
+            .map_or(quote, String::as_str);
+
+        let format = match &symbols.long_format {
+            Some(long) if base.len() > 3 || quote.len() > 3 => long,
+            _ => &symbols.format,
+        };
+        let symbol = format.replace("{base}", base).replace("{quote}", quote);
+
+        Some(match symbols.case {
+            Some(Case::Upper) => symbol.to_uppercase(),
+            Some(Case::Lower) => symbol.to_lowercase(),
+            None => symbol,
+        })
+    }
+
+    pub fn asset(&self, code: &str) -> Option<&Asset> {
+        self.assets.get(code)
+    }
+
+    pub fn mint(&self, code: &str) -> Option<&str> {
+        self.asset(code)?.mint.as_deref()
+    }
+
+    pub fn decimals(&self, code: &str) -> Option<u8> {
+        self.asset(code)?.decimals
+    }
+
+    /// Pool of `venue` serving `pair`
+    pub fn pool(&self, venue: &str, pair: &str) -> Option<&Pool> {
+        let venue = venue.to_lowercase();
+        self.pools
+            .iter()
+            .find(|pool| pool.venue == venue && pool.serves(pair))
+    }
+
+    /// Every pair the pools of `venue` can price
+    pub fn pool_pairs(&self, venue: &str) -> Vec<String> {
+        let venue = venue.to_lowercase();
+        self.pools
+            .iter()
+            .filter(|pool| pool.venue == venue)
+            .flat_map(Pool::pairs)
+            .collect()
+    }
+
+    /// Replace the configured decimals with the ones of the mint accounts. Unreadable
+    /// mints keep the file value; a mismatch is logged since it skews every DEX price.
+    pub async fn refresh_decimals(&mut self, rpc_url: &str) {
+        let mints: Vec<(String, Pubkey)> = self
+            .assets
+            .iter()
+            .filter_map(|(code, asset)| {
+                let mint = Pubkey::from_str(asset.mint.as_deref()?).ok()?;
+                Some((code.clone(), mint))
+            })
+            .collect();
+        let keys: Vec<Pubkey> = mints.iter().map(|(_, mint)| *mint).collect();
+
+        let rpc = RpcClient::new(rpc_url.to_string());
+        let accounts = match timeout(MINT_READ_TIMEOUT, rpc.get_multiple_accounts(&keys)).await {
+            Ok(Ok(accounts)) => accounts,
+            Ok(Err(e)) => {
+                eprintln!(
+                    "ASSETS Could not read mint accounts, keeping file decimals: {}",
+                    e
+                );
+                return;
+            }
+            Err(_) => {
+                eprintln!("ASSETS Mint account read timed out, keeping file decimals");
+                return;
+            }
+        };
+
+        for ((code, mint), account) in mints.iter().zip(accounts) {
+            let Some(decimals) = account.and_then(|a| a.data.get(MINT_DECIMALS_OFFSET).copied())
+            else {
+                eprintln!(
+                    "ASSETS Mint {} of {} not found, keeping file decimals",
+                    mint, code
+                );
+                continue;
+            };
+            let asset = self.assets.get_mut(code).expect("asset listed above");
+            if asset.decimals.is_some_and(|d| d != decimals) {
+                eprintln!(
+                    "ASSETS {} has {} decimals on-chain, not {:?} as configured",
+                    code, decimals, asset.decimals
+                );
+            }
+            asset.decimals = Some(decimals);
+        }
+    }
+}
+
+impl Default for AssetRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_venue_symbols() {
+        let assets = AssetRegistry::builtin();
+        for (venue, pair, symbol) in [
+            ("Binance", "SOL/USDC", "solusdc"),
+            ("OKX", "SOL/USDT", "SOL-USDT"),
+            ("Kraken", "BTC/USD", "XBT/USD"),
+            ("Bitfinex", "SOL/USDT", "tSOLUST"),
+            ("Bitfinex", "DOGE/USD", "tDOGE:USD"),
+            ("Coinbase", "SOL/USDC", "SOL-USD"),
+            ("Coinbase", "USDC/USD", "USDC-USD"),
+            ("Backpack", "SOL/USDC", "SOL_USDC"),
+            ("HTX", "SOL/USDT", "solusdt"),
+        ] {
+            assert_eq!(assets.venue_symbol(venue, pair).as_deref(), Some(symbol));
+        }
+        assert_eq!(assets.venue_symbol("Orca", "SOL/USDC"), None);
+    }
+
+    #[test]
+    fn pools_serve_their_quotes() {
+        let assets = AssetRegistry::builtin();
+        let pool = assets.pool("Raydium", "ETH/USDT").unwrap();
+        assert_eq!(pool.quote, "USDC");
+        assert_eq!(assets.decimals(&pool.base), Some(8));
+        assert!(assets.pool("Orca", "SOL/EUR").is_none());
+        assert_eq!(assets.pool_pairs("orca").len(), 9);
+    }
+
+    #[test]
+    fn rejects_broken_pools() {
+        let err = AssetRegistry::from_toml(
+            r#"
+            [assets.SOL]
+            mint = "So11111111111111111111111111111111111111112"
+
+            [[pools]]
+            venue = "orca"
+            base = "SOL"
+            quote = "USDC"
+            address = "not-a-key"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        for expected in ["USDC is not an asset with a mint", "address \"not-a-key\""] {
+            assert!(
+                err.contains(expected),
+                "{:?} missing from:\n{}",
+                expected,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::arbitrage_engine::EngineConfig;
+use crate::connectors::now_ms;
+use crate::state::PriceUpdate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// Venue name of the candles built across every venue of a pair
+pub const COMPOSITE: &str = "composite";
+
+/// Candles kept per (pair, venue, interval)
+const MAX_CANDLES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    Sec1,
+    #[serde(rename = "1m")]
+    Min1,
+    #[serde(rename = "5m")]
+    Min5,
+    #[serde(rename = "1h")]
+    Hour1,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 4] = [
+        Interval::Sec1,
+        Interval::Min1,
+        Interval::Min5,
+        Interval::Hour1,
+    ];
+
+    pub fn millis(self) -> u64 {
+        match self {
+            Interval::Sec1 => 1_000,
+            Interval::Min1 => 60_000,
+            Interval::Min5 => 300_000,
+            Interval::Hour1 => 3_600_000,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Interval::Sec1 => "1s",
+            Interval::Min1 => "1m",
+            Interval::Min5 => "5m",
+            Interval::Hour1 => "1h",
+        }
+    }
+
+    /// Start of the candle containing `timestamp` (ms)
+    fn open_time(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.millis()
+    }
+}
+
+/// OHLCV of the mid price
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candle {
+    pub venue: String,
+    pub interval: Interval,
+    pub open_time: u64, // Unix ms
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Sum of `trade_size` of the folded updates. Always 0 for now: no connector streams
+    /// trades yet, so this is not traded volume.
+    pub volume: f64,
+    pub ticks: u64, // Price updates folded in
+}
+
+impl Candle {
+    fn new(venue: &str, interval: Interval, open_time: u64, price: f64, volume: f64) -> Self {
+        Self {
+            venue: venue.to_string(),
+            interval,
+            open_time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume,
+            ticks: 1,
+        }
+    }
+
+    fn add(&mut self, price: f64, volume: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += volume;
+        self.ticks += 1;
+    }
+}
+
+/// Latest mid of a venue, with its venue timestamp and when we received it
+struct Mid {
+    price: f64,
+    timestamp: u64,
+    received: Instant,
+}
+
+/// Per-venue and composite candles of every pair, built incrementally from the update stream.
+/// The composite price is the mean of the latest mid of each venue that is not stale.
+pub struct CandleStore {
+    series: HashMap<(String, String, Interval), VecDeque<Candle>>, // (pair, venue, interval)
+    mids: HashMap<String, HashMap<String, Mid>>,                   // pair -> venue -> last mid
+    config: EngineConfig, // Max price age per venue, as for the engine
+}
+
+impl CandleStore {
+    pub fn new(config: EngineConfig) -> Self {
+        Self {
+            series: HashMap::new(),
+            mids: HashMap::new(),
+            config,
+        }
+    }
+
+    /// Fold in an update, returning the candles it closed
+    pub fn add(&mut self, update: &PriceUpdate) -> Vec<Candle> {
+        let mut closed = Vec::new();
+        if update.price <= 0.0 {
+            return closed;
+        }
+        let volume = update.trade_size.unwrap_or(0.0);
+
+        let mids = self.mids.entry(update.pair.clone()).or_default();
+        mids.insert(
+            update.source.clone(),
+            Mid {
+                price: update.price,
+                timestamp: update.timestamp,
+                received: Instant::now(),
+            },
+        );
+        // Same rule as the engine: too old by receive time or by venue timestamp
+        let config = &self.config;
+        mids.retain(|venue, mid| {
+            let max_age = config.max_age(venue);
+            mid.received.elapsed() <= max_age
+                && now_ms().saturating_sub(mid.timestamp) <= max_age.as_millis() as u64
+        });
+        let composite = (!mids.is_empty())
+            .then(|| mids.values().map(|mid| mid.price).sum::<f64>() / mids.len() as f64);
+
+        for interval in Interval::ALL {
+            let (pair, ts) = (&update.pair, update.timestamp);
+            closed.extend(self.fold(pair, &update.source, interval, ts, update.price, volume));
+            if let Some(composite) = composite {
+                closed.extend(self.fold(pair, COMPOSITE, interval, ts, composite, volume));
+            }
+        }
+
+        closed
+    }
+
+    fn fold(
+        &mut self,
+        pair: &str,
+        venue: &str,
+        interval: Interval,
+        timestamp: u64,
+        price: f64,
+        volume: f64,
+    ) -> Option<Candle> {
+        let open_time = interval.open_time(timestamp);
+        let candles = self
+            .series
+            .entry((pair.to_string(), venue.to_string(), interval))
+            .or_default();
+
+        match candles.back_mut() {
+            Some(last) if last.open_time == open_time => {
+                last.add(price, volume);
+                None
+            }
+            // Late update for a candle that is already closed
+            Some(last) if last.open_time > open_time => None,
+            last => {
+                let closed = last.cloned();
+                if candles.len() >= MAX_CANDLES {
+                    candles.pop_front();
+                }
+                candles.push_back(Candle::new(venue, interval, open_time, price, volume));
+                closed
+            }
+        }
+    }
+
+    /// Candles of one series (venue matched case-insensitively) overlapping `from..=to` (ms)
+    pub fn get(
+        &self,
+        pair: &str,
+        venue: &str,
+        interval: Interval,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Vec<Candle> {
+        self.series
+            .iter()
+            .find(|((p, v, i), _)| p == pair && v.eq_ignore_ascii_case(venue) && *i == interval)
+            .map_or(Vec::new(), |(_, candles)| {
+                candles
+                    .iter()
+                    .filter(|c| from.is_none_or(|from| c.open_time + interval.millis() > from))
+                    .filter(|c| to.is_none_or(|to| c.open_time <= to))
+                    .cloned()
+                    .collect()
+            })
+    }
+
+    /// Current (last) candle of every series of `pair`
+    pub fn current(&self, pair: &str) -> Vec<Candle> {
+        self.series
+            .iter()
+            .filter(|((p, _, _), _)| p == pair)
+            .filter_map(|(_, candles)| candles.back().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(source: &str, price: f64, timestamp: u64) -> PriceUpdate {
+        PriceUpdate::single(source, "SOL/USDC", price, timestamp)
+    }
+
+    /// Fixed timestamps far in the past, so nothing may go stale
+    fn store() -> CandleStore {
+        CandleStore::new(EngineConfig::default().without_age_limits())
+    }
+
+    fn last(store: &CandleStore, venue: &str, interval: Interval) -> Candle {
+        store
+            .get("SOL/USDC", venue, interval, None, None)
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn folds_open_high_low_close_within_a_bucket() {
+        let mut store = store();
+        for (price, timestamp) in [(10.0, 1_000), (12.0, 1_200), (9.0, 1_500), (11.0, 1_999)] {
+            assert!(store.add(&update("Binance", price, timestamp)).is_empty());
+        }
+
+        let candle = last(&store, "Binance", Interval::Sec1);
+        assert_eq!(candle.open_time, 1_000);
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (10.0, 12.0, 9.0, 11.0)
+        );
+        assert_eq!(candle.ticks, 4);
+        assert_eq!(candle.volume, 0.0);
+    }
+
+    #[test]
+    fn rollover_returns_the_closed_candle() {
+        let mut store = store();
+        store.add(&update("Binance", 10.0, 1_000));
+        store.add(&update("Binance", 11.0, 1_500));
+
+        let closed = store.add(&update("Binance", 12.0, 2_000));
+        // Only the 1s candles rolled over, for the venue and the composite
+        assert_eq!(closed.len(), 2);
+        assert!(closed.iter().all(|c| c.interval == Interval::Sec1));
+        let venue = closed.iter().find(|c| c.venue == "Binance").unwrap();
+        assert_eq!(
+            (venue.open_time, venue.close, venue.ticks),
+            (1_000, 11.0, 2)
+        );
+        assert_eq!(last(&store, "Binance", Interval::Sec1).open, 12.0);
+
+        // A late update for the closed second changes nothing
+        assert!(store.add(&update("Binance", 99.0, 1_900)).is_empty());
+        assert_eq!(last(&store, "Binance", Interval::Sec1).high, 12.0);
+    }
+
+    #[test]
+    fn every_interval_has_its_own_bucket() {
+        let mut store = store();
+        store.add(&update("Binance", 10.0, 3_725_500)); // 1h 2m 5.5s
+
+        for (interval, open_time) in [
+            (Interval::Sec1, 3_725_000),
+            (Interval::Min1, 3_720_000),
+            (Interval::Min5, 3_600_000),
+            (Interval::Hour1, 3_600_000),
+        ] {
+            assert_eq!(last(&store, "Binance", interval).open_time, open_time);
+            assert_eq!(last(&store, COMPOSITE, interval).open_time, open_time);
+        }
+        assert_eq!(store.current("SOL/USDC").len(), 8);
+    }
+
+    #[test]
+    fn composite_leaves_out_stale_venues() {
+        let mut store = CandleStore::new(EngineConfig::default());
+        let now = now_ms();
+        store.add(&update("Orca", 100.0, now));
+        store.add(&update("Binance", 102.0, now));
+        assert_eq!(last(&store, COMPOSITE, Interval::Hour1).close, 101.0);
+
+        // Orca's last price is older than the 15s default when Kraken quotes
+        store
+            .mids
+            .get_mut("SOL/USDC")
+            .unwrap()
+            .get_mut("Orca")
+            .unwrap()
+            .timestamp = now - 60_000;
+        store.add(&update("Kraken", 106.0, now));
+        assert_eq!(last(&store, COMPOSITE, Interval::Hour1).close, 104.0);
+    }
+
+    #[test]
+    fn get_filters_by_venue_and_time() {
+        let mut store = store();
+        for (second, price) in [(1, 10.0), (2, 11.0), (3, 12.0), (4, 13.0)] {
+            store.add(&update("Binance", price, second * 1_000 + 500));
+        }
+        store.add(&update("Kraken", 20.0, 2_500));
+
+        let opens = |venue: &str, from, to| -> Vec<u64> {
+            store
+                .get("SOL/USDC", venue, Interval::Sec1, from, to)
+                .iter()
+                .map(|c| c.open_time)
+                .collect()
+        };
+        assert_eq!(opens("binance", None, None), [1_000, 2_000, 3_000, 4_000]);
+        // Candles overlapping the range: 1s..2s ends after 1_999
+        assert_eq!(
+            opens("Binance", Some(1_999), Some(3_000)),
+            [1_000, 2_000, 3_000]
+        );
+        assert_eq!(opens("Binance", Some(2_000), None), [2_000, 3_000, 4_000]);
+        assert_eq!(opens("Kraken", None, None), [2_000]);
+        assert!(opens("Orca", None, None).is_empty());
+        assert!(
+            store
+                .get("BTC/USDC", "Binance", Interval::Sec1, None, None)
+                .is_empty()
+        );
+    }
+}
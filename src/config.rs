@@ -0,0 +1,665 @@
+use crate::arbitrage_engine::EngineConfig;
+use crate::connectors::{ConnectorRegistry, VenueKind};
+use crate::fees::FeeSchedule;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+/// Read when `CONFIG_FILE` is not set, skipped if it doesn't exist
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DEFAULT_SOLANA_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+/// Fastest a polled venue may be hit
+const MIN_POLL_INTERVAL_MS: u64 = 50;
+/// Venues with a REST endpoint next to their stream
+const REST_VENUES: [&str; 2] = ["binance", "kucoin"];
+/// Venues whose stream URL is handed out by their REST endpoint
+const REST_ONLY_VENUES: [&str; 1] = ["kucoin"];
+/// Venues whose symbol isn't a single venue-native id (Raydium is a vault pair)
+const FIXED_SYMBOL_VENUES: [&str; 1] = ["raydium"];
+
+/// Everything the server reads at startup: built-in defaults, then the TOML file,
+/// then environment overrides. See `config.example.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub cache: CacheConfig,
+    pub engine: EngineSettings,
+    pub rpc: RpcConfig,
+    pub database: DatabaseConfig,
+    pub assets: AssetsConfig,
+    /// Keyed by venue name, case-insensitive
+    pub venues: HashMap<String, VenueConfig>,
+    /// Fee schedule overrides, keyed by venue name
+    pub fees: HashMap<String, FeeSchedule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 8081)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Prices kept in memory per pair
+    pub history_size: usize,
+    /// Capacity of the per-pair price and feed channels
+    pub broadcast_capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            history_size: 500,
+            broadcast_capacity: EngineConfig::default().channel_capacity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineSettings {
+    pub target_spread_percent: f64,
+    pub max_price_age_ms: u64,
+    /// Per-venue overrides of `max_price_age_ms`
+    pub venue_max_age_ms: HashMap<String, u64>,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        let defaults = EngineConfig::default();
+        Self {
+            target_spread_percent: defaults.target_spread_percent,
+            max_price_age_ms: defaults.max_age.as_millis() as u64,
+            venue_max_age_ms: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    /// Solana JSON-RPC endpoint of the on-chain venues without their own `url`
+    pub solana_url: String,
+    /// Pubsub endpoint for account subscriptions, next to `solana_url` (ws/wss) when unset
+    pub solana_ws_url: Option<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            solana_url: DEFAULT_SOLANA_RPC_URL.to_string(),
+            solana_ws_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Postgres history, in-memory only when unset
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+    /// Venue symbols, mints and pools; the built-in `assets.toml` when unset
+    pub file: Option<String>,
+}
+
+/// Per-venue settings, anything unset keeps the connector's built-in value
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VenueConfig {
+    pub enabled: bool,
+    /// Stream endpoint (WebSocket, HTTP API or Solana RPC depending on the venue)
+    pub url: Option<String>,
+    /// Snapshot / token endpoint of the venues that have one
+    pub rest_url: Option<String>,
+    /// Polling period of the venues that poll
+    pub poll_interval_ms: Option<u64>,
+    /// Venue-native symbol per canonical pair, e.g. `"SOL/USDC" = "SOLUSDC"`
+    pub symbols: HashMap<String, String>,
+}
+
+impl Default for VenueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: None,
+            rest_url: None,
+            poll_interval_ms: None,
+            symbols: HashMap::new(),
+        }
+    }
+}
+
+impl VenueConfig {
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval_ms.map(Duration::from_millis)
+    }
+}
+
+impl Config {
+    /// Load `CONFIG_FILE` (or `config.toml` if present), apply the environment
+    /// overrides and validate the result
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            }
+            Err(_) => Self::default(),
+        };
+
+        let errors = config.apply_env(|key| std::env::var(key).ok());
+        config.check(errors)?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let raw = std::fs::read_to_string(path).with_context(|| format!("Reading {}", path))?;
+        Self::from_toml(&raw).with_context(|| format!("Parsing {}", path))
+    }
+
+    pub fn from_toml(raw: &str) -> anyhow::Result<Self> {
+        let mut config: Self = toml::from_str(raw)?;
+        let mut venues = HashMap::new();
+        for (name, venue) in config.venues {
+            if venues.insert(name.to_lowercase(), venue).is_some() {
+                bail!("venues.{} is configured twice", name.to_lowercase());
+            }
+        }
+        config.venues = venues;
+        Ok(config)
+    }
+
+    /// Apply the environment overrides, returning the ones that don't parse:
+    /// - `LISTEN_ADDR`, `CACHE_HISTORY_SIZE`, `BROADCAST_CAPACITY`
+    /// - `SOLANA_RPC_URL`, `SOLANA_WS_URL`, `DATABASE_URL`, `ASSETS_FILE`
+    /// - `ENABLED_VENUES` / `DISABLED_VENUES` (comma separated venue names)
+    /// - `FEE_CONFIG` (JSON file of venue -> fee schedule)
+    /// - `TARGET_SPREAD_PERCENT`, `MAX_PRICE_AGE_MS`, `VENUE_MAX_AGE_MS` ("raydium=5000,orca=5000")
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if let Some(v) = var("LISTEN_ADDR") {
+            set_parsed(&mut self.server.listen, "LISTEN_ADDR", &v, &mut errors);
+        }
+        if let Some(v) = var("CACHE_HISTORY_SIZE") {
+            set_parsed(
+                &mut self.cache.history_size,
+                "CACHE_HISTORY_SIZE",
+                &v,
+                &mut errors,
+            );
+        }
+        if let Some(v) = var("BROADCAST_CAPACITY") {
+            set_parsed(
+                &mut self.cache.broadcast_capacity,
+                "BROADCAST_CAPACITY",
+                &v,
+                &mut errors,
+            );
+        }
+        if let Some(v) = var("SOLANA_RPC_URL") {
+            self.rpc.solana_url = v;
+        }
+        if let Some(v) = var("SOLANA_WS_URL") {
+            self.rpc.solana_ws_url = Some(v);
+        }
+        if let Some(v) = var("DATABASE_URL") {
+            self.database.url = Some(v);
+        }
+        if let Some(v) = var("ASSETS_FILE") {
+            self.assets.file = Some(v);
+        }
+
+        let (enabled, disabled) = (var("ENABLED_VENUES"), var("DISABLED_VENUES"));
+        let known = if enabled.is_some() || disabled.is_some() {
+            known_venues()
+        } else {
+            HashMap::new()
+        };
+        if let Some(v) = enabled {
+            let enabled = parse_venue_list(&v);
+            for name in known.keys() {
+                self.venue_mut(name).enabled = enabled.contains(name);
+            }
+            check_venue_list("ENABLED_VENUES", &enabled, &known, &mut errors);
+        }
+        if let Some(v) = disabled {
+            let disabled = parse_venue_list(&v);
+            for name in disabled.iter().filter(|name| known.contains_key(*name)) {
+                self.venue_mut(name).enabled = false;
+            }
+            check_venue_list("DISABLED_VENUES", &disabled, &known, &mut errors);
+        }
+
+        if let Some(path) = var("FEE_CONFIG") {
+            let overrides = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|raw| Ok(serde_json::from_str::<HashMap<String, FeeSchedule>>(&raw)?));
+            match overrides {
+                Ok(overrides) => self.fees.extend(overrides),
+                Err(e) => errors.push(format!("FEE_CONFIG {}: {}", path, e)),
+            }
+        }
+
+        if let Some(v) = var("TARGET_SPREAD_PERCENT") {
+            set_parsed(
+                &mut self.engine.target_spread_percent,
+                "TARGET_SPREAD_PERCENT",
+                &v,
+                &mut errors,
+            );
+        }
+        if let Some(v) = var("MAX_PRICE_AGE_MS") {
+            set_parsed(
+                &mut self.engine.max_price_age_ms,
+                "MAX_PRICE_AGE_MS",
+                &v,
+                &mut errors,
+            );
+        }
+        if let Some(v) = var("VENUE_MAX_AGE_MS") {
+            for entry in v.split(',').filter(|e| !e.trim().is_empty()) {
+                match entry
+                    .split_once('=')
+                    .map(|(venue, ms)| (venue, ms.trim().parse()))
+                {
+                    Some((venue, Ok(ms))) => {
+                        self.engine
+                            .venue_max_age_ms
+                            .insert(venue.trim().to_lowercase(), ms);
+                    }
+                    _ => errors.push(format!(
+                        "VENUE_MAX_AGE_MS: expected venue=ms, got {:?}",
+                        entry
+                    )),
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Fail with every problem at once: earlier `errors` plus the invalid settings
+    fn check(&self, mut errors: Vec<String>) -> anyhow::Result<()> {
+        errors.extend(self.problems());
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.check(Vec::new())
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let venues = known_venues();
+
+        if self.cache.history_size == 0 {
+            errors.push("cache.history_size must be at least 1".to_string());
+        }
+        if self.cache.broadcast_capacity == 0 {
+            errors.push("cache.broadcast_capacity must be at least 1".to_string());
+        }
+
+        let engine = &self.engine;
+        if !engine.target_spread_percent.is_finite() || engine.target_spread_percent < 0.0 {
+            errors.push(format!(
+                "engine.target_spread_percent must be >= 0, got {}",
+                engine.target_spread_percent
+            ));
+        }
+        if engine.max_price_age_ms == 0 {
+            errors.push("engine.max_price_age_ms must be at least 1".to_string());
+        }
+        for (venue, ms) in &engine.venue_max_age_ms {
+            if !venues.contains_key(venue.to_lowercase().as_str()) {
+                errors.push(format!(
+                    "engine.venue_max_age_ms: unknown venue {:?}",
+                    venue
+                ));
+            }
+            if *ms == 0 {
+                errors.push(format!(
+                    "engine.venue_max_age_ms.{} must be at least 1",
+                    venue
+                ));
+            }
+        }
+
+        check_url(
+            "rpc.solana_url",
+            &self.rpc.solana_url,
+            &["http", "https"],
+            &mut errors,
+        );
+        if let Some(url) = &self.rpc.solana_ws_url {
+            check_url("rpc.solana_ws_url", url, &["ws", "wss"], &mut errors);
+        }
+
+        for (name, venue) in &self.venues {
+            let Some(kind) = venues.get(name) else {
+                errors.push(format!(
+                    "venues.{}: unknown venue (known: {})",
+                    name,
+                    sorted_names(&venues)
+                ));
+                continue;
+            };
+
+            // Solana RPC and Jupiter are HTTP, every exchange streams over a WebSocket
+            let schemes: &[&str] = match kind {
+                VenueKind::Cex => &["ws", "wss"],
+                VenueKind::Dex => &["http", "https"],
+            };
+            if venue.url.is_some() && REST_ONLY_VENUES.contains(&name.as_str()) {
+                errors.push(format!(
+                    "venues.{}.url: stream URL comes from the REST endpoint, set rest_url",
+                    name
+                ));
+            } else if let Some(url) = &venue.url {
+                check_url(&format!("venues.{}.url", name), url, schemes, &mut errors);
+            }
+            if let Some(url) = &venue.rest_url {
+                if REST_VENUES.contains(&name.as_str()) {
+                    let key = format!("venues.{}.rest_url", name);
+                    check_url(&key, url, &["http", "https"], &mut errors);
+                } else {
+                    errors.push(format!(
+                        "venues.{}.rest_url: venue has no REST endpoint",
+                        name
+                    ));
+                }
+            }
+            if let Some(ms) = venue.poll_interval_ms {
+                if *kind != VenueKind::Dex {
+                    errors.push(format!(
+                        "venues.{}.poll_interval_ms: venue streams, it doesn't poll",
+                        name
+                    ));
+                } else if ms < MIN_POLL_INTERVAL_MS {
+                    errors.push(format!(
+                        "venues.{}.poll_interval_ms must be at least {}, got {}",
+                        name, MIN_POLL_INTERVAL_MS, ms
+                    ));
+                }
+            }
+            if !venue.symbols.is_empty() && FIXED_SYMBOL_VENUES.contains(&name.as_str()) {
+                errors.push(format!(
+                    "venues.{}.symbols: not supported by this venue",
+                    name
+                ));
+            }
+            for (pair, symbol) in &venue.symbols {
+                if !is_canonical_pair(pair) {
+                    errors.push(format!(
+                        "venues.{}.symbols: {:?} is not an upper-case BASE/QUOTE pair",
+                        name, pair
+                    ));
+                }
+                if symbol.trim().is_empty() {
+                    errors.push(format!("venues.{}.symbols.{:?} is empty", name, pair));
+                }
+            }
+        }
+
+        for (name, schedule) in &self.fees {
+            if !venues.contains_key(name.to_lowercase().as_str()) {
+                errors.push(format!("fees.{}: unknown venue", name));
+            }
+            let tiers = schedule
+                .tiers
+                .iter()
+                .flat_map(|t| [t.maker_bps, t.taker_bps]);
+            if [schedule.maker_bps, schedule.taker_bps]
+                .into_iter()
+                .chain(tiers)
+                .any(|bps| !bps.is_finite() || bps < 0.0)
+            {
+                errors.push(format!("fees.{}: fees must be finite and >= 0 bps", name));
+            }
+        }
+
+        errors
+    }
+
+    /// Settings of a venue (by name, case-insensitive), defaults when not configured
+    pub fn venue(&self, name: &str) -> VenueConfig {
+        self.venues
+            .get(&name.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn venue_mut(&mut self, name: &str) -> &mut VenueConfig {
+        self.venues.entry(name.to_lowercase()).or_default()
+    }
+
+    pub fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            target_spread_percent: self.engine.target_spread_percent,
+            max_age: Duration::from_millis(self.engine.max_price_age_ms),
+            venue_max_age: self
+                .engine
+                .venue_max_age_ms
+                .iter()
+                .map(|(venue, ms)| (venue.to_lowercase(), Duration::from_millis(*ms)))
+                .collect(),
+            channel_capacity: self.cache.broadcast_capacity,
+        }
+    }
+}
+
+/// Lowercase name -> kind of every built-in venue. Builds the default registry,
+/// so callers look it up once and pass it along.
+fn known_venues() -> HashMap<String, VenueKind> {
+    ConnectorRegistry::with_defaults()
+        .venues(None)
+        .into_iter()
+        .map(|venue| (venue.name.to_lowercase(), venue.kind))
+        .collect()
+}
+
+fn sorted_names(venues: &HashMap<String, VenueKind>) -> String {
+    let mut names: Vec<&str> = venues.keys().map(String::as_str).collect();
+    names.sort();
+    names.join(", ")
+}
+
+fn parse_venue_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn check_venue_list(
+    key: &str,
+    names: &[String],
+    venues: &HashMap<String, VenueKind>,
+    errors: &mut Vec<String>,
+) {
+    for name in names.iter().filter(|name| !venues.contains_key(*name)) {
+        errors.push(format!("{}: unknown venue {:?}", key, name));
+    }
+}
+
+fn set_parsed<T: std::str::FromStr>(target: &mut T, key: &str, raw: &str, errors: &mut Vec<String>)
+where
+    T::Err: std::fmt::Display,
+{
+    match raw.trim().parse() {
+        Ok(value) => *target = value,
+        Err(e) => errors.push(format!("{} {:?}: {}", key, raw, e)),
+    }
+}
+
+fn check_url(key: &str, raw: &str, schemes: &[&str], errors: &mut Vec<String>) {
+    match url::Url::parse(raw) {
+        Ok(url) if schemes.contains(&url.scheme()) => {}
+        Ok(url) => errors.push(format!(
+            "{}: scheme {:?} not allowed, expected {}",
+            key,
+            url.scheme(),
+            schemes.join("/")
+        )),
+        Err(e) => errors.push(format!("{} {:?}: {}", key, raw, e)),
+    }
+}
+
+fn is_canonical_pair(pair: &str) -> bool {
+    pair.split_once('/').is_some_and(|(base, quote)| {
+        [base, quote].iter().all(|token| {
+            !token.is_empty()
+                && token
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn file_then_env_overrides_defaults() {
+        let mut config = Config::from_toml(
+            r#"
+            [server]
+            listen = "127.0.0.1:9000"
+
+            [cache]
+            history_size = 1000
+
+            [venues.Binance]
+            url = "wss://binance.test/ws"
+            symbols = { "SOL/USDC" = "solusdc" }
+
+            [venues.orca]
+            poll_interval_ms = 250
+
+            [fees.kraken]
+            maker_bps = 16.0
+            taker_bps = 26.0
+            "#,
+        )
+        .unwrap();
+        let errors = config.apply_env(env(&[
+            ("CACHE_HISTORY_SIZE", "2000"),
+            ("DISABLED_VENUES", "coinbase"),
+            ("MAX_PRICE_AGE_MS", "5000"),
+        ]));
+        assert!(errors.is_empty(), "{:?}", errors);
+        config.validate().unwrap();
+
+        assert_eq!(config.server.listen.port(), 9000);
+        assert_eq!(config.cache.history_size, 2000); // env wins over the file
+        assert_eq!(config.cache.broadcast_capacity, 5000); // default
+        assert_eq!(
+            config.venue("BINANCE").url.as_deref(),
+            Some("wss://binance.test/ws")
+        );
+        assert_eq!(config.venue("Binance").symbols["SOL/USDC"], "solusdc");
+        assert_eq!(
+            config.venue("Orca").poll_interval(),
+            Some(Duration::from_millis(250))
+        );
+        assert!(!config.venue("Coinbase").enabled);
+        assert!(config.venue("Kraken").enabled);
+        assert_eq!(config.fees["kraken"].taker_bps, 26.0);
+        assert_eq!(config.engine_config().max_age, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let mut config = Config::from_toml(
+            r#"
+            [cache]
+            broadcast_capacity = 0
+
+            [venues.binanse]
+            enabled = false
+
+            [venues.kraken]
+            url = "https://ws.kraken.com/v2"
+            poll_interval_ms = 100
+
+            [venues.orca]
+            poll_interval_ms = 1
+
+            [venues.okx]
+            symbols = { "sol-usdt" = "SOL-USDT" }
+
+            [fees.okx]
+            maker_bps = -1.0
+            taker_bps = 10.0
+            "#,
+        )
+        .unwrap();
+        let errors = config.apply_env(env(&[
+            ("LISTEN_ADDR", "localhost"),
+            ("ENABLED_VENUES", "okx,foo"),
+        ]));
+        let message = config.check(errors).unwrap_err().to_string();
+
+        for expected in [
+            "LISTEN_ADDR \"localhost\"",
+            "ENABLED_VENUES: unknown venue \"foo\"",
+            "cache.broadcast_capacity",
+            "venues.binanse: unknown venue",
+            "venues.kraken.url: scheme \"https\" not allowed",
+            "venues.kraken.poll_interval_ms: venue streams",
+            "venues.orca.poll_interval_ms must be at least 50",
+            "venues.okx.symbols: \"sol-usdt\"",
+            "fees.okx: fees must be finite",
+        ] {
+            assert!(
+                message.contains(expected),
+                "{:?} missing from:\n{}",
+                expected,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn example_file_is_valid() {
+        let config = Config::from_file("config.example.toml").unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = Config::from_toml("[server]\nlisten_addr = \"0.0.0.0:80\"\n").unwrap_err();
+        assert!(err.to_string().contains("listen_addr"), "{}", err);
+    }
+}
@@ -1,70 +1,62 @@
+use super::{Connector, ConnectorContext, VenueKind};
+use crate::config::Config;
 use crate::state::PriceUpdate;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::connect_async;
 
 #[derive(Debug, Deserialize)]
-struct TradeMessage {
+struct BookTickerMessage {
     #[serde(rename = "e")]
     _event: String,
     #[serde(rename = "s")]
     _symbol: String,
-    #[serde(rename = "p")]
-    price: String,
-    #[serde(rename = "q")]
-    _quantity: String,
-    #[serde(rename = "t")]
-    _trade_id: u64,
+    #[serde(rename = "b")]
+    bid: String,
+    #[serde(rename = "B")]
+    bid_size: String,
+    #[serde(rename = "a")]
+    ask: String,
+    #[serde(rename = "A")]
+    ask_size: String,
     #[serde(rename = "T")]
     ts: u64,
 }
 
-pub async fn run_backpack_connector(tx: Sender<PriceUpdate>, pair: String) {
-    const BACKPACK_WS_URL: &str = "wss://ws.backpack.exchange";
-    let symbol = pair.replace("/", "_").to_uppercase();
+const BACKPACK_WS_URL: &str = "wss://ws.backpack.exchange";
 
-    println!("Backpack Connecting: {}", BACKPACK_WS_URL);
+async fn run_backpack_connector(ctx: ConnectorContext, url: String) {
+    let symbol = ctx.symbol.clone();
 
-    let (ws_stream, _) = match connect_async(BACKPACK_WS_URL).await {
-        Ok(ok) => ok,
-        Err(e) => {
-            eprintln!("Backpack Connection error: {:?}", e);
-            return;
-        }
-    };
+    ctx.supervise("Backpack", || backpack_session(&ctx, &url, &symbol))
+        .await;
+}
+
+async fn backpack_session(ctx: &ConnectorContext, url: &str, symbol: &str) -> anyhow::Result<()> {
+    println!("Backpack Connecting: {}", url);
+
+    let ws_stream = ctx.connect_ws("Backpack", url).await?;
 
     let (mut write, mut read) = ws_stream.split();
 
-    // Subscribe to trade stream
+    // Subscribe to the best bid/ask stream
     let subscribe_msg = json!({
         "method": "SUBSCRIBE",
-        "params": [ format!("trade.{}", symbol) ]
+        "params": [ format!("bookTicker.{}", symbol) ]
     });
 
-    if let Err(e) = write
+    write
         .send(tokio_tungstenite::tungstenite::Message::Text(
             subscribe_msg.to_string().into(),
         ))
-        .await
-    {
-        eprintln!("Backpack Failed to subscribe: {:?}", e);
-        return;
-    }
+        .await?;
 
-    println!("Backpack Subscribed to trade.{}", symbol);
+    println!("Backpack Subscribed to bookTicker.{}", symbol);
+    ctx.monitor.connected("Backpack");
 
     while let Some(msg) = read.next().await {
-        let msg = match msg {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("[Backpack] Error reading message: {:?}", e);
-                break;
-            }
-        };
-
-        if let tokio_tungstenite::tungstenite::Message::Text(txt) = msg {
+        if let tokio_tungstenite::tungstenite::Message::Text(txt) = msg? {
             let v: serde_json::Value = match serde_json::from_str(&txt) {
                 Ok(v) => v,
                 Err(_) => continue,
@@ -73,24 +65,68 @@ pub async fn run_backpack_connector(tx: Sender<PriceUpdate>, pair: String) {
             if v.get("stream").is_some() {
                 let data = &v["data"];
 
-                let trade: TradeMessage = match serde_json::from_value(data.clone()) {
+                let ticker: BookTickerMessage = match serde_json::from_value(data.clone()) {
                     Ok(t) => t,
                     Err(_) => continue,
                 };
 
-                let price: f64 = trade.price.parse().unwrap_or(0.0);
-
-                let update = PriceUpdate {
-                    source: "Backpack".into(),
-                    pair: pair.clone(),
-                    price,
-                    timestamp: trade.ts,
+                let (Ok(bid), Ok(bid_size), Ok(ask), Ok(ask_size)) = (
+                    ticker.bid.parse::<f64>(),
+                    ticker.bid_size.parse::<f64>(),
+                    ticker.ask.parse::<f64>(),
+                    ticker.ask_size.parse::<f64>(),
+                ) else {
+                    continue;
                 };
 
-                let _ = tx.send(update);
+                let update = PriceUpdate::quote(
+                    "Backpack",
+                    &ctx.pair,
+                    bid,
+                    bid_size,
+                    ask,
+                    ask_size,
+                    ticker.ts / 1000, // Backpack timestamps are in microseconds
+                );
+
+                let _ = ctx.tx.send(update);
             }
         }
     }
 
     println!("Backpack Disconnected");
+    Ok(())
+}
+
+pub struct BackpackConnector {
+    pub url: String,
+}
+
+impl Default for BackpackConnector {
+    fn default() -> Self {
+        Self {
+            url: BACKPACK_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for BackpackConnector {
+    fn name(&self) -> &'static str {
+        "Backpack"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_backpack_connector(ctx, url))
+    }
 }
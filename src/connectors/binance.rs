@@ -1,70 +1,292 @@
-use crate::state::PriceUpdate;
+use super::{Connector, ConnectorContext, VenueKind};
+use crate::config::Config;
+use crate::orderbook::{OrderBook, Side};
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::tungstenite::Message;
 
+/// REST depth snapshot the diff stream is applied on top of
 #[derive(Debug, Deserialize)]
-struct BinanceTrade {
-    #[serde(rename = "p")]
-    price: String,
-    #[serde(rename = "q")]
-    _quantity: String,
-    #[serde(rename = "T")]
-    timestamp: u64,
+#[serde(rename_all = "camelCase")]
+struct BinanceDepthSnapshot {
+    last_update_id: u64,
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
 }
 
-pub async fn run_binance_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let symbol = pair.to_lowercase().replace("/", "");
-    let url = format!("wss://data-stream.binance.vision/ws/{}@trade", symbol);
+/// `<symbol>@depth@100ms` diff event
+#[derive(Debug, Deserialize)]
+struct BinanceDepthUpdate {
+    #[serde(rename = "E")]
+    event_time: u64,
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    final_update_id: u64,
+    #[serde(rename = "b")]
+    bids: Vec<(String, String)>,
+    #[serde(rename = "a")]
+    asks: Vec<(String, String)>,
+}
+
+const BINANCE_WS_URL: &str = "wss://data-stream.binance.vision/ws";
+const BINANCE_REST_URL: &str = "https://data-api.binance.vision";
+
+async fn run_binance_connector(ctx: ConnectorContext, ws_url: String, rest_url: String) {
+    let symbol = ctx.symbol.clone();
+    let url = format!("{}/{}@depth@100ms", ws_url, symbol);
+    let snapshot_url = format!(
+        "{}/api/v3/depth?symbol={}&limit=1000",
+        rest_url,
+        symbol.to_uppercase()
+    );
 
+    ctx.supervise("Binance", || binance_session(&ctx, &url, &snapshot_url))
+        .await;
+}
+
+async fn binance_session(
+    ctx: &ConnectorContext,
+    url: &str,
+    snapshot_url: &str,
+) -> anyhow::Result<()> {
     println!("Connecting to Binance: {}", url);
 
-    match connect_async(&url).await {
-        Ok((mut ws_stream, _)) => {
-            println!("Binance connected!");
-
-            while let Some(msg) = ws_stream.next().await {
-                match msg {
-                    Ok(msg) => {
-                        if msg.is_ping() {
-                            ws_stream
-                                .send(Message::Pong(bytes::Bytes::new()))
-                                .await
-                                .ok();
-                            continue;
-                        }
-
-                        if msg.is_text() {
-                            let txt = msg.to_text().unwrap();
-
-                            match serde_json::from_str::<BinanceTrade>(txt) {
-                                Ok(parsed) => {
-                                    if let Ok(price) = parsed.price.parse::<f64>() {
-                                        if let Err(err) = tx.send(PriceUpdate {
-                                            source: "Binance".to_string(),
-                                            pair: pair.clone(),
-                                            price,
-                                            timestamp: parsed.timestamp,
-                                        }) {
-                                            eprintln!("Binance TX send error: {:?}", err);
-                                            break;
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    eprintln!("Binance parse error: {:?}", err);
-                                }
-                            }
-                        }
+    let mut ws_stream = ctx.connect_ws("Binance", url).await?;
+    println!("Binance connected!");
+
+    // Diffs buffer on the socket while the snapshot is fetched
+    let body: String = ctx
+        .fetch("Binance", "depth_snapshot", async {
+            Ok(reqwest::get(snapshot_url)
+                .await?
+                .error_for_status()?
+                .text()
+                .await?)
+        })
+        .await?;
+    let snapshot: BinanceDepthSnapshot = serde_json::from_str(&body)?;
+
+    let mut book = OrderBook::new();
+    for (price, size) in &snapshot.bids {
+        book.apply(Side::Bid, price, size)?;
+    }
+    for (price, size) in &snapshot.asks {
+        book.apply(Side::Ask, price, size)?;
+    }
+    book.sequence = Some(snapshot.last_update_id);
+    ctx.monitor.connected("Binance");
+
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg?;
+
+        if msg.is_ping() {
+            ws_stream
+                .send(Message::Pong(bytes::Bytes::new()))
+                .await
+                .ok();
+            continue;
+        }
+
+        if msg.is_text() {
+            let txt = msg.to_text()?;
+
+            match serde_json::from_str::<BinanceDepthUpdate>(txt) {
+                Ok(update) => {
+                    let last = book.sequence.unwrap_or(0);
+
+                    // Already contained in the snapshot
+                    if update.final_update_id <= last {
+                        continue;
                     }
-                    Err(err) => {
-                        eprintln!("Binance WS error: {:?}", err);
-                        break;
+                    // Every diff must start right after the last one we applied
+                    if update.first_update_id > last + 1 {
+                        anyhow::bail!(
+                            "depth gap: expected update {}, got {}. Resyncing",
+                            last + 1,
+                            update.first_update_id
+                        );
+                    }
+
+                    for (price, size) in &update.bids {
+                        book.apply(Side::Bid, price, size)?;
                     }
+                    for (price, size) in &update.asks {
+                        book.apply(Side::Ask, price, size)?;
+                    }
+                    book.sequence = Some(update.final_update_id);
+                    book.timestamp = update.event_time;
+
+                    ctx.publish_book("Binance", &book);
+                }
+                Err(err) => {
+                    eprintln!("Binance parse error: {:?}", err);
                 }
             }
         }
-        Err(e) => eprintln!("Binance connection error: {:?}", e),
+    }
+
+    Ok(())
+}
+
+/// Diff stream on top of a REST depth snapshot
+pub struct BinanceConnector {
+    pub ws_url: String,
+    pub rest_url: String,
+}
+
+impl Default for BinanceConnector {
+    fn default() -> Self {
+        Self {
+            ws_url: BINANCE_WS_URL.to_string(),
+            rest_url: BINANCE_REST_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for BinanceConnector {
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        let venue = config.venue(self.name());
+        if let Some(url) = venue.url {
+            self.ws_url = url;
+        }
+        if let Some(url) = venue.rest_url {
+            self.rest_url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let (ws_url, rest_url) = (self.ws_url.clone(), self.rest_url.clone());
+        ctx.run(move |ctx| run_binance_connector(ctx, ws_url, rest_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    fn diff(first: u64, last: u64, bids: serde_json::Value, asks: serde_json::Value) -> Step {
+        Step::json(json!({
+            "e": "depthUpdate",
+            "E": 1_700_000_000_000u64 + last,
+            "s": "SOLUSDT",
+            "U": first,
+            "u": last,
+            "b": bids,
+            "a": asks,
+        }))
+    }
+
+    #[tokio::test]
+    async fn applies_diffs_on_the_snapshot_and_resyncs_after_a_server_close() {
+        let snapshot = json!({
+            "lastUpdateId": 100,
+            "bids": [["150.10", "2.0"]],
+            "asks": [["150.20", "3.0"]],
+        });
+        let mut venue = MockVenue::with_rest(
+            vec![
+                vec![
+                    // Already part of the snapshot
+                    diff(95, 100, json!([["149.00", "9.0"]]), json!([])),
+                    diff(101, 102, json!([["150.15", "1.0"]]), json!([])),
+                    Step::ping(b"binance"),
+                    Step::RecvPong,
+                    Step::Close,
+                ],
+                vec![diff(101, 101, json!([]), json!([["150.18", "1.0"]]))],
+            ],
+            vec![("/api/v3/depth", snapshot)],
+        )
+        .await;
+        let mut harness = Harness::start(
+            BinanceConnector {
+                ws_url: venue.ws_url(),
+                rest_url: venue.http_url(),
+            },
+            "SOL/USDT",
+        );
+
+        let path = venue
+            .expect("stream", |e| match e {
+                Inbound::Connected(path) => Some(path.clone()),
+                _ => None,
+            })
+            .await;
+        assert_eq!(path, "/solusdt@depth@100ms");
+        let snapshot_call = venue
+            .expect("snapshot", |e| match e {
+                Inbound::Rest(uri) => Some(uri.clone()),
+                _ => None,
+            })
+            .await;
+        assert_eq!(snapshot_call, "/api/v3/depth?symbol=SOLUSDT&limit=1000");
+
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+        assert_eq!(update.timestamp, 1_700_000_000_102);
+
+        venue
+            .expect("pong", |e| matches!(e, Inbound::Pong(_)).then_some(()))
+            .await;
+
+        // Second session: new snapshot, then diffs on top of it
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.1, 150.18));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("Binance"), 1);
+    }
+
+    #[tokio::test]
+    async fn refetches_the_snapshot_on_an_update_id_gap() {
+        let snapshot = json!({
+            "lastUpdateId": 100,
+            "bids": [["150.10", "2.0"]],
+            "asks": [["150.20", "3.0"]],
+        });
+        let mut venue = MockVenue::with_rest(
+            vec![
+                vec![
+                    diff(101, 102, json!([["150.15", "1.0"]]), json!([])),
+                    // 103 and 104 never arrived
+                    diff(105, 106, json!([["150.19", "1.0"]]), json!([])),
+                ],
+                vec![diff(101, 101, json!([]), json!([["150.18", "1.0"]]))],
+            ],
+            vec![("/api/v3/depth", snapshot)],
+        )
+        .await;
+        let mut harness = Harness::start(
+            BinanceConnector {
+                ws_url: venue.ws_url(),
+                rest_url: venue.http_url(),
+            },
+            "SOL/USDT",
+        );
+
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+
+        // The gapped diff is never applied: the book is rebuilt from a fresh snapshot
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.1, 150.18));
+        for what in ["snapshot", "second snapshot"] {
+            venue
+                .expect(what, |e| matches!(e, Inbound::Rest(_)).then_some(()))
+                .await;
+        }
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("Binance"), 1);
     }
 }
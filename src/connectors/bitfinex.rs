@@ -1,53 +1,37 @@
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
 use crate::state::PriceUpdate;
+use futures_util::future::BoxFuture;
 
 use futures_util::{SinkExt, StreamExt};
 use serde_json::{Value, json};
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-
-// Helper function to map canonical pair (e.g., BTC/USDC) to Bitfinex symbol (e.g., tBTCUSD)
-fn to_bitfinex_symbol(pair: &str) -> String {
-    let parts: Vec<&str> = pair.split('/').collect();
-    if parts.len() != 2 {
-        return format!("t{}", pair.replace("/", ""));
-    }
+use tokio_tungstenite::tungstenite::Message;
 
-    let base = parts[0];
-    let quote = parts[1];
+const BITFINEX_WS_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
 
-    // Convert to Bitfinex's tSYMBOL format
-    let symbol = match (base, quote) {
-        (b, "USD") => format!("t{b}USD"),
-        (b, "USDT") => format!("t{b}:USDT"),
-        (b, "USDC") => format!("t{b}:USDC"),
-        (b, q) => format!("t{b}{q}"),
-    };
+async fn run_bitfinex_connector(ctx: ConnectorContext, url: String) {
+    // 1. Derive Bitfinex symbol from the canonical pair
+    let bitfinex_symbol = ctx.symbol.clone();
 
-    symbol.to_uppercase()
+    ctx.supervise("Bitfinex", || {
+        bitfinex_session(&ctx, &url, &bitfinex_symbol)
+    })
+    .await;
 }
 
-pub async fn run_bitfinex_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let url = "wss://api-pub.bitfinex.com/ws/2";
-
-    // 1. Derive Bitfinex symbol from the canonical pair
-    let bitfinex_symbol = to_bitfinex_symbol(&pair);
-    let canonical_pair = pair.clone();
-
+async fn bitfinex_session(
+    ctx: &ConnectorContext,
+    url: &str,
+    bitfinex_symbol: &str,
+) -> anyhow::Result<()> {
     println!(
         "Bitfinex connecting to pair: {} (Symbol: {})",
-        canonical_pair, bitfinex_symbol
+        ctx.pair, bitfinex_symbol
     );
 
-    let (mut ws, _) = match connect_async(url).await {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Bitfinex Connection error for {}: {:?}", canonical_pair, e);
-            return;
-        }
-    };
+    let mut ws = ctx.connect_ws("Bitfinex", url).await?;
 
-    println!("Bitfinex Connected to {}", canonical_pair);
+    println!("Bitfinex Connected to {}", ctx.pair);
 
     // 2. Subscribe using the dynamic Bitfinex symbol
     let sub = json!({
@@ -56,19 +40,14 @@ pub async fn run_bitfinex_connector(tx: Sender<PriceUpdate>, pair: String) {
         "symbol": bitfinex_symbol
     });
 
-    if let Err(e) = ws.send(Message::Text(sub.to_string().into())).await {
-        eprintln!(
-            "Bitfinex Subscription failed for {}: {:?}",
-            canonical_pair, e
-        );
-        return;
-    }
+    ws.send(Message::Text(sub.to_string().into())).await?;
 
-    println!("Bitfinex Subscribed to TICKER {}", canonical_pair);
+    println!("Bitfinex Subscribed to TICKER {}", ctx.pair);
+    ctx.monitor.connected("Bitfinex");
 
     while let Some(msg) = ws.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
+        match msg? {
+            Message::Text(text) => {
                 if let Ok(parsed) = serde_json::from_str::<Value>(&text) {
                     // Skip event messages
                     if parsed.get("event").is_some() {
@@ -81,42 +60,140 @@ pub async fn run_bitfinex_connector(tx: Sender<PriceUpdate>, pair: String) {
                             continue;
                         }
 
-                        if arr.len() >= 2 {
-                            if let Some(data) = arr[1].as_array() {
-                                // LAST_PRICE is at index 6
-                                if data.len() > 6 {
-                                    if let Some(price) = data[6].as_f64() {
-                                        // Generate current timestamp (Bitfinex ticker doesn't provide one)
-                                        let timestamp = SystemTime::now()
-                                            .duration_since(UNIX_EPOCH)
-                                            .unwrap()
-                                            .as_millis()
-                                            as u64;
-
-                                        let _ = tx.send(PriceUpdate {
-                                            source: "Bitfinex".to_string(),
-                                            pair: canonical_pair.clone(),
-                                            price,
-                                            timestamp, // Added timestamp
-                                        });
-                                    }
-                                }
+                        if arr.len() >= 2
+                            && let Some(data) = arr[1].as_array()
+                        {
+                            // [BID, BID_SIZE, ASK, ASK_SIZE, ..., LAST_PRICE (6), ...]
+                            if data.len() > 6
+                                && let (Some(bid), Some(bid_size), Some(ask), Some(ask_size)) = (
+                                    data[0].as_f64(),
+                                    data[1].as_f64(),
+                                    data[2].as_f64(),
+                                    data[3].as_f64(),
+                                )
+                            {
+                                // Generate current timestamp (Bitfinex ticker doesn't provide one)
+                                let timestamp = now_ms();
+
+                                let _ = ctx.tx.send(PriceUpdate::quote(
+                                    "Bitfinex", &ctx.pair, bid, bid_size, ask, ask_size, timestamp,
+                                ));
                             }
                         }
                     }
                 }
             }
-            Ok(Message::Ping(p)) => {
+            Message::Ping(p) => {
                 let _ = ws.send(Message::Pong(p)).await;
             }
-            Ok(Message::Close(_)) => break,
-            Err(e) => {
-                eprintln!("Bitfinex WS error for {}: {:?}", canonical_pair, e);
-                break;
-            }
+            Message::Close(_) => break,
             _ => {}
         }
     }
 
-    eprintln!("Bitfinex Connector for {} stopped.", canonical_pair);
+    Ok(())
+}
+
+/// Public `ticker` channel
+pub struct BitfinexConnector {
+    pub url: String,
+}
+
+impl Default for BitfinexConnector {
+    fn default() -> Self {
+        Self {
+            url: BITFINEX_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for BitfinexConnector {
+    fn name(&self) -> &'static str {
+        "Bitfinex"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_bitfinex_connector(ctx, url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+
+    fn ticker(bid: f64, ask: f64) -> Step {
+        Step::json(json!([
+            17,
+            [bid, 2.0, ask, 3.0, 0.5, 0.01, bid, 1000.0, 160.0, 140.0]
+        ]))
+    }
+
+    #[tokio::test]
+    async fn streams_the_ticker_and_resubscribes_after_a_server_close() {
+        let subscribed = json!({
+            "event": "subscribed",
+            "channel": "ticker",
+            "chanId": 17,
+            "symbol": "tSOLUST",
+            "pair": "SOLUST",
+        });
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::json(json!({ "event": "info", "version": 2 })),
+                Step::Recv,
+                Step::json(subscribed.clone()),
+                ticker(150.1, 150.2),
+                Step::json(json!([17, "hb"])),
+                Step::ping(b"bitfinex"),
+                Step::RecvPong,
+                ticker(150.15, 150.2),
+                Step::Close,
+            ],
+            vec![Step::Recv, Step::json(subscribed), ticker(150.3, 150.4)],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            BitfinexConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
+        );
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["event"], "subscribe");
+        assert_eq!(subscribe["channel"], "ticker");
+        assert_eq!(subscribe["symbol"], "tSOLUST");
+
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.1, 150.2));
+        assert_eq!((update.bid_size, update.ask_size), (Some(2.0), Some(3.0)));
+
+        venue
+            .expect("pong", |e| {
+                (e == &Inbound::Pong(b"bitfinex".to_vec())).then_some(())
+            })
+            .await;
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+
+        // Exactly one subscribe per session
+        let resubscribe = venue.expect_json("resubscribe").await;
+        assert_eq!(resubscribe["event"], "subscribe");
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.3, 150.4));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("Bitfinex"), 1);
+    }
 }
@@ -1,129 +1,145 @@
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
 use crate::state::PriceUpdate;
-use bytes::Bytes;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::{Mutex, broadcast::Sender};
-use tokio::time::{Duration, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::time::{Duration, Instant, interval_at};
+use tokio_tungstenite::tungstenite::Message;
 
 #[derive(Debug, Deserialize)]
 struct BitgetEnvelope {
-    _event: Option<String>,
-    _arg: Option<BitgetArg>,
     data: Option<Vec<BitgetTicker>>,
 }
 
+// IMPORTANT: Bitget uses camelCase (bidPr, askSz, ...)
 #[derive(Debug, Deserialize)]
-struct BitgetArg {
-    _inst_type: String,
-    _channel: String,
-    _inst_id: String,
+#[serde(rename_all = "camelCase")]
+struct BitgetTicker {
+    bid_pr: String,
+    bid_sz: String,
+    ask_pr: String,
+    ask_sz: String,
+    ts: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct BitgetTicker {
-    last_pr: String, // IMPORTANT: Bitget uses lastPr
+const BITGET_WS_URL: &str = "wss://ws.bitget.com/v2/ws/public";
+
+async fn run_bitget_connector(ctx: ConnectorContext, url: String) {
+    let symbol = ctx.symbol.clone();
+
+    ctx.supervise("Bitget", || bitget_session(&ctx, &url, &symbol))
+        .await;
 }
 
-pub async fn run_bitget_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let symbol = pair.replace("/", "").to_uppercase();
+async fn bitget_session(ctx: &ConnectorContext, ws_url: &str, symbol: &str) -> anyhow::Result<()> {
+    println!("Connecting to Bitget for {}", symbol);
+    let ws_stream = ctx.connect_ws("Bitget", ws_url).await?;
+
+    println!("Bitget WS connected");
+
+    let (mut write, mut read) = ws_stream.split();
+
+    // Subscribe to ticker
+    let sub = serde_json::json!({
+        "op": "subscribe",
+        "args": [{
+            "instType": "SPOT",
+            "channel": "ticker",
+            "instId": symbol
+        }]
+    });
+
+    write.send(Message::Text(sub.to_string().into())).await?;
+
+    println!("Subscribed to Bitget ticker {}", symbol);
+    ctx.monitor.connected("Bitget");
 
+    // Ping timer, driven by this session so it stops with it
+    // (Bitget closes sockets that send no "ping" text for 2 minutes)
+    let ping_interval = Duration::from_secs(15);
+    let mut ping = interval_at(Instant::now() + ping_interval, ping_interval);
+
+    // Read loop
     loop {
-        println!("Connecting to Bitget for {}", symbol);
-        let ws_url = "wss://ws.bitget.com/v2/ws/public";
-
-        let (ws_stream, _) = match connect_async(ws_url).await {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Bitget WS connect failed: {:?}", e);
-                sleep(Duration::from_millis(500)).await;
+        let msg = tokio::select! {
+            _ = ping.tick() => {
+                write.send(Message::Text("ping".into())).await?;
                 continue;
             }
+            msg = read.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
         };
 
-        println!("Bitget WS connected");
+        match msg {
+            Message::Text(text) => {
+                if let Ok(parsed) = serde_json::from_str::<BitgetEnvelope>(&text)
+                    && let Some(ticks) = parsed.data
+                {
+                    for tick in ticks {
+                        if let (Ok(bid), Ok(bid_size), Ok(ask), Ok(ask_size)) = (
+                            tick.bid_pr.parse::<f64>(),
+                            tick.bid_sz.parse::<f64>(),
+                            tick.ask_pr.parse::<f64>(),
+                            tick.ask_sz.parse::<f64>(),
+                        ) {
+                            let timestamp = tick
+                                .ts
+                                .and_then(|ts| ts.parse::<u64>().ok())
+                                .unwrap_or_else(now_ms);
+
+                            let _ = ctx.tx.send(PriceUpdate::quote(
+                                "Bitget", &ctx.pair, bid, bid_size, ask, ask_size, timestamp,
+                            ));
+                        }
+                    }
+                }
+            }
 
-        let (write, mut read) = ws_stream.split();
-        let write = Arc::new(Mutex::new(write));
+            Message::Ping(payload) => {
+                let _ = write.send(Message::Pong(payload)).await;
+            }
 
-        // Subscribe to ticker
-        let sub = serde_json::json!({
-            "op": "subscribe",
-            "args": [{
-                "inst_type": "SPOT",
-                "channel": "ticker",
-                "inst_id": symbol
-            }]
-        });
+            Message::Close(_) => break,
 
-        {
-            let mut w = write.lock().await;
-            let _ = w.send(Message::Text(sub.to_string().into())).await;
+            _ => {}
         }
+    }
 
-        println!("Subscribed to Bitget ticker {}", symbol);
-
-        // Spawn ping task (every 15s - increased from 1s to avoid rate limits)
-        let ping_write = Arc::clone(&write);
-        tokio::spawn(async move {
-            loop {
-                sleep(Duration::from_secs(15)).await;
-                let mut w = ping_write.lock().await;
-                // Send standard Ping
-                if w.send(Message::Ping(Bytes::from_static(b"ping")))
-                    .await
-                    .is_err()
-                {
-                    break; // connection closed
-                }
-            }
-        });
-
-        // Read loop
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    if let Ok(parsed) = serde_json::from_str::<BitgetEnvelope>(&text) {
-                        if let Some(ticks) = parsed.data {
-                            for tick in ticks {
-                                if let Ok(price) = tick.last_pr.parse::<f64>() {
-                                    // Generate System Timestamp since Bitget ticker object didn't have one in struct
-                                    let timestamp = SystemTime::now()
-                                        .duration_since(UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_millis()
-                                        as u64;
-
-                                    let _ = tx.send(PriceUpdate {
-                                        source: "Bitget".to_string(),
-                                        pair: pair.clone(),
-                                        price,
-                                        timestamp, // Added timestamp field
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
+    Ok(())
+}
 
-                Ok(Message::Ping(payload)) => {
-                    let mut w = write.lock().await;
-                    let _ = w.send(Message::Pong(payload)).await;
-                }
+pub struct BitgetConnector {
+    pub url: String,
+}
 
-                Ok(Message::Close(_)) => break,
-                Err(e) => {
-                    eprintln!("Bitget WS error: {:?}", e);
-                    break;
-                }
+impl Default for BitgetConnector {
+    fn default() -> Self {
+        Self {
+            url: BITGET_WS_URL.to_string(),
+        }
+    }
+}
 
-                _ => {}
-            }
+impl Connector for BitgetConnector {
+    fn name(&self) -> &'static str {
+        "Bitget"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
         }
+    }
 
-        eprintln!("Bitget connector disconnected. Reconnecting in 5s...");
-        sleep(Duration::from_millis(500)).await;
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_bitget_connector(ctx, url))
     }
 }
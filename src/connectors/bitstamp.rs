@@ -1,46 +1,50 @@
+use super::{Connector, ConnectorContext, VenueKind};
+use crate::config::Config;
 use crate::state::PriceUpdate;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::connect_async;
 
-// Bitstamp trade data is nested inside a "data" object
+// Bitstamp order book snapshot (top 100 levels) is nested inside a "data" object
 #[derive(Debug, Deserialize)]
-struct BitstampTradeData {
-    id: u64,
-    timestamp: String, // Bitstamp sends this as a string of seconds
-    amount_str: String,
-    price_str: String, // We use the string version for safety
+struct BitstampBookData {
+    microtimestamp: String,      // Bitstamp sends this as a string of microseconds
+    bids: Vec<(String, String)>, // [price, amount], best first
+    asks: Vec<(String, String)>,
 }
 
 #[derive(Debug, Deserialize)]
 struct BitstampMessage {
     event: String,
-    channel: String,
-    data: serde_json::Value, // We delay parsing this until we confirm it's a trade
+    #[serde(rename = "channel")]
+    _channel: String,
+    data: serde_json::Value, // Book payload, parsed once the event is "data"
 }
 
-pub async fn run_bitstamp_connector(tx: Sender<PriceUpdate>, pair: String) {
-    const BITSTAMP_WS_URL: &str = "wss://ws.bitstamp.net";
+const BITSTAMP_WS_URL: &str = "wss://ws.bitstamp.net";
 
+async fn run_bitstamp_connector(ctx: ConnectorContext, url: String) {
     // Bitstamp format: lowercase, no separator (e.g., "solusdc")
-    let symbol = pair.replace("/", "").to_lowercase();
-    let channel_name = format!("live_trades_{}", symbol);
+    let symbol = ctx.symbol.clone();
+    let channel_name = format!("order_book_{}", symbol);
 
-    println!("Bitstamp Connecting: {}", BITSTAMP_WS_URL);
+    ctx.supervise("Bitstamp", || bitstamp_session(&ctx, &url, &channel_name))
+        .await;
+}
 
-    let (ws_stream, _) = match connect_async(BITSTAMP_WS_URL).await {
-        Ok(ok) => ok,
-        Err(e) => {
-            eprintln!("Bitstamp Connection error: {:?}", e);
-            return;
-        }
-    };
+async fn bitstamp_session(
+    ctx: &ConnectorContext,
+    url: &str,
+    channel_name: &str,
+) -> anyhow::Result<()> {
+    println!("Bitstamp Connecting: {}", url);
+
+    let ws_stream = ctx.connect_ws("Bitstamp", url).await?;
 
     let (mut write, mut read) = ws_stream.split();
 
-    // Subscribe to trade stream
+    // Subscribe to order book stream
     let subscribe_msg = json!({
         "event": "bts:subscribe",
         "data": {
@@ -48,59 +52,107 @@ pub async fn run_bitstamp_connector(tx: Sender<PriceUpdate>, pair: String) {
         }
     });
 
-    if let Err(e) = write
+    write
         .send(tokio_tungstenite::tungstenite::Message::Text(
             subscribe_msg.to_string().into(),
         ))
-        .await
-    {
-        eprintln!("Bitstamp Failed to subscribe: {:?}", e);
-        return;
-    }
+        .await?;
 
     println!("Bitstamp Subscribed to {}", channel_name);
+    ctx.monitor.connected("Bitstamp");
 
     while let Some(msg) = read.next().await {
-        let msg = match msg {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("[Bitstamp] Error reading message: {:?}", e);
-                break;
-            }
-        };
-
-        if let tokio_tungstenite::tungstenite::Message::Text(txt) = msg {
+        if let tokio_tungstenite::tungstenite::Message::Text(txt) = msg? {
             // First parse the outer structure to check the event type
             let v: BitstampMessage = match serde_json::from_str(&txt) {
                 Ok(v) => v,
                 Err(_) => continue, // Skip heartbeats or malformed json
             };
 
-            // Only process if the event is strictly a "trade"
-            if v.event == "trade" {
-                let trade: BitstampTradeData = match serde_json::from_value(v.data) {
-                    Ok(t) => t,
+            // Server is about to go away, reconnect now instead of waiting for the drop
+            if v.event == "bts:request_reconnect" {
+                break;
+            }
+
+            // Only process book snapshots
+            if v.event == "data" {
+                let book: BitstampBookData = match serde_json::from_value(v.data) {
+                    Ok(b) => b,
                     Err(_) => continue,
                 };
 
-                // Parse price (string -> f64)
-                let price: f64 = trade.price_str.parse().unwrap_or(0.0);
-
-                // Parse timestamp (string -> u64)
-                // Bitstamp sends Unix seconds (e.g. "1630000000")
-                let ts: u64 = trade.timestamp.parse().unwrap_or(0);
+                // Top of book is the first level on each side
+                let (Some((bid, bid_size)), Some((ask, ask_size))) =
+                    (book.bids.first(), book.asks.first())
+                else {
+                    continue;
+                };
+                let (Ok(bid), Ok(bid_size), Ok(ask), Ok(ask_size)) = (
+                    bid.parse::<f64>(),
+                    bid_size.parse::<f64>(),
+                    ask.parse::<f64>(),
+                    ask_size.parse::<f64>(),
+                ) else {
+                    continue;
+                };
 
-                let update = PriceUpdate {
-                    source: "Bitstamp".into(),
-                    pair: pair.clone(),
-                    price,
-                    timestamp: ts,
+                // A zero timestamp would leave the venue stale, drop the frame instead
+                let Ok(ts) = book.microtimestamp.parse::<u64>() else {
+                    eprintln!(
+                        "Bitstamp skipping book with bad microtimestamp: {:?}",
+                        book.microtimestamp
+                    );
+                    continue;
                 };
 
-                let _ = tx.send(update);
+                let update = PriceUpdate::quote(
+                    "Bitstamp",
+                    &ctx.pair,
+                    bid,
+                    bid_size,
+                    ask,
+                    ask_size,
+                    ts / 1000, // Convert to ms
+                );
+
+                let _ = ctx.tx.send(update);
             }
         }
     }
 
     println!("Bitstamp Disconnected");
+    Ok(())
+}
+
+pub struct BitstampConnector {
+    pub url: String,
+}
+
+impl Default for BitstampConnector {
+    fn default() -> Self {
+        Self {
+            url: BITSTAMP_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for BitstampConnector {
+    fn name(&self) -> &'static str {
+        "Bitstamp"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_bitstamp_connector(ctx, url))
+    }
 }
@@ -1,81 +1,259 @@
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::connect_async;
 
-use crate::state::PriceUpdate;
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
+use crate::orderbook::{OrderBook, Side};
+use futures_util::future::BoxFuture;
 
+/// orderbook.50 payload: [price, size] levels, size "0" deletes the level
 #[derive(Debug, Deserialize)]
-struct BybitTickerData {
-    #[serde(rename = "lastPrice")]
-    last_price: String,
+struct BybitBookData {
+    #[serde(rename = "b")]
+    bids: Vec<(String, String)>,
+    #[serde(rename = "a")]
+    asks: Vec<(String, String)>,
+    #[serde(rename = "u")]
+    update_id: u64,
 }
 
 #[derive(Debug, Deserialize)]
 struct BybitMessage {
     topic: Option<String>,
-    data: Option<BybitTickerData>,
+    #[serde(rename = "type")]
+    msg_type: Option<String>, // "snapshot" or "delta"
+    data: Option<BybitBookData>,
     ts: Option<u64>, // Added timestamp field (Bybit sends 'ts')
 }
 
-pub async fn run_bybit_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let symbol = pair.to_uppercase().replace("/", "");
-    let url = "wss://stream.bybit.com/v5/public/spot";
+const BYBIT_WS_URL: &str = "wss://stream.bybit.com/v5/public/spot";
 
+async fn run_bybit_connector(ctx: ConnectorContext, url: String) {
+    let symbol = ctx.symbol.clone();
+
+    ctx.supervise("Bybit", || bybit_session(&ctx, &url, &symbol))
+        .await;
+}
+
+async fn bybit_session(ctx: &ConnectorContext, url: &str, symbol: &str) -> anyhow::Result<()> {
     println!("Connecting to Bybit WebSocket…");
 
-    let (ws_stream, _) = match connect_async(url).await {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Bybit WS Connect Error: {:?}", err);
-            return;
-        }
-    };
+    let ws_stream = ctx.connect_ws("Bybit", url).await?;
 
     println!("Connected to Bybit!");
 
     let (mut write, mut read) = ws_stream.split();
 
-    // Subscribe to ticker
+    // Subscribe to the 50-level book
     let subscribe_msg = serde_json::json!({
         "op": "subscribe",
-        "args": [format!("tickers.{}", symbol)] // Dynamically insert the symbol
+        "args": [format!("orderbook.50.{}", symbol)] // Dynamically insert the symbol
     });
 
-    let _ = write
+    write
         .send(tokio_tungstenite::tungstenite::Message::Text(
             subscribe_msg.to_string().into(),
         ))
-        .await;
+        .await?;
 
-    println!("Subscribed to Bybit {} ticker.", symbol);
+    println!("Subscribed to Bybit {} order book.", symbol);
+    ctx.monitor.connected("Bybit");
+
+    let mut book = OrderBook::new();
 
     // Read incoming messages
     while let Some(msg) = read.next().await {
-        if let Ok(text) = msg.and_then(|m| m.into_text()) {
-            if let Ok(parsed) = serde_json::from_str::<BybitMessage>(&text) {
-                if let (Some(_topic), Some(data)) = (parsed.topic, parsed.data) {
-                    if let Ok(price) = data.last_price.parse::<f64>() {
-                        // Use Bybit timestamp or fallback to SystemTime
-                        let timestamp = parsed.ts.unwrap_or_else(|| {
-                            SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_millis() as u64
-                        });
-
-                        let _ = tx.send(PriceUpdate {
-                            source: "Bybit".to_string(),
-                            pair: pair.clone(),
-                            price,
-                            timestamp, // Added timestamp
-                        });
-                    }
-                }
-            }
+        let Ok(text) = msg?.into_text() else {
+            continue;
+        };
+        let Ok(parsed) = serde_json::from_str::<BybitMessage>(&text) else {
+            continue;
+        };
+        let (Some(_topic), Some(data)) = (parsed.topic, parsed.data) else {
+            continue;
+        };
+
+        // A snapshot (also sent with u = 1 after a Bybit service restart) replaces the book
+        if parsed.msg_type.as_deref() == Some("snapshot") || data.update_id == 1 {
+            book.clear();
+        } else if book.sequence.map(|u| u + 1) != Some(data.update_id) {
+            anyhow::bail!(
+                "book update gap: last {:?}, got {}. Resyncing",
+                book.sequence,
+                data.update_id
+            );
+        }
+
+        for (price, size) in &data.bids {
+            book.apply(Side::Bid, price, size)?;
+        }
+        for (price, size) in &data.asks {
+            book.apply(Side::Ask, price, size)?;
         }
+        book.sequence = Some(data.update_id);
+        // Use Bybit timestamp or fallback to SystemTime
+        book.timestamp = parsed.ts.unwrap_or_else(now_ms);
+
+        ctx.publish_book("Bybit", &book);
     }
 
     println!("Bybit WebSocket closed.");
+    Ok(())
+}
+
+/// Spot `orderbook.50` snapshots and deltas
+pub struct BybitConnector {
+    pub url: String,
+}
+
+impl Default for BybitConnector {
+    fn default() -> Self {
+        Self {
+            url: BYBIT_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for BybitConnector {
+    fn name(&self) -> &'static str {
+        "Bybit"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_bybit_connector(ctx, url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    fn book(kind: &str, bids: serde_json::Value, asks: serde_json::Value, u: u64) -> Step {
+        Step::json(json!({
+            "topic": "orderbook.50.SOLUSDT",
+            "ts": 1_700_000_000_000u64 + u,
+            "type": kind,
+            "data": { "s": "SOLUSDT", "b": bids, "a": asks, "u": u, "seq": u },
+        }))
+    }
+
+    #[tokio::test]
+    async fn streams_the_book_and_resubscribes_after_a_server_close() {
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::Recv,
+                Step::json(json!({ "success": true, "ret_msg": "", "op": "subscribe" })),
+                book(
+                    "snapshot",
+                    json!([["150.1", "2"]]),
+                    json!([["150.2", "3"]]),
+                    100,
+                ),
+                Step::ping(b"bybit"),
+                Step::RecvPong,
+                book(
+                    "delta",
+                    json!([["150.1", "0"], ["150.05", "4"]]),
+                    json!([]),
+                    101,
+                ),
+                Step::Close,
+            ],
+            vec![
+                Step::Recv,
+                book(
+                    "snapshot",
+                    json!([["150.5", "1"]]),
+                    json!([["150.6", "1"]]),
+                    500,
+                ),
+            ],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            BybitConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
+        );
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["op"], "subscribe");
+        assert_eq!(subscribe["args"], json!(["orderbook.50.SOLUSDT"]));
+
+        let snapshot = harness.next_update().await;
+        assert_eq!((snapshot.bid, snapshot.ask), (150.1, 150.2));
+
+        venue
+            .expect("pong", |e| {
+                (e == &Inbound::Pong(b"bybit".to_vec())).then_some(())
+            })
+            .await;
+        // The delta removes the best bid
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.05, 150.2));
+        assert_eq!(update.timestamp, 1_700_000_000_101);
+
+        venue.expect_json("resubscribe").await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.5, 150.6));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("Bybit"), 1);
+    }
+
+    #[tokio::test]
+    async fn resubscribes_on_an_update_id_gap() {
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::Recv,
+                book(
+                    "snapshot",
+                    json!([["150.1", "2"]]),
+                    json!([["150.2", "3"]]),
+                    100,
+                ),
+                // u = 101 never arrived
+                book("delta", json!([["150.15", "1"]]), json!([]), 102),
+            ],
+            vec![
+                Step::Recv,
+                book(
+                    "snapshot",
+                    json!([["150.5", "1"]]),
+                    json!([["150.6", "1"]]),
+                    500,
+                ),
+            ],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            BybitConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
+        );
+
+        venue.expect_json("subscribe").await;
+        let snapshot = harness.next_update().await;
+        assert_eq!((snapshot.bid, snapshot.ask), (150.1, 150.2));
+
+        // The gapped delta is dropped and a fresh subscribe brings a new snapshot
+        venue.expect_json("resubscribe").await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.5, 150.6));
+        assert_eq!(harness.reconnects("Bybit"), 1);
+    }
 }
@@ -1,95 +1,157 @@
-use crate::state::PriceUpdate;
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
+use crate::orderbook::{OrderBook, PUBLISHED_DEPTH, Side};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_tungstenite::tungstenite::protocol::Message;
 
+/// level2 channel message: a full "snapshot" followed by "l2update" diffs
 #[derive(Debug, Deserialize)]
-struct CoinbaseMatch {
+struct CoinbaseLevel2 {
     #[serde(rename = "type")]
     msg_type: String,
 
-    price: Option<String>,
-    _size: Option<String>,
-    _time: Option<String>,
+    #[serde(default)]
+    bids: Vec<(String, String)>, // snapshot: [price, size]
+    #[serde(default)]
+    asks: Vec<(String, String)>,
+    #[serde(default)]
+    changes: Vec<(String, String, String)>, // l2update: [side ("buy"/"sell"), price, new size]
+    time: Option<DateTime<Utc>>,
 }
 
-pub async fn run_coinbase_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let url = "wss://ws-feed.exchange.coinbase.com";
+const COINBASE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
 
-    // Store the original requested pair for output (e.g., "BTC/USDC")
-    let canonical_pair = pair.clone();
-
-    // 1. DERIVE PRODUCT ID: "BTC/USDC" -> "BTC-USDC"
-    let mut coinbase_product_id = pair.replace("/", "-");
-
-    // 2. FALLBACK LOGIC: If requesting USDC, attempt to subscribe to the more common USD product.
-    if coinbase_product_id.ends_with("-USDC") {
-        let base_currency = coinbase_product_id.trim_end_matches("-USDC");
-        coinbase_product_id = format!("{}-USD", base_currency);
+async fn run_coinbase_connector(ctx: ConnectorContext, url: String) {
+    let coinbase_product_id = ctx.symbol.clone();
+    if coinbase_product_id != ctx.pair.replace("/", "-") {
         println!(
             "Coinbase Falling back to product ID: {} (Requested: {})",
-            coinbase_product_id, canonical_pair
+            coinbase_product_id, ctx.pair
         );
     }
 
+    ctx.supervise("Coinbase", || {
+        coinbase_session(&ctx, &url, &coinbase_product_id)
+    })
+    .await;
+}
+
+async fn coinbase_session(
+    ctx: &ConnectorContext,
+    url: &str,
+    coinbase_product_id: &str,
+) -> anyhow::Result<()> {
     println!(
         "Coinbase connecting to pair: {} (Product ID: {})",
-        canonical_pair, coinbase_product_id
+        ctx.pair, coinbase_product_id
     );
 
-    match connect_async(url).await {
-        Ok((mut ws_stream, _)) => {
-            println!("Coinbase Connected");
-
-            // Subscribe using the potentially modified product ID
-            let subscribe_msg = serde_json::json!({
-                "type": "subscribe",
-                "product_ids": [coinbase_product_id],
-                "channels": ["matches"]
-            });
-
-            if ws_stream
-                .send(Message::text(subscribe_msg.to_string()))
-                .await
-                .is_ok()
-            {
-                println!("Coinbase Subscribed to {} trades", canonical_pair);
-            }
+    let mut ws_stream = ctx.connect_ws("Coinbase", url).await?;
+    println!("Coinbase Connected");
+
+    // Subscribe using the potentially modified product ID.
+    // level2_batch is the unauthenticated level2 feed (updates batched every 50ms)
+    let subscribe_msg = serde_json::json!({
+        "type": "subscribe",
+        "product_ids": [coinbase_product_id],
+        "channels": ["level2_batch"]
+    });
+
+    ws_stream
+        .send(Message::text(subscribe_msg.to_string()))
+        .await?;
+    println!("Coinbase Subscribed to {} level2", ctx.pair);
+    let native_quote = coinbase_product_id
+        .rsplit_once('-')
+        .map_or(coinbase_product_id, |(_, quote)| quote);
+    ctx.monitor.connected("Coinbase");
+
+    // level2 carries no sequence numbers, so the only detectable gap is a diff before the snapshot
+    let mut book = OrderBook::new();
+    let mut have_snapshot = false;
+
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg?;
 
-            while let Some(msg) = ws_stream.next().await {
-                if let Ok(msg) = msg {
-                    if msg.is_text() {
-                        if let Ok(parsed) =
-                            serde_json::from_str::<CoinbaseMatch>(msg.to_text().unwrap())
-                        {
-                            if parsed.msg_type == "match" {
-                                if let Some(price_str) = parsed.price {
-                                    if let Ok(price) = price_str.parse::<f64>() {
-                                        // Generate timestamp
-                                        let timestamp = SystemTime::now()
-                                            .duration_since(UNIX_EPOCH)
-                                            .unwrap()
-                                            .as_millis()
-                                            as u64;
-
-                                        let _ = tx.send(PriceUpdate {
-                                            source: "Coinbase".to_string(),
-                                            pair: canonical_pair.clone(),
-                                            price,
-                                            timestamp, // Added timestamp field
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                    }
+        if !msg.is_text() {
+            continue;
+        }
+
+        let Ok(parsed) = serde_json::from_str::<CoinbaseLevel2>(msg.to_text()?) else {
+            continue;
+        };
+
+        match parsed.msg_type.as_str() {
+            "snapshot" => {
+                book.clear();
+                for (price, size) in &parsed.bids {
+                    book.apply(Side::Bid, price, size)?;
                 }
+                for (price, size) in &parsed.asks {
+                    book.apply(Side::Ask, price, size)?;
+                }
+                have_snapshot = true;
             }
+            "l2update" => {
+                if !have_snapshot {
+                    anyhow::bail!("level2 update before snapshot. Resyncing");
+                }
+                for (side, price, size) in &parsed.changes {
+                    let side = if side == "buy" { Side::Bid } else { Side::Ask };
+                    book.apply(side, price, size)?;
+                }
+            }
+            _ => continue,
         }
-        Err(e) => {
-            eprintln!("Coinbase Connection error for {}: {:?}", canonical_pair, e);
+
+        book.timestamp = parsed
+            .time
+            .map(|t| t.timestamp_millis() as u64)
+            .unwrap_or_else(now_ms);
+
+        // Quotes stay in the product's currency (USD after the USDC fallback)
+        if let Some(update) = book.quote("Coinbase", &ctx.pair) {
+            let _ = ctx.tx.send(update.in_native_quote(native_quote));
         }
+        ctx.books
+            .publish(book.snapshot("Coinbase", &ctx.pair, PUBLISHED_DEPTH));
+    }
+
+    Ok(())
+}
+
+pub struct CoinbaseConnector {
+    pub url: String,
+}
+
+impl Default for CoinbaseConnector {
+    fn default() -> Self {
+        Self {
+            url: COINBASE_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for CoinbaseConnector {
+    fn name(&self) -> &'static str {
+        "Coinbase"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_coinbase_connector(ctx, url))
     }
 }
@@ -1,14 +1,14 @@
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
 use crate::state::PriceUpdate;
 use bytes::Bytes;
 use flate2::read::GzDecoder;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use std::io::Read;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for fallback timestamp
-use tokio::sync::{Mutex, broadcast::Sender};
-use tokio::time::{Duration, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio::time::{Duration, interval};
+use tokio_tungstenite::tungstenite::protocol::Message;
 
 #[derive(Debug, Deserialize)]
 struct HtxEnvelope {
@@ -17,127 +17,214 @@ struct HtxEnvelope {
     #[serde(default)]
     tick: Option<HtxTick>,
     ts: Option<u64>, // Added: HTX sends timestamp at root
+    #[serde(default)]
+    ping: Option<u64>, // Server heartbeat, must be answered with {"pong": <same value>}
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct HtxTick {
-    data: Vec<HtxTrade>,
+    bid: f64,
+    bid_size: f64,
+    ask: f64,
+    ask_size: f64,
+    quote_time: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
-struct HtxTrade {
-    price: f64,
-}
+const HTX_WS_URL: &str = "wss://api-aws.huobi.pro/ws";
 
 /// Run the HTX WebSocket connector for a given pair
-pub async fn run_htx_connector(tx: Sender<PriceUpdate>, pair: String) {
+async fn run_htx_connector(ctx: ConnectorContext, url: String) {
     // HTX expects lowercase without "/": "SOL/USDT" -> "solusdt"
-    let symbol = pair.replace("/", "").to_lowercase();
-    let canonical_pair = pair.clone();
-    let channel = format!("market.{}.trade.detail", symbol);
+    let symbol = ctx.symbol.clone();
+
+    ctx.supervise("HTX", || htx_session(&ctx, &url, &symbol))
+        .await;
+}
+
+async fn htx_session(ctx: &ConnectorContext, ws_url: &str, symbol: &str) -> anyhow::Result<()> {
+    let channel = format!("market.{}.bbo", symbol);
+
+    let ws_stream = ctx.connect_ws("HTX", ws_url).await?;
+
+    let (mut write, mut read) = ws_stream.split();
+
+    // Subscribe
+    let sub = serde_json::json!({
+        "sub": channel,
+        "id": format!("htx_sub_{}", symbol)
+    });
+
+    write.send(Message::Text(sub.to_string().into())).await?;
+    ctx.monitor.connected("HTX");
+
+    // Ping timer, driven by this session so it stops with it
+    let mut ping = interval(Duration::from_millis(500));
 
+    // Read loop
     loop {
-        //println!("HTX: connecting to {}", canonical_pair);
-
-        let ws_url = "wss://api-aws.huobi.pro/ws";
-        let (ws_stream, _) = match connect_async(ws_url).await {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("HTX WS connect failed: {:?}", e);
-                sleep(Duration::from_millis(500)).await;
+        let msg = tokio::select! {
+            _ = ping.tick() => {
+                write.send(Message::Ping(Bytes::new())).await?;
                 continue;
             }
+            msg = read.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
         };
 
-        //println!("HTX: connected for {}", canonical_pair);
+        match msg {
+            Message::Binary(bin) => {
+                let mut d = GzDecoder::new(&bin[..]);
+                let mut decoded = String::new();
+                if d.read_to_string(&mut decoded).is_err() {
+                    continue;
+                }
 
-        let (write, mut read) = ws_stream.split();
-        let write = Arc::new(Mutex::new(write));
+                let Ok(parsed) = serde_json::from_str::<HtxEnvelope>(&decoded) else {
+                    continue;
+                };
 
-        // Subscribe
-        let sub = serde_json::json!({
-            "sub": channel,
-            "id": format!("htx_sub_{}", symbol)
-        });
+                // HTX drops the connection unless its gzip heartbeat is echoed back
+                if let Some(ping) = parsed.ping {
+                    let pong = serde_json::json!({ "pong": ping });
+                    write.send(Message::Text(pong.to_string().into())).await?;
+                    continue;
+                }
 
-        {
-            let mut w = write.lock().await;
-            if let Err(e) = w.send(Message::Text(sub.to_string().into())).await {
-                eprintln!("HTX subscribe failed for {}: {:?}", canonical_pair, e);
-                sleep(Duration::from_millis(500)).await;
-                continue;
+                if let Some(tick) = parsed.tick {
+                    // Use the quote time, then the envelope timestamp, then system time
+                    let timestamp = tick.quote_time.or(parsed.ts).unwrap_or_else(now_ms);
+
+                    let _ = ctx.tx.send(PriceUpdate::quote(
+                        "HTX",
+                        &ctx.pair,
+                        tick.bid,
+                        tick.bid_size,
+                        tick.ask,
+                        tick.ask_size,
+                        timestamp,
+                    ));
+                }
             }
-        }
 
-        //println!("HTX subscribed to {}", canonical_pair);
+            Message::Ping(p) => {
+                let _ = write.send(Message::Pong(p)).await;
+            }
 
-        // Ping task
-        let ping_write = Arc::clone(&write);
-        tokio::spawn(async move {
-            loop {
-                sleep(Duration::from_millis(500)).await;
-                let mut w = ping_write.lock().await;
-                if w.send(Message::Ping(Bytes::new())).await.is_err() {
-                    break;
-                }
+            Message::Close(_) => {
+                eprintln!("HTX connection closed by server for {}", ctx.pair);
+                break;
             }
-        });
-
-        // Read loop
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Binary(bin)) => {
-                    let mut d = GzDecoder::new(&bin[..]);
-                    let mut decoded = String::new();
-                    if d.read_to_string(&mut decoded).is_err() {
-                        continue;
-                    }
-
-                    if let Ok(parsed) = serde_json::from_str::<HtxEnvelope>(&decoded) {
-                        if let Some(tick) = parsed.tick {
-                            // Use HTX timestamp or fallback to system time
-                            let timestamp = parsed.ts.unwrap_or_else(|| {
-                                SystemTime::now()
-                                    .duration_since(UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_millis() as u64
-                            });
-
-                            for trade in tick.data {
-                                let _ = tx.send(PriceUpdate {
-                                    source: "HTX".to_string(),
-                                    pair: canonical_pair.clone(),
-                                    price: trade.price,
-                                    timestamp, // Added timestamp field
-                                });
-                            }
-                        }
-                    }
-                }
 
-                Ok(Message::Ping(p)) => {
-                    let mut w = write.lock().await;
-                    let _ = w.send(Message::Pong(p)).await;
-                }
+            _ => {}
+        }
+    }
 
-                Ok(Message::Close(_)) => {
-                    eprintln!("HTX connection closed by server for {}", canonical_pair);
-                    break;
-                }
+    Ok(())
+}
 
-                Err(e) => {
-                    eprintln!("HTX WS error for {}: {:?}", canonical_pair, e);
-                    break;
-                }
+/// Gzip-compressed `bbo` channel
+pub struct HtxConnector {
+    pub url: String,
+}
 
-                _ => {}
-            }
+impl Default for HtxConnector {
+    fn default() -> Self {
+        Self {
+            url: HTX_WS_URL.to_string(),
         }
+    }
+}
 
-        eprintln!(
-            "HTX disconnected for {}. Reconnecting in 5s...",
-            canonical_pair
+impl Connector for HtxConnector {
+    fn name(&self) -> &'static str {
+        "HTX"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_htx_connector(ctx, url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    fn bbo(bid: f64, ask: f64, quote_time: u64) -> Step {
+        Step::gzip(json!({
+            "ch": "market.solusdt.bbo",
+            "ts": quote_time + 1,
+            "tick": {
+                "symbol": "solusdt",
+                "bid": bid,
+                "bidSize": 2.0,
+                "ask": ask,
+                "askSize": 3.0,
+                "quoteTime": quote_time,
+            },
+        }))
+    }
+
+    #[tokio::test]
+    async fn decodes_gzip_frames_answers_heartbeats_and_resubscribes_after_a_server_close() {
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::Recv,
+                Step::gzip(json!({ "id": "htx_sub_solusdt", "status": "ok", "subbed": "market.solusdt.bbo" })),
+                bbo(150.1, 150.2, 1_700_000_000_000),
+                Step::gzip(json!({ "ping": 1_700_000_000_500u64 })),
+                Step::Recv,
+                bbo(150.15, 150.2, 1_700_000_001_000),
+                Step::Close,
+            ],
+            vec![Step::Recv, bbo(150.3, 150.4, 1_700_000_002_000)],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            HtxConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
         );
-        sleep(Duration::from_millis(500)).await;
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["sub"], "market.solusdt.bbo");
+
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.1, 150.2));
+        assert_eq!(update.timestamp, 1_700_000_000_000);
+
+        // The gzip heartbeat is echoed back as a text pong
+        let pong = venue.expect_json("pong").await;
+        assert_eq!(pong, json!({ "pong": 1_700_000_000_500u64 }));
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+
+        // The client also keeps the socket alive with its own pings
+        venue
+            .expect("client ping", |e| {
+                matches!(e, Inbound::Ping(_)).then_some(())
+            })
+            .await;
+
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.3, 150.4));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("HTX"), 1);
     }
 }
@@ -1,9 +1,11 @@
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::assets::AssetRegistry;
+use crate::config::Config;
 use crate::state::PriceUpdate;
+use futures_util::future::BoxFuture;
 use reqwest::Client;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::broadcast::Sender;
 use tokio::time::{Duration, sleep};
 
 // Jupiter Price API V3 response format
@@ -18,38 +20,41 @@ pub struct JupiterPrice {
 // Map<mint → JupiterPrice>
 pub type JupiterResponse = HashMap<String, JupiterPrice>;
 
-/// Mint address constants (using a small, fixed set as requested)
-const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
-const ETH_MINT: &str = "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs";
-const BTC_MINT: &str = "3NZ9JMVBmGAqocybic2c7LQCJScmgsAZ6vQqTDzcqmJh";
+const JUPITER_PRICE_URL: &str = "https://lite-api.jup.ag/price/v3";
+/// Default period between two polls
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-/// Helper to map the token symbol (e.g., "BTC") to its Solana Mint address
-fn get_mint_from_pair(pair: &str) -> Option<&'static str> {
-    let base_token = pair.split('/').next()?;
+/// Quotes Jupiter's USD price can be converted into
+const USD_QUOTES: [&str; 3] = ["USD", "USDT", "USDC"];
 
-    match base_token {
-        "SOL" => Some(SOL_MINT),
-        "ETH" => Some(ETH_MINT),
-        "BTC" => Some(BTC_MINT),
-        _ => None,
+/// Solana mint of the base token (e.g., "BTC") from the asset registry
+fn get_mint_from_pair<'a>(assets: &'a AssetRegistry, pair: &str) -> Option<&'a str> {
+    let (base_token, quote) = pair.split_once('/')?;
+    if !USD_QUOTES.contains(&quote) {
+        return None;
     }
+
+    assets.mint(base_token)
 }
 
 // UPDATED SIGNATURE: Accept the `pair` string
-pub async fn run_jupiter_connector(tx: Sender<PriceUpdate>, pair: String) {
+async fn run_jupiter_connector(ctx: ConnectorContext, price_url: String, poll_interval: Duration) {
+    let ConnectorContext {
+        tx, pair, symbol, ..
+    } = &ctx;
     let canonical_pair = pair.clone(); // Store original pair for output
 
-    // 1. Map the pair to the required mint address
-    let mint_address = match get_mint_from_pair(&canonical_pair) {
-        Some(mint) => mint,
-        None => {
-            eprintln!(
-                "Jupiter Error Unsupported base token in pair {}. Only SOL, ETH, BTC supported.",
-                canonical_pair
-            );
-            return;
-        }
-    };
+    // 1. The mint address is the venue symbol (built-in or configured)
+    let mint_address = symbol.as_str();
+    if mint_address.is_empty() {
+        eprintln!(
+            "Jupiter Error Unsupported base token in pair {}. No mint in the asset registry.",
+            canonical_pair
+        );
+        ctx.monitor
+            .disconnected("Jupiter", Some("No mint for the base token".to_string()));
+        return;
+    }
 
     println!(
         "JUPITER Starting Jupiter (API V3) feed for {}. Mint: {}",
@@ -60,35 +65,47 @@ pub async fn run_jupiter_connector(tx: Sender<PriceUpdate>, pair: String) {
 
     loop {
         // 2. Use the mint address in the dynamic URL
-        let url = format!("https://lite-api.jup.ag/price/v3?ids={}", mint_address);
+        let url = format!("{}?ids={}", price_url, mint_address);
 
         // Use the mint address itself as the key for parsing the response map
         let expected_key = mint_address;
 
-        let response = client.get(&url).send().await;
+        let response: anyhow::Result<String> = ctx
+            .fetch("Jupiter", "price", async {
+                Ok(client.get(&url).send().await?.text().await?)
+            })
+            .await;
 
         match response {
-            Ok(resp) => {
-                let json = resp.json::<JupiterResponse>().await;
+            Ok(body) => {
+                let json = serde_json::from_str::<JupiterResponse>(&body);
                 match json {
                     Ok(map) => {
                         if let Some(price_data) = map.get(expected_key) {
                             // Generate timestamp since this is a polled HTTP endpoint
-                            let timestamp = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_millis() as u64;
-
-                            let update = PriceUpdate {
-                                source: "Jupiter".into(),
-                                // 3. Use the original canonical pair for output
-                                pair: canonical_pair.clone(),
-                                price: price_data.usd_price,
-                                timestamp, // Added timestamp field
-                            };
-
-                            // broadcast the update
-                            let _ = tx.send(update);
+                            let timestamp = now_ms();
+
+                            // 3. Use the original canonical pair for output
+                            // (an aggregator price has no book, so bid == ask)
+                            // Jupiter prices everything in USD
+                            let update = PriceUpdate::single(
+                                "Jupiter",
+                                &canonical_pair,
+                                price_data.usd_price,
+                                timestamp,
+                            )
+                            .in_native_quote("USD");
+
+                            ctx.monitor.connected("Jupiter");
+
+                            // broadcast the update, stop polling once nobody listens
+                            if tx.send(update).is_err() {
+                                println!(
+                                    "Jupiter No subscribers left for {}. Stopping",
+                                    canonical_pair
+                                );
+                                break;
+                            }
                         } else {
                             println!(
                                 "Jupiter Warning: Price data not found for mint {}.",
@@ -99,16 +116,64 @@ pub async fn run_jupiter_connector(tx: Sender<PriceUpdate>, pair: String) {
 
                     Err(e) => {
                         println!("Jupiter JSON parse error for {}: {:?}", canonical_pair, e);
+                        ctx.monitor
+                            .disconnected("Jupiter", Some(format!("Bad response: {}", e)));
                     }
                 }
             }
 
             Err(e) => {
                 println!("Jupiter Request error for {}: {:?}", canonical_pair, e);
+                ctx.monitor
+                    .disconnected("Jupiter", Some(format!("{:#}", e)));
             }
         }
 
-        // Delay is 1 second, Jupiter API rate limit is usually fine with this.
-        sleep(Duration::from_millis(500)).await;
+        // Jupiter API rate limit is usually fine with the default interval
+        sleep(poll_interval).await;
+    }
+}
+
+/// Polls the Price API V3
+pub struct JupiterConnector {
+    pub url: String,
+    pub poll_interval: Duration,
+}
+
+impl Default for JupiterConnector {
+    fn default() -> Self {
+        Self {
+            url: JUPITER_PRICE_URL.to_string(),
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+}
+
+impl Connector for JupiterConnector {
+    fn name(&self) -> &'static str {
+        "Jupiter"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Dex
+    }
+
+    fn symbol(&self, assets: &AssetRegistry, pair: &str) -> Option<String> {
+        get_mint_from_pair(assets, pair).map(str::to_string)
+    }
+
+    fn configure(&mut self, config: &Config) {
+        let venue = config.venue(self.name());
+        if let Some(url) = &venue.url {
+            self.url = url.clone();
+        }
+        if let Some(interval) = venue.poll_interval() {
+            self.poll_interval = interval;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let (url, poll_interval) = (self.url.clone(), self.poll_interval);
+        ctx.run(move |ctx| run_jupiter_connector(ctx, url, poll_interval))
     }
 }
@@ -1,115 +1,255 @@
-use crate::state::PriceUpdate;
+use super::{Connector, ConnectorContext, VenueKind};
+use crate::config::Config;
+use crate::orderbook::{OrderBook, Side};
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-
-#[derive(Debug, Deserialize)]
-struct KrakenTradeEntry(
-    String,                     // price
-    #[allow(dead_code)] String, // volume
-    String,                     // timestamp (seconds.decimals)
-    #[allow(dead_code)] String, // buy/sell
-    #[allow(dead_code)] String, // market/limit
-    #[allow(dead_code)] String, // misc
-);
-
-// Helper function to map canonical pair (e.g., BTC/USDC) to Kraken V1 symbol (e.g., XBT/USDC)
-fn to_kraken_symbol(pair: &str) -> String {
-    let parts: Vec<&str> = pair.split('/').collect();
-    if parts.len() != 2 {
-        return pair.to_string(); // Return original if format is wrong
+use tokio_tungstenite::tungstenite::Message;
+
+const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
+
+/// Subscribed book depth, also the depth covered by the checksum
+const BOOK_DEPTH: usize = 10;
+
+/// One book object of a book message. Snapshots use "as"/"bs", updates "a"/"b" + checksum.
+/// Levels are [price, volume, timestamp (seconds.decimals), optional "r" republish flag]
+#[derive(Debug, Default, Deserialize)]
+struct KrakenBookData {
+    #[serde(rename = "as", default)]
+    snapshot_asks: Vec<Vec<String>>,
+    #[serde(rename = "bs", default)]
+    snapshot_bids: Vec<Vec<String>>,
+    #[serde(rename = "a", default)]
+    asks: Vec<Vec<String>>,
+    #[serde(rename = "b", default)]
+    bids: Vec<Vec<String>>,
+    #[serde(rename = "c")]
+    checksum: Option<String>,
+}
+
+async fn run_kraken_connector(ctx: ConnectorContext, url: String) {
+    let kraken_subscription_symbol = ctx.symbol.clone();
+
+    ctx.supervise("Kraken", || {
+        kraken_session(&ctx, &url, &kraken_subscription_symbol)
+    })
+    .await;
+}
+
+/// CRC32 over the top 10 asks then the top 10 bids, each level as
+/// price + volume with the decimal point and leading zeros removed
+fn kraken_checksum(book: &OrderBook) -> u32 {
+    let mut payload = String::new();
+    for side in [Side::Ask, Side::Bid] {
+        for (price, volume) in book.raw_levels(side).take(BOOK_DEPTH) {
+            for field in [price, volume] {
+                payload.push_str(field.replace('.', "").trim_start_matches('0'));
+            }
+        }
     }
 
-    let base = parts[0];
-    let quote = parts[1];
-
-    // 1. Map Base Currency: BTC is usually XBT on V1
-    let kraken_base = match base {
-        "BTC" => "XBT".to_string(),
-        // SOL is sometimes XSOL, but often just SOL
-        _ => base.to_string(),
-    };
-
-    // 2. Map Quote Currency: Fiat/Stablecoin conversion is messy on Kraken V1
-    let kraken_quote = match quote {
-        "USD" => "USD".to_string(), // SOL/USD or BTC/USD are common
-        "USDT" => "USDT".to_string(),
-        "USDC" => "USDC".to_string(),
-        _ => quote.to_string(),
-    };
-
-    // Combine them
-    format!("{}/{}", kraken_base, kraken_quote)
+    crc32fast::hash(payload.as_bytes())
 }
 
-pub async fn run_kraken_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let kraken_subscription_symbol = to_kraken_symbol(&pair);
+/// Apply [price, volume, timestamp, ..] levels, returning the newest timestamp (ms)
+fn apply_levels(book: &mut OrderBook, side: Side, levels: &[Vec<String>]) -> anyhow::Result<u64> {
+    let mut latest = 0;
+    for level in levels {
+        if let [price, volume, ts, ..] = level.as_slice() {
+            book.apply(side, price, volume)?;
+            latest = latest.max((ts.parse::<f64>()? * 1000.0) as u64);
+        }
+    }
+    Ok(latest)
+}
 
-    let url = "wss://ws.kraken.com";
+async fn kraken_session(
+    ctx: &ConnectorContext,
+    url: &str,
+    kraken_subscription_symbol: &str,
+) -> anyhow::Result<()> {
     println!("Kraken connecting : {url}");
 
-    match connect_async(url).await {
-        Ok((mut ws_stream, _)) => {
-            println!("Kraken Connected");
-
-            // Subscribe to trades
-            let subscribe_msg = serde_json::json!({
-                "event": "subscribe",
-                "pair": [kraken_subscription_symbol.clone()],
-                "subscription": { "name": "trade" }
-            });
-
-            ws_stream
-                .send(Message::Text(subscribe_msg.to_string().into()))
-                .await
-                .unwrap();
-
-            println!("Kraken Subscribed to {} trades", kraken_subscription_symbol);
-
-            while let Some(msg) = ws_stream.next().await {
-                if let Ok(msg) = msg {
-                    if msg.is_text() {
-                        let text = msg.to_text().unwrap();
-
-                        // Skip non-trade events
-                        if text.contains("heartbeat") || text.contains("\"event\"") {
-                            continue;
-                        }
-
-                        // Kraken sends trade arrays: [channelID, [[price, vol, time, ...], ...], channelName, pair]
-                        if let Ok(json_val) = serde_json::from_str::<serde_json::Value>(text) {
-                            if let Some(arr) = json_val.as_array() {
-                                // Ensure we have the data array at index 1
-                                if arr.len() >= 2 && arr[1].is_array() {
-                                    if let Ok(trades) =
-                                        serde_json::from_value::<Vec<KrakenTradeEntry>>(
-                                            arr[1].clone(),
-                                        )
-                                    {
-                                        for trade in trades {
-                                            if let Ok(price) = trade.0.parse::<f64>() {
-                                                // Parse timestamp: Kraken sends seconds as string "1616661666.1234"
-                                                let ts_seconds =
-                                                    trade.2.parse::<f64>().unwrap_or(0.0);
-                                                let timestamp = (ts_seconds * 1000.0) as u64; // Convert to ms
-
-                                                let _ = tx.send(PriceUpdate {
-                                                    source: "Kraken".to_string(),
-                                                    pair: pair.clone(),
-                                                    price,
-                                                    timestamp, // Added timestamp field
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    let mut ws_stream = ctx.connect_ws("Kraken", url).await?;
+    println!("Kraken Connected");
+
+    // Subscribe to the book (re-sent on every reconnect, which also re-sends the snapshot)
+    let subscribe_msg = serde_json::json!({
+        "event": "subscribe",
+        "pair": [kraken_subscription_symbol],
+        "subscription": { "name": "book", "depth": BOOK_DEPTH }
+    });
+
+    ws_stream
+        .send(Message::Text(subscribe_msg.to_string().into()))
+        .await?;
+
+    println!("Kraken Subscribed to {} book", kraken_subscription_symbol);
+    ctx.monitor.connected("Kraken");
+
+    let mut book = OrderBook::new();
+
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg?;
+        if !msg.is_text() {
+            continue;
+        }
+        let text = msg.to_text()?;
+
+        // Skip non-book events
+        if text.contains("heartbeat") || text.contains("\"event\"") {
+            continue;
+        }
+
+        // Kraken sends book arrays: [channelID, {book}, ({book},) "book-10", pair]
+        let Ok(json_val) = serde_json::from_str::<serde_json::Value>(text) else {
+            continue;
+        };
+        let Some(arr) = json_val.as_array() else {
+            continue;
+        };
+        if arr.len() < 4 {
+            continue;
+        }
+
+        let mut checksum = None;
+        for part in &arr[1..arr.len() - 2] {
+            let data: KrakenBookData = serde_json::from_value(part.clone())?;
+
+            if !data.snapshot_asks.is_empty() || !data.snapshot_bids.is_empty() {
+                book.clear();
+            }
+
+            let mut latest = book.timestamp;
+            for (side, levels) in [
+                (Side::Ask, &data.snapshot_asks),
+                (Side::Bid, &data.snapshot_bids),
+                (Side::Ask, &data.asks),
+                (Side::Bid, &data.bids),
+            ] {
+                latest = latest.max(apply_levels(&mut book, side, levels)?);
+            }
+            book.timestamp = latest;
+
+            if data.checksum.is_some() {
+                checksum = data.checksum;
             }
         }
-        Err(e) => eprintln!("Kraken Connection error: {:?}", e),
+
+        // Levels pushed out of the subscribed depth are not deleted explicitly
+        book.truncate(BOOK_DEPTH);
+
+        if let Some(expected) = checksum
+            && kraken_checksum(&book) != expected.parse::<u32>()?
+        {
+            anyhow::bail!("book checksum mismatch. Resyncing");
+        }
+
+        ctx.publish_book("Kraken", &book);
+    }
+
+    Ok(())
+}
+
+/// V1 `book` channel with checksum validation
+pub struct KrakenConnector {
+    pub url: String,
+}
+
+impl Default for KrakenConnector {
+    fn default() -> Self {
+        Self {
+            url: KRAKEN_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for KrakenConnector {
+    fn name(&self) -> &'static str {
+        "Kraken"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_kraken_connector(ctx, url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    fn book(data: serde_json::Value) -> Step {
+        Step::json(json!([336, data, "book-10", "SOL/USD"]))
+    }
+
+    #[tokio::test]
+    async fn streams_the_book_and_resubscribes_after_a_server_close() {
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::json(json!({ "event": "systemStatus", "status": "online" })),
+                Step::Recv,
+                book(json!({
+                    "as": [["150.20000", "3.00000000", "1700000000.000000"]],
+                    "bs": [["150.10000", "2.00000000", "1700000000.000000"]],
+                })),
+                Step::json(json!({ "event": "heartbeat" })),
+                Step::ping(b"kraken"),
+                Step::RecvPong,
+                book(json!({ "b": [["150.15000", "1.00000000", "1700000001.500000"]] })),
+                Step::Close,
+            ],
+            vec![
+                Step::Recv,
+                book(json!({
+                    "as": [["151.00000", "4.00000000", "1700000002.000000"]],
+                    "bs": [["150.90000", "5.00000000", "1700000002.000000"]],
+                })),
+            ],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            KrakenConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USD",
+        );
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["event"], "subscribe");
+        assert_eq!(subscribe["pair"], json!(["SOL/USD"]));
+        assert_eq!(subscribe["subscription"]["name"], "book");
+
+        let snapshot = harness.next_update().await;
+        assert_eq!((snapshot.bid, snapshot.ask), (150.1, 150.2));
+        assert_eq!(snapshot.timestamp, 1_700_000_000_000);
+
+        venue
+            .expect("pong", |e| {
+                (e == &Inbound::Pong(b"kraken".to_vec())).then_some(())
+            })
+            .await;
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+        assert_eq!(update.timestamp, 1_700_000_001_500);
+
+        // The server closed the first session: a fresh subscribe rebuilds the book
+        venue.expect_json("resubscribe").await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.9, 151.0));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("Kraken"), 1);
     }
 }
@@ -1,12 +1,12 @@
+use super::{Connector, ConnectorContext, VenueKind, now_ms};
+use crate::config::Config;
 use crate::state::PriceUpdate;
 use bytes::Bytes;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::{Mutex, broadcast::Sender};
-use tokio::time::{Duration, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::time::{Duration, Instant, interval_at};
+use tokio_tungstenite::tungstenite::Message;
 
 #[derive(Debug, Deserialize)]
 struct BulletResponse {
@@ -49,148 +49,268 @@ struct KucoinMessage {
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct TickerData {
-    price: String,
+    best_bid: String,
+    best_bid_size: String,
+    best_ask: String,
+    best_ask_size: String,
+    time: Option<u64>,
 }
 
+const KUCOIN_REST_URL: &str = "https://api.kucoin.com";
+
 /// Run the KuCoin WebSocket connector
-/// - `tx` : broadcast sender for PriceUpdate
-/// - `pair`: trading pair, e.g., "BTC/USDT"
-pub async fn run_kucoin_connector(tx: Sender<PriceUpdate>, pair: String) {
+/// - `ctx.tx` : broadcast sender for PriceUpdate
+/// - `ctx.pair`: trading pair, e.g., "BTC/USDT"
+async fn run_kucoin_connector(ctx: ConnectorContext, rest_url: String) {
     // Convert to KuCoin symbol format: "BTC/USDT" -> "BTC-USDT"
-    let kucoin_symbol = pair.replace("/", "-").to_uppercase();
-    let canonical_pair = pair.clone(); // keep original for broadcast
+    let kucoin_symbol = ctx.symbol.clone();
+
+    ctx.supervise("KuCoin", || kucoin_session(&ctx, &rest_url, &kucoin_symbol))
+        .await;
+}
+
+async fn kucoin_session(
+    ctx: &ConnectorContext,
+    rest_url: &str,
+    kucoin_symbol: &str,
+) -> anyhow::Result<()> {
+    println!("KuCoin Attempting connection for {}", ctx.pair);
+
+    // 1️⃣ Fetch Bullet token & server endpoint (tokens are single-use, so every reconnect asks again)
+    let body: String = ctx
+        .fetch("KuCoin", "bullet", async {
+            Ok(reqwest::Client::new()
+                .post(format!("{}/api/v1/bullet-public", rest_url))
+                .send()
+                .await?
+                .text()
+                .await?)
+        })
+        .await?;
+    let bullet: BulletResponse = serde_json::from_str(&body)?;
+
+    let server = bullet
+        .data
+        .instance_servers
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("KuCoin returned no instance servers"))?;
+    let ws_url = format!("{}?token={}", server.endpoint, bullet.data.token);
+
+    // 2️⃣ Connect to WebSocket
+    let ws_stream = ctx.connect_ws("KuCoin", &ws_url).await?;
+    println!(
+        "KuCoin WS connected for {} at {}",
+        ctx.pair, server.endpoint
+    );
 
+    let (mut write, mut read) = ws_stream.split();
+
+    // 3️⃣ Subscribe to ticker
+    let sub_msg = serde_json::json!({
+        "id": 1,
+        "type": "subscribe",
+        "topic": format!("/market/ticker:{}", kucoin_symbol),
+        "privateChannel": false,
+        "response": true
+    });
+
+    write
+        .send(Message::Text(sub_msg.to_string().into()))
+        .await?;
+    println!("Subscribed to KuCoin ticker {}", ctx.pair);
+    ctx.monitor.connected("KuCoin");
+
+    // 4️⃣ Keep-alive timer, driven by this session so it stops with it
+    // (KuCoin expects an application-level ping message)
+    let ping_interval = Duration::from_millis(server.ping_interval);
+    let mut ping = interval_at(Instant::now() + ping_interval, ping_interval);
+
+    // 5️⃣ Read loop
     loop {
-        println!("KuCoin Attempting connection for {}", canonical_pair);
-
-        // 1️⃣ Fetch Bullet token & server endpoint
-        let bullet_resp = match reqwest::Client::new()
-            .post("https://api.kucoin.com/api/v1/bullet-public")
-            .send()
-            .await
-        {
-            Ok(resp) => resp,
-            Err(e) => {
-                eprintln!("KuCoin bullet request failed: {:?}", e);
-                sleep(Duration::from_secs(5)).await;
+        let msg = tokio::select! {
+            _ = ping.tick() => {
+                let ping = serde_json::json!({ "id": now_ms().to_string(), "type": "ping" });
+                write.send(Message::Text(ping.to_string().into())).await?;
                 continue;
             }
+            msg = read.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
         };
 
-        let bullet: BulletResponse = match bullet_resp.json().await {
-            Ok(json) => json,
-            Err(e) => {
-                eprintln!("Failed to parse Bullet JSON: {:?}", e);
-                sleep(Duration::from_secs(5)).await;
-                continue;
+        match msg {
+            Message::Text(text) => {
+                if let Ok(parsed) = serde_json::from_str::<KucoinMessage>(&text)
+                    && parsed.msg_type == "message"
+                    && let Some(tick) = parsed.data
+                    && let (Ok(bid), Ok(bid_size), Ok(ask), Ok(ask_size)) = (
+                        tick.best_bid.parse::<f64>(),
+                        tick.best_bid_size.parse::<f64>(),
+                        tick.best_ask.parse::<f64>(),
+                        tick.best_ask_size.parse::<f64>(),
+                    )
+                {
+                    let timestamp = tick.time.unwrap_or_else(now_ms);
+                    let _ = ctx.tx.send(PriceUpdate::quote(
+                        "KuCoin", &ctx.pair, bid, bid_size, ask, ask_size, timestamp,
+                    ));
+                }
             }
-        };
-
-        if bullet.data.instance_servers.is_empty() {
-            eprintln!("KuCoin Error: No instance servers found");
-            sleep(Duration::from_secs(5)).await;
-            continue;
+            Message::Ping(_) => {
+                let _ = write.send(Message::Pong(Bytes::new())).await;
+            }
+            Message::Close(_) => break,
+            _ => {}
         }
+    }
 
-        let server = &bullet.data.instance_servers[0];
-        let ws_url = format!("{}?token={}", server.endpoint, bullet.data.token);
+    Ok(())
+}
 
-        // 2️⃣ Connect to WebSocket
-        let (ws_stream, _) = match connect_async(&ws_url).await {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("KuCoin WS connection failed: {:?}", e);
-                sleep(Duration::from_secs(5)).await;
-                continue;
-            }
-        };
-        println!(
-            "KuCoin WS connected for {} at {}",
-            canonical_pair, server.endpoint
-        );
+/// Ticker channel on the WebSocket endpoint handed out by the bullet-public REST call
+pub struct KucoinConnector {
+    pub rest_url: String,
+}
+
+impl Default for KucoinConnector {
+    fn default() -> Self {
+        Self {
+            rest_url: KUCOIN_REST_URL.to_string(),
+        }
+    }
+}
 
-        let (write, mut read) = ws_stream.split();
-        let write = Arc::new(Mutex::new(write));
+impl Connector for KucoinConnector {
+    fn name(&self) -> &'static str {
+        "KuCoin"
+    }
 
-        // 3️⃣ Subscribe to ticker
-        let sub_msg = serde_json::json!({
-            "id": 1,
-            "type": "subscribe",
-            "topic": format!("/market/ticker:{}", kucoin_symbol),
-            "privateChannel": false,
-            "response": true
-        });
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
 
-        {
-            let mut w = write.lock().await;
-            if w.send(Message::Text(sub_msg.to_string().into()))
-                .await
-                .is_err()
-            {
-                eprintln!("Failed to send subscription for {}", canonical_pair);
-                continue;
-            }
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).rest_url {
+            self.rest_url = url;
         }
-        println!("Subscribed to KuCoin ticker {}", canonical_pair);
-
-        // 4️⃣ Ping task to keep WS alive
-        let ping_write = Arc::clone(&write);
-        let ping_interval = Duration::from_millis(server.ping_interval);
-        tokio::spawn(async move {
-            loop {
-                sleep(ping_interval).await;
-                let mut w = ping_write.lock().await;
-                if w.send(Message::Ping(Bytes::new())).await.is_err() {
-                    break; // WS closed
-                }
-            }
-        });
+    }
 
-        // 5️⃣ Read loop
-        let read_tx = tx.clone();
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    if let Ok(parsed) = serde_json::from_str::<KucoinMessage>(&text) {
-                        if parsed.msg_type == "message" {
-                            if let Some(tick) = parsed.data {
-                                if let Ok(price) = tick.price.parse::<f64>() {
-                                    // Generate System Timestamp
-                                    let timestamp = SystemTime::now()
-                                        .duration_since(UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_millis()
-                                        as u64;
-
-                                    let _ = read_tx.send(PriceUpdate {
-                                        source: "KuCoin".to_string(),
-                                        pair: canonical_pair.clone(),
-                                        price,
-                                        timestamp, // Added timestamp field
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-                Ok(Message::Ping(_)) => {
-                    let mut w = write.lock().await;
-                    let _ = w.send(Message::Pong(Bytes::new())).await;
-                }
-                Ok(Message::Close(_)) => break,
-                Err(e) => {
-                    eprintln!("KuCoin WS error: {:?}", e);
-                    break;
-                }
-                _ => {}
-            }
-        }
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let rest_url = self.rest_url.clone();
+        ctx.run(move |ctx| run_kucoin_connector(ctx, rest_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    fn ticker(bid: &str, ask: &str, time: u64) -> Step {
+        Step::json(json!({
+            "type": "message",
+            "topic": "/market/ticker:SOL-USDT",
+            "subject": "trade.ticker",
+            "data": {
+                "bestBid": bid,
+                "bestBidSize": "2",
+                "bestAsk": ask,
+                "bestAskSize": "3",
+                "price": bid,
+                "sequence": "1",
+                "size": "0.1",
+                "time": time,
+            },
+        }))
+    }
 
-        eprintln!(
-            "KuCoin connector for {} disconnected. Reconnecting...",
-            canonical_pair
+    #[tokio::test]
+    async fn fetches_a_bullet_token_keeps_alive_and_reconnects_after_a_server_close() {
+        let bullet = json!({
+            "code": "200000",
+            "data": {
+                "token": "bullet-token",
+                "instanceServers": [{
+                    "endpoint": "ws://{addr}/endpoint",
+                    "encrypt": true,
+                    "protocol": "websocket",
+                    "pingInterval": 100,
+                    "pingTimeout": 1000,
+                }],
+            },
+        });
+        let welcome = json!({ "id": "welcome", "type": "welcome" });
+        let mut venue = MockVenue::with_rest(
+            vec![
+                vec![
+                    Step::json(welcome.clone()),
+                    Step::Recv,
+                    Step::json(json!({ "id": "1", "type": "ack" })),
+                    ticker("150.1", "150.2", 1_700_000_000_000),
+                    Step::Recv,
+                    Step::json(json!({ "id": "ping", "type": "pong" })),
+                    Step::ping(b"kucoin"),
+                    Step::RecvPong,
+                    ticker("150.15", "150.2", 1_700_000_001_000),
+                    Step::Close,
+                ],
+                vec![
+                    Step::json(welcome),
+                    Step::Recv,
+                    ticker("150.3", "150.4", 1_700_000_002_000),
+                ],
+            ],
+            vec![("/api/v1/bullet-public", bullet)],
+        )
+        .await;
+        let mut harness = Harness::start(
+            KucoinConnector {
+                rest_url: venue.http_url(),
+            },
+            "SOL/USDT",
         );
-        sleep(Duration::from_millis(500)).await;
+
+        venue
+            .expect("bullet", |e| matches!(e, Inbound::Rest(_)).then_some(()))
+            .await;
+        let path = venue
+            .expect("connect", |e| match e {
+                Inbound::Connected(path) => Some(path.clone()),
+                _ => None,
+            })
+            .await;
+        assert_eq!(path, "/endpoint?token=bullet-token");
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["type"], "subscribe");
+        assert_eq!(subscribe["topic"], "/market/ticker:SOL-USDT");
+
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.1, 150.2));
+        assert_eq!(update.timestamp, 1_700_000_000_000);
+
+        // Application-level keep-alive at the bullet's ping interval
+        let ping = venue.expect_json("keep-alive ping").await;
+        assert_eq!(ping["type"], "ping");
+        venue
+            .expect("pong", |e| matches!(e, Inbound::Pong(_)).then_some(()))
+            .await;
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+
+        // Every reconnect asks for a new token
+        venue
+            .expect("second bullet", |e| {
+                matches!(e, Inbound::Rest(_)).then_some(())
+            })
+            .await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.3, 150.4));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("KuCoin"), 1);
     }
 }
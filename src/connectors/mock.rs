@@ -0,0 +1,663 @@
+//! Local stand-ins for venue WebSocket/REST servers and the Solana JSON-RPC,
+//! used by the connector integration tests.
+
+use super::{Connector, ConnectorContext};
+use crate::assets::AssetRegistry;
+use crate::fees::FeeModel;
+use crate::orderbook::BookStore;
+use crate::state::{PriceUpdate, VenueMonitor, VenueStatus};
+use axum::Router;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Json, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, post};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::{Value, json};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// How long a test waits for the next expected event
+const WAIT: Duration = Duration::from_secs(5);
+
+// --- Mock Venue ---
+
+/// One step of the server side of a WebSocket session
+pub enum Step {
+    /// Wait for the next text or binary message of the client
+    Recv,
+    /// Wait for the client's pong
+    RecvPong,
+    Send(Message),
+    /// Send a close frame and drop the connection
+    Close,
+}
+
+impl Step {
+    pub fn text(text: impl Into<String>) -> Self {
+        Step::Send(Message::Text(text.into().into()))
+    }
+
+    pub fn json(value: Value) -> Self {
+        Step::text(value.to_string())
+    }
+
+    /// Gzip-compressed JSON in a binary frame (HTX)
+    pub fn gzip(value: Value) -> Self {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(value.to_string().as_bytes()).unwrap();
+        Step::Send(Message::Binary(encoder.finish().unwrap().into()))
+    }
+
+    pub fn ping(payload: &'static [u8]) -> Self {
+        Step::Send(Message::Ping(payload.into()))
+    }
+}
+
+/// What the mock saw from the client
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inbound {
+    /// A WebSocket session started on this path (with query)
+    Connected(String),
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// A REST route was called
+    Rest(String),
+}
+
+impl Inbound {
+    /// Text message parsed as JSON
+    pub fn json(&self) -> Option<Value> {
+        match self {
+            Inbound::Text(text) => serde_json::from_str(text).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A local venue server. Session `n` (0-based) of the client plays `sessions[n]`;
+/// later sessions just read. REST routes answer a fixed body in which `{addr}` is
+/// replaced by the server address.
+pub struct MockVenue {
+    pub addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+    events: mpsc::UnboundedReceiver<Inbound>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Clone)]
+struct VenueState {
+    sessions: Arc<Mutex<Vec<Option<Vec<Step>>>>>,
+    connections: Arc<AtomicUsize>,
+    events: mpsc::UnboundedSender<Inbound>,
+}
+
+impl MockVenue {
+    pub async fn start(sessions: Vec<Vec<Step>>) -> Self {
+        Self::with_rest(sessions, Vec::new()).await
+    }
+
+    pub async fn with_rest(sessions: Vec<Vec<Step>>, rest: Vec<(&str, Value)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let state = VenueState {
+            sessions: Arc::new(Mutex::new(sessions.into_iter().map(Some).collect())),
+            connections: Arc::clone(&connections),
+            events: events_tx,
+        };
+
+        let mut app = Router::new();
+        for (path, body) in rest {
+            let body = body.to_string().replace("{addr}", &addr.to_string());
+            let events = state.events.clone();
+            app = app.route(
+                path,
+                any(move |uri: Uri| async move {
+                    let _ = events.send(Inbound::Rest(uri.to_string()));
+                    ([("content-type", "application/json")], body)
+                }),
+            );
+        }
+        let app = app.fallback(ws_handler).with_state(state);
+
+        let task = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self {
+            addr,
+            connections,
+            events,
+            task,
+        }
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    pub fn http_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// WebSocket sessions opened so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Skip events until `f` accepts one
+    pub async fn expect<T>(&mut self, what: &str, mut f: impl FnMut(&Inbound) -> Option<T>) -> T {
+        let found = timeout(WAIT, async {
+            while let Some(event) = self.events.recv().await {
+                if let Some(found) = f(&event) {
+                    return Some(found);
+                }
+            }
+            None
+        })
+        .await;
+
+        match found {
+            Ok(Some(found)) => found,
+            _ => panic!("mock venue never saw {}", what),
+        }
+    }
+
+    /// Next JSON text message the client sent
+    pub async fn expect_json(&mut self, what: &str) -> Value {
+        self.expect(what, Inbound::json).await
+    }
+}
+
+impl Drop for MockVenue {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    uri: Uri,
+    State(state): State<VenueState>,
+) -> impl IntoResponse {
+    let index = state.connections.fetch_add(1, Ordering::SeqCst);
+    let steps = state
+        .sessions
+        .lock()
+        .unwrap()
+        .get_mut(index)
+        .and_then(Option::take)
+        .unwrap_or_default();
+    let _ = state.events.send(Inbound::Connected(uri.to_string()));
+
+    ws.on_upgrade(move |socket| play(socket, steps, state.events))
+}
+
+async fn play(mut socket: WebSocket, steps: Vec<Step>, events: mpsc::UnboundedSender<Inbound>) {
+    for step in steps {
+        match step {
+            Step::Recv | Step::RecvPong => loop {
+                let Some(Ok(msg)) = socket.recv().await else {
+                    return;
+                };
+                let done = match step {
+                    Step::RecvPong => matches!(msg, Message::Pong(_)),
+                    _ => matches!(msg, Message::Text(_) | Message::Binary(_)),
+                };
+                record(&events, msg);
+                if done {
+                    break;
+                }
+            },
+            Step::Send(msg) => {
+                if socket.send(msg).await.is_err() {
+                    return;
+                }
+            }
+            Step::Close => {
+                let _ = socket.send(Message::Close(None)).await;
+                return;
+            }
+        }
+    }
+
+    // Script done: keep the session open until the client leaves
+    while let Some(Ok(msg)) = socket.recv().await {
+        record(&events, msg);
+    }
+}
+
+fn record(events: &mpsc::UnboundedSender<Inbound>, msg: Message) {
+    let event = match msg {
+        Message::Text(text) => Inbound::Text(text.to_string()),
+        Message::Binary(bin) => Inbound::Binary(bin.to_vec()),
+        Message::Ping(payload) => Inbound::Ping(payload.to_vec()),
+        Message::Pong(payload) => Inbound::Pong(payload.to_vec()),
+        Message::Close(_) => return,
+    };
+    let _ = events.send(event);
+}
+
+// --- Mock Solana RPC ---
+
+/// JSON-RPC server answering `getAccountInfo`, `getMultipleAccounts` and
+/// `getProgramAccounts` from in-memory accounts; unknown accounts answer `null`
+/// like a real node. The same
+/// address serves the pubsub WebSocket: `accountSubscribe` gets a notification for
+/// every later `set_account` / `set_balance` of the account.
+pub struct MockRpc {
+    pub addr: SocketAddr,
+    state: RpcState,
+    task: JoinHandle<()>,
+}
+
+/// A live `accountSubscribe` of a pubsub session
+struct Subscription {
+    account: String,
+    id: u64,
+    session: mpsc::UnboundedSender<Value>,
+}
+
+#[derive(Clone, Default)]
+struct RpcState {
+    accounts: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    owners: Arc<Mutex<HashMap<String, String>>>, // Program of program-owned accounts
+    slot: Arc<AtomicU64>,
+    calls: Arc<Mutex<Vec<String>>>,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    next_subscription: Arc<AtomicU64>,
+    pubsub_disabled: Arc<AtomicBool>,
+}
+
+impl RpcState {
+    fn next_slot(&self) -> u64 {
+        self.slot.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Store account data and notify its subscribers at `slot`
+    fn write_account(&self, pubkey: &str, data: Vec<u8>, slot: u64) {
+        for sub in self.subscriptions.lock().unwrap().iter() {
+            if sub.account == pubkey {
+                let _ = sub.session.send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "accountNotification",
+                    "params": {
+                        "result": { "context": { "slot": slot }, "value": account_json(&data) },
+                        "subscription": sub.id,
+                    },
+                }));
+            }
+        }
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(pubkey.to_string(), data);
+    }
+}
+
+impl MockRpc {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = RpcState::default();
+
+        let app = Router::new()
+            .route("/", post(rpc_handler).get(pubsub_handler))
+            .with_state(state.clone());
+        let task = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { addr, state, task }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    pub fn set_account(&self, pubkey: &str, data: Vec<u8>) {
+        self.state
+            .write_account(pubkey, data, self.state.next_slot());
+    }
+
+    /// Accounts changing together in one slot, like a pool and its vaults in a swap
+    pub fn set_accounts(&self, accounts: &[(&str, Vec<u8>)]) {
+        let slot = self.state.next_slot();
+        for (pubkey, data) in accounts {
+            self.state.write_account(pubkey, data.clone(), slot);
+        }
+    }
+
+    /// Account of `program`, found by `getProgramAccounts`
+    pub fn set_owned_account(&self, pubkey: &str, program: &str, data: Vec<u8>) {
+        self.state
+            .owners
+            .lock()
+            .unwrap()
+            .insert(pubkey.to_string(), program.to_string());
+        self.set_account(pubkey, data);
+    }
+
+    /// Raw token amount of an SPL token account
+    pub fn set_balance(&self, pubkey: &str, amount: u64) {
+        self.set_account(pubkey, Self::token_account(amount));
+    }
+
+    /// SPL token account data: mint, owner, amount, ...
+    pub fn token_account(amount: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 165];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data
+    }
+
+    /// Rewind the node to `slot`, like a load balancer switching to a lagging node
+    pub fn set_slot(&self, slot: u64) {
+        self.state.slot.store(slot, Ordering::SeqCst);
+    }
+
+    /// Refuse pubsub connections, so connectors stay on polling
+    pub fn disable_pubsub(&self) {
+        self.state.pubsub_disabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Live account subscriptions
+    pub fn subscriptions(&self) -> usize {
+        self.state.subscriptions.lock().unwrap().len()
+    }
+
+    /// Methods called so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.state.calls.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockRpc {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn rpc_handler(State(state): State<RpcState>, Json(request): Json<Value>) -> Json<Value> {
+    let method = request["method"].as_str().unwrap_or_default().to_string();
+    let pubkey = request["params"][0]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    state.calls.lock().unwrap().push(method.clone());
+    let context = json!({ "slot": state.next_slot() });
+
+    let result = match method.as_str() {
+        "getAccountInfo" => {
+            let value = state
+                .accounts
+                .lock()
+                .unwrap()
+                .get(&pubkey)
+                .map(|d| account_json(d));
+            json!({ "context": context, "value": value })
+        }
+        "getMultipleAccounts" => {
+            let accounts = state.accounts.lock().unwrap();
+            let value: Vec<Value> = request["params"][0]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|key| {
+                    let data = accounts.get(key.as_str().unwrap_or_default());
+                    data.map_or(Value::Null, |d| account_json(d))
+                })
+                .collect();
+            json!({ "context": context, "value": value })
+        }
+        "getProgramAccounts" => program_accounts(&state, &request["params"]),
+        _ => {
+            return Json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": -32601, "message": "Method not found" },
+            }));
+        }
+    };
+
+    Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+}
+
+/// Accounts of `params[0]` passing every `dataSize` / `memcmp` filter, cut to the
+/// `dataSlice`
+fn program_accounts(state: &RpcState, params: &Value) -> Value {
+    let program = params[0].as_str().unwrap_or_default();
+    let config = &params[1];
+    let owners = state.owners.lock().unwrap();
+    let accounts = state.accounts.lock().unwrap();
+
+    let mut found: Vec<Value> = accounts
+        .iter()
+        .filter(|(pubkey, _)| owners.get(*pubkey).is_some_and(|p| p == program))
+        .filter(|(_, data)| {
+            config["filters"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .all(|filter| passes(filter, data))
+        })
+        .map(|(pubkey, data)| {
+            let data = match config["dataSlice"].as_object() {
+                Some(slice) => {
+                    let offset = slice["offset"].as_u64().unwrap_or(0) as usize;
+                    let length = slice["length"].as_u64().unwrap_or(0) as usize;
+                    let start = offset.min(data.len());
+                    &data[start..(start + length).min(data.len())]
+                }
+                None => data.as_slice(),
+            };
+            json!({ "pubkey": pubkey, "account": account_json(data) })
+        })
+        .collect();
+    found.sort_by_key(|a| a["pubkey"].as_str().unwrap_or_default().to_string());
+    json!(found)
+}
+
+fn passes(filter: &Value, data: &[u8]) -> bool {
+    if let Some(size) = filter["dataSize"].as_u64() {
+        return data.len() as u64 == size;
+    }
+    let memcmp = &filter["memcmp"];
+    let offset = memcmp["offset"].as_u64().unwrap_or(0) as usize;
+    let encoded = memcmp["bytes"].as_str().unwrap_or_default();
+    let bytes = match memcmp["encoding"].as_str() {
+        Some("base64") => BASE64.decode(encoded).unwrap_or_default(),
+        // The filters used here compare pubkeys
+        _ => match Pubkey::from_str(encoded) {
+            Ok(pubkey) => pubkey.to_bytes().to_vec(),
+            Err(_) => return false,
+        },
+    };
+    data.get(offset..offset + bytes.len()) == Some(bytes.as_slice())
+}
+
+fn account_json(data: &[u8]) -> Value {
+    json!({
+        "data": [BASE64.encode(data), "base64"],
+        "executable": false,
+        "lamports": 1_000_000_000u64,
+        "owner": "11111111111111111111111111111111",
+        "rentEpoch": 0,
+        "space": data.len(),
+    })
+}
+
+async fn pubsub_handler(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    if state.pubsub_disabled.load(Ordering::SeqCst) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    ws.on_upgrade(move |socket| pubsub_session(socket, state))
+}
+
+/// Answer `accountSubscribe` / `accountUnsubscribe` and forward notifications
+async fn pubsub_session(mut socket: WebSocket, state: RpcState) {
+    let (tx, mut notifications) = mpsc::unbounded_channel();
+    let mut ids = Vec::new();
+
+    loop {
+        let reply = tokio::select! {
+            msg = socket.recv() => {
+                let Some(Ok(msg)) = msg else { break };
+                let Message::Text(text) = msg else { continue };
+                let Some(reply) = pubsub_reply(&state, &text, &tx, &mut ids) else { continue };
+                reply
+            }
+            Some(notification) = notifications.recv() => notification,
+        };
+        if socket
+            .send(Message::Text(reply.to_string().into()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    state
+        .subscriptions
+        .lock()
+        .unwrap()
+        .retain(|s| !ids.contains(&s.id));
+}
+
+/// Reply to a pubsub request of a session, `None` for anything but (un)subscribing
+fn pubsub_reply(
+    state: &RpcState,
+    text: &str,
+    session: &mpsc::UnboundedSender<Value>,
+    ids: &mut Vec<u64>,
+) -> Option<Value> {
+    let request: Value = serde_json::from_str(text).ok()?;
+    let mut subscriptions = state.subscriptions.lock().unwrap();
+    let result = match request["method"].as_str()? {
+        "accountSubscribe" => {
+            let id = state.next_subscription.fetch_add(1, Ordering::SeqCst);
+            subscriptions.push(Subscription {
+                account: request["params"][0].as_str()?.to_string(),
+                id,
+                session: session.clone(),
+            });
+            ids.push(id);
+            json!(id)
+        }
+        "accountUnsubscribe" => {
+            let id = request["params"][0].as_u64();
+            subscriptions.retain(|s| Some(s.id) != id);
+            json!(true)
+        }
+        _ => return None,
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+}
+
+// --- Connector Harness ---
+
+/// A connector streaming one pair into a local channel, cancelled on drop
+pub struct Harness {
+    pub rx: broadcast::Receiver<PriceUpdate>,
+    pub monitor: Arc<VenueMonitor>,
+    pub books: Arc<BookStore>,
+    pub fees: Arc<FeeModel>,
+    cancel: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl Harness {
+    pub fn start(connector: impl Connector, pair: &str) -> Self {
+        let (tx, rx) = broadcast::channel(100);
+        let cancel = CancellationToken::new();
+        let monitor = Arc::new(VenueMonitor::default());
+        let books = Arc::new(BookStore::default());
+        let fees = Arc::new(FeeModel::default());
+        let assets = Arc::new(AssetRegistry::builtin());
+
+        let task = tokio::spawn(connector.run(ConnectorContext {
+            tx,
+            pair: pair.to_string(),
+            symbol: connector.symbol(&assets, pair).unwrap_or_default(),
+            cancel: cancel.clone(),
+            monitor: Arc::clone(&monitor),
+            books: Arc::clone(&books),
+            fees: Arc::clone(&fees),
+            assets,
+            tape: None,
+        }));
+
+        Self {
+            rx,
+            monitor,
+            books,
+            fees,
+            cancel,
+            task,
+        }
+    }
+
+    pub async fn next_update(&mut self) -> PriceUpdate {
+        timeout(WAIT, self.rx.recv())
+            .await
+            .expect("connector sent no update")
+            .unwrap()
+    }
+
+    /// Status of `venue` once it satisfies `done`
+    pub async fn wait_status(
+        &self,
+        venue: &str,
+        done: impl Fn(&VenueStatus) -> bool,
+    ) -> VenueStatus {
+        timeout(WAIT, async {
+            loop {
+                if let Some(status) = self.monitor.snapshot().remove(venue)
+                    && done(&status)
+                {
+                    return status;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{} status: {:?}", venue, self.monitor.snapshot()))
+    }
+
+    /// Reconnects the supervisor has made for `venue`
+    pub fn reconnects(&self, venue: &str) -> u64 {
+        self.monitor
+            .snapshot()
+            .get(venue)
+            .map_or(0, |status| status.reconnects)
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        self.task.abort();
+    }
+}
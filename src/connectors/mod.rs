@@ -1,3 +1,35 @@
+use crate::assets::AssetRegistry;
+use crate::config::Config;
+use crate::fees::FeeModel;
+use crate::orderbook::{BookStore, OrderBook, PUBLISHED_DEPTH};
+use crate::state::{PriceUpdate, VenueMonitor};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::Sender;
+use tokio_util::sync::CancellationToken;
+
+pub mod registry;
+#[allow(unused_imports)]
+pub use registry::*;
+
+pub mod supervisor;
+#[allow(unused_imports)]
+pub use supervisor::*;
+
+pub mod tape;
+#[allow(unused_imports)]
+pub use tape::*;
+
+pub mod solana;
+#[allow(unused_imports)]
+pub use solana::*;
+
+#[cfg(test)]
+pub mod mock;
+
 pub mod binance;
 #[allow(unused_imports)]
 pub use binance::*;
@@ -10,6 +42,22 @@ pub mod raydium;
 #[allow(unused_imports)]
 pub use raydium::*;
 
+pub mod raydium_amm;
+#[allow(unused_imports)]
+pub use raydium_amm::*;
+
+pub mod raydium_clmm;
+#[allow(unused_imports)]
+pub use raydium_clmm::*;
+
+pub mod raydium_cpmm;
+#[allow(unused_imports)]
+pub use raydium_cpmm::*;
+
+pub mod orca_whirlpool;
+#[allow(unused_imports)]
+pub use orca_whirlpool::*;
+
 pub mod coinbase;
 #[allow(unused_imports)]
 pub use coinbase::*;
@@ -53,3 +101,93 @@ pub use backpack::*;
 pub mod bitstamp;
 #[allow(unused_imports)]
 pub use bitstamp::*;
+
+// --- Connector Abstraction ---
+
+/// Whether a venue is an order-book exchange or an on-chain pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum VenueKind {
+    Cex,
+    Dex,
+}
+
+/// Everything a connector needs to stream one pair
+pub struct ConnectorContext {
+    pub tx: Sender<PriceUpdate>,
+    pub pair: String,
+    /// Venue-native symbol of `pair`, after configured overrides
+    pub symbol: String,
+    pub cancel: CancellationToken,
+    pub monitor: Arc<VenueMonitor>,
+    pub books: Arc<BookStore>,
+    pub fees: Arc<FeeModel>,
+    /// Venue symbols, mints and pools
+    pub assets: Arc<AssetRegistry>,
+    /// Recording or replaying raw venue traffic, if enabled
+    pub tape: Option<Arc<Tape>>,
+}
+
+impl ConnectorContext {
+    /// Drive a connector body until it finishes or the context is cancelled
+    pub fn run<F, Fut>(self, body: F) -> BoxFuture<'static, ()>
+    where
+        F: FnOnce(ConnectorContext) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let cancel = self.cancel.clone();
+        let fut = body(self);
+        Box::pin(async move {
+            cancel.run_until_cancelled(fut).await;
+        })
+    }
+
+    /// Share a venue's local book and send its top of book as the venue quote
+    pub fn publish_book(&self, venue: &str, book: &OrderBook) {
+        if let Some(quote) = book.quote(venue, &self.pair) {
+            let _ = self.tx.send(quote);
+        }
+        self.books
+            .publish(book.snapshot(venue, &self.pair, PUBLISHED_DEPTH));
+    }
+}
+
+/// A price source for canonical pairs such as "SOL/USDC"
+pub trait Connector: Send + Sync {
+    /// Venue name, used as `PriceUpdate::source`
+    fn name(&self) -> &'static str;
+
+    fn kind(&self) -> VenueKind;
+
+    /// Fixed list of pairs the venue can serve, `None` if it lists most pairs
+    fn supported_pairs(&self, _assets: &AssetRegistry) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Venue-native symbol for a canonical pair (e.g. "SOL/USDC" -> "solusdc"),
+    /// from the venue's entry in the asset registry
+    fn symbol(&self, assets: &AssetRegistry, pair: &str) -> Option<String> {
+        assets.venue_symbol(self.name(), pair)
+    }
+
+    /// Apply the configured endpoints / poll interval, before the connector is shared
+    fn configure(&mut self, _config: &Config) {}
+
+    fn supports(&self, assets: &AssetRegistry, pair: &str) -> bool {
+        match self.supported_pairs(assets) {
+            Some(pairs) => pairs.iter().any(|p| p == pair),
+            None => self.symbol(assets, pair).is_some(),
+        }
+    }
+
+    /// Stream `ctx.pair` into `ctx.tx` until `ctx.cancel` fires
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()>;
+}
+
+/// Current Unix time in milliseconds, for venues that don't timestamp their messages
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
@@ -1,103 +1,321 @@
-use crate::state::PriceUpdate;
+use super::{Connector, ConnectorContext, VenueKind};
+use crate::config::Config;
+use crate::orderbook::{OrderBook, Side};
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for fallback timestamp
-use tokio::sync::broadcast::Sender;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::tungstenite::Message;
 
-#[derive(Debug, Deserialize)]
-struct OkxTrade {
-    px: String, // price
-    ts: String, // timestamp
-}
+/// Levels covered by the OKX book checksum
+const CHECKSUM_DEPTH: usize = 25;
 
 #[derive(Debug, Deserialize)]
-struct _OkxData {
-    data: Vec<OkxTrade>,
+#[serde(rename_all = "camelCase")]
+struct OkxBook {
+    asks: Vec<Vec<String>>, // [price, size, deprecated, order count]
+    bids: Vec<Vec<String>>,
+    ts: String, // timestamp
+    checksum: i32,
+    seq_id: i64,
+    prev_seq_id: i64, // -1 on snapshots
 }
 
 #[derive(Debug, Deserialize)]
 struct OkxMsg {
-    _arg: serde_json::Value,
-    data: Option<Vec<OkxTrade>>,
+    action: Option<String>,
+    data: Option<Vec<OkxBook>>,
 }
 
-pub async fn run_okx_connector(tx: Sender<PriceUpdate>, pair: String) {
-    let url = "wss://ws.okx.com:8443/ws/v5/public";
+const OKX_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+
+/// CRC32 over the top 25 levels, bid and ask interleaved: "bidPx:bidSz:askPx:askSz:..."
+fn okx_checksum(book: &OrderBook) -> i32 {
+    let bids: Vec<(&str, &str)> = book.raw_levels(Side::Bid).take(CHECKSUM_DEPTH).collect();
+    let asks: Vec<(&str, &str)> = book.raw_levels(Side::Ask).take(CHECKSUM_DEPTH).collect();
+
+    let mut fields = Vec::with_capacity(CHECKSUM_DEPTH * 4);
+    for i in 0..CHECKSUM_DEPTH {
+        for (price, size) in [bids.get(i), asks.get(i)].into_iter().flatten() {
+            fields.push(*price);
+            fields.push(*size);
+        }
+    }
+
+    crc32fast::hash(fields.join(":").as_bytes()) as i32
+}
 
-    // Store the original requested pair for output
-    let canonical_pair = pair.clone();
+fn apply_levels(book: &mut OrderBook, side: Side, levels: &[Vec<String>]) -> anyhow::Result<()> {
+    for level in levels {
+        if let [price, size, ..] = level.as_slice() {
+            book.apply(side, price, size)?;
+        }
+    }
+    Ok(())
+}
 
+async fn run_okx_connector(ctx: ConnectorContext, url: String) {
     // 1. Derive OKX Instrument ID (e.g., "BTC/USDC" -> "BTC-USDC")
     // OKX uses BASE-QUOTE format
-    let inst_id = pair.replace("/", "-");
-
-    println!(
-        "OKX connecting to pair: {} (InstID: {})",
-        canonical_pair, inst_id
-    );
-
-    match connect_async(url).await {
-        Ok((mut ws_stream, _)) => {
-            println!("OKX Connected");
-
-            // 2. USE THE PAIR: Subscribe using the dynamic InstID
-            let subscribe_msg = serde_json::json!({
-                "op": "subscribe",
-                "args": [{
-                    "channel": "trades",
-                    "instId": inst_id
-                }]
-            });
-
-            // Send subscription message
-            if ws_stream
-                .send(Message::Text(subscribe_msg.to_string().into()))
-                .await
-                .is_err()
-            {
-                eprintln!(
-                    "OKX Failed to send subscribe message for {}",
-                    canonical_pair
+    let inst_id = ctx.symbol.clone();
+
+    ctx.supervise("OKX", || okx_session(&ctx, &url, &inst_id))
+        .await;
+}
+
+async fn okx_session(ctx: &ConnectorContext, url: &str, inst_id: &str) -> anyhow::Result<()> {
+    println!("OKX connecting to pair: {} (InstID: {})", ctx.pair, inst_id);
+
+    let mut ws_stream = ctx.connect_ws("OKX", url).await?;
+    println!("OKX Connected");
+
+    // 2. USE THE PAIR: Subscribe using the dynamic InstID
+    let subscribe_msg = serde_json::json!({
+        "op": "subscribe",
+        "args": [{
+            "channel": "books",
+            "instId": inst_id
+        }]
+    });
+
+    ws_stream
+        .send(Message::Text(subscribe_msg.to_string().into()))
+        .await?;
+
+    println!("OKX Subscribed to {} books", ctx.pair);
+    ctx.monitor.connected("OKX");
+
+    let mut book = OrderBook::new();
+
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg?;
+        if !msg.is_text() {
+            continue;
+        }
+
+        let Ok(parsed) = serde_json::from_str::<OkxMsg>(msg.to_text()?) else {
+            continue;
+        };
+        let (Some(action), Some(data)) = (parsed.action, parsed.data) else {
+            continue;
+        };
+
+        for update in data {
+            if action == "snapshot" {
+                book.clear();
+            } else if book.sequence != Some(update.prev_seq_id as u64) {
+                anyhow::bail!(
+                    "book sequence gap: expected prevSeqId {:?}, got {}. Resyncing",
+                    book.sequence,
+                    update.prev_seq_id
                 );
-                return;
             }
 
-            println!("OKX Subscribed to {} trades", canonical_pair);
-
-            while let Some(msg) = ws_stream.next().await {
-                if let Ok(msg) = msg {
-                    if !msg.is_text() {
-                        continue;
-                    }
-
-                    if let Ok(parsed) = serde_json::from_str::<OkxMsg>(msg.to_text().unwrap()) {
-                        if let Some(data) = parsed.data {
-                            for t in data {
-                                if let Ok(price) = t.px.parse::<f64>() {
-                                    // Parse OKX timestamp (string ms) or fallback to system time
-                                    let timestamp = t.ts.parse::<u64>().unwrap_or_else(|_| {
-                                        SystemTime::now()
-                                            .duration_since(UNIX_EPOCH)
-                                            .unwrap()
-                                            .as_millis()
-                                            as u64
-                                    });
-
-                                    let _ = tx.send(PriceUpdate {
-                                        source: "OKX".to_string(),
-                                        // 3. Use the original requested pair for output
-                                        pair: canonical_pair.clone(),
-                                        price,
-                                        timestamp, // Added timestamp field
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
+            apply_levels(&mut book, Side::Bid, &update.bids)?;
+            apply_levels(&mut book, Side::Ask, &update.asks)?;
+            book.sequence = Some(update.seq_id as u64);
+            book.timestamp = update.ts.parse()?;
+
+            if okx_checksum(&book) != update.checksum {
+                anyhow::bail!("book checksum mismatch. Resyncing");
             }
+
+            // 3. Use the original requested pair for output
+            ctx.publish_book("OKX", &book);
         }
-        Err(e) => eprintln!("OKX Connection error for {}: {:?}", canonical_pair, e),
+    }
+
+    Ok(())
+}
+
+/// Public `books` channel with sequence and checksum validation
+pub struct OkxConnector {
+    pub url: String,
+}
+
+impl Default for OkxConnector {
+    fn default() -> Self {
+        Self {
+            url: OKX_WS_URL.to_string(),
+        }
+    }
+}
+
+impl Connector for OkxConnector {
+    fn name(&self) -> &'static str {
+        "OKX"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Cex
+    }
+
+    fn configure(&mut self, config: &Config) {
+        if let Some(url) = config.venue(self.name()).url {
+            self.url = url;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let url = self.url.clone();
+        ctx.run(move |ctx| run_okx_connector(ctx, url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, Inbound, MockVenue, Step};
+    use serde_json::json;
+
+    /// A books message carrying the checksum the venue would send
+    fn books(
+        action: &str,
+        bids: &[(&str, &str)],
+        asks: &[(&str, &str)],
+        (seq, prev): (i64, i64),
+        checksum: i32,
+    ) -> Step {
+        let levels = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(p, s)| json!([p, s, "0", "1"]))
+                .collect::<Vec<_>>()
+        };
+        Step::json(json!({
+            "arg": { "channel": "books", "instId": "SOL-USDT" },
+            "action": action,
+            "data": [{
+                "asks": levels(asks),
+                "bids": levels(bids),
+                "ts": (1_700_000_000_000 + seq).to_string(),
+                "checksum": checksum,
+                "seqId": seq,
+                "prevSeqId": prev,
+            }],
+        }))
+    }
+
+    #[test]
+    fn checksum_matches_the_documented_vectors() {
+        // Check strings from the OKX docs: "3366.1:7:3366.8:9:3366:6:3368:8" and,
+        // with fewer bids than asks, "3366.1:7:3366.8:9:3368:8:3372:8"
+        let mut book = OrderBook::new();
+        book.apply(Side::Bid, "3366.1", "7").unwrap();
+        book.apply(Side::Bid, "3366", "6").unwrap();
+        book.apply(Side::Ask, "3366.8", "9").unwrap();
+        book.apply(Side::Ask, "3368", "8").unwrap();
+        assert_eq!(okx_checksum(&book), -1881014294);
+
+        book.apply(Side::Bid, "3366", "0").unwrap();
+        book.apply(Side::Ask, "3372", "8").unwrap();
+        assert_eq!(okx_checksum(&book), 831078360);
+    }
+
+    #[tokio::test]
+    async fn streams_the_book_and_resubscribes_after_a_server_close() {
+        let first = vec![
+            Step::Recv,
+            Step::json(
+                json!({ "event": "subscribe", "arg": { "channel": "books", "instId": "SOL-USDT" } }),
+            ),
+            books(
+                "snapshot",
+                &[("150.1", "2")],
+                &[("150.2", "3")],
+                (10, -1),
+                -8044064,
+            ),
+            Step::ping(b"okx"),
+            Step::RecvPong,
+            books("update", &[("150.15", "1")], &[], (11, 10), -1014096173),
+            Step::Close,
+        ];
+        let second = vec![
+            Step::Recv,
+            books(
+                "snapshot",
+                &[("150.3", "1")],
+                &[("150.4", "1")],
+                (20, -1),
+                1961351546,
+            ),
+        ];
+        let mut venue = MockVenue::start(vec![first, second]).await;
+        let mut harness = Harness::start(
+            OkxConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
+        );
+
+        let subscribe = venue.expect_json("subscribe").await;
+        assert_eq!(subscribe["op"], "subscribe");
+        assert_eq!(
+            subscribe["args"],
+            json!([{ "channel": "books", "instId": "SOL-USDT" }])
+        );
+
+        let snapshot = harness.next_update().await;
+        assert_eq!((snapshot.bid, snapshot.ask), (150.1, 150.2));
+        assert_eq!(snapshot.timestamp, 1_700_000_000_010);
+
+        venue
+            .expect("pong", |e| {
+                (e == &Inbound::Pong(b"okx".to_vec())).then_some(())
+            })
+            .await;
+        let update = harness.next_update().await;
+        assert_eq!((update.bid, update.ask), (150.15, 150.2));
+
+        venue.expect_json("resubscribe").await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.3, 150.4));
+        assert_eq!(venue.connections(), 2);
+        assert_eq!(harness.reconnects("OKX"), 1);
+    }
+
+    #[tokio::test]
+    async fn resyncs_on_a_prev_seq_id_gap() {
+        let mut venue = MockVenue::start(vec![
+            vec![
+                Step::Recv,
+                books(
+                    "snapshot",
+                    &[("150.1", "2")],
+                    &[("150.2", "3")],
+                    (10, -1),
+                    -8044064,
+                ),
+                // seqId 11 never arrived
+                books("update", &[("150.15", "1")], &[], (12, 11), -1014096173),
+            ],
+            vec![
+                Step::Recv,
+                books(
+                    "snapshot",
+                    &[("150.3", "1")],
+                    &[("150.4", "1")],
+                    (20, -1),
+                    1961351546,
+                ),
+            ],
+        ])
+        .await;
+        let mut harness = Harness::start(
+            OkxConnector {
+                url: venue.ws_url(),
+            },
+            "SOL/USDT",
+        );
+
+        venue.expect_json("subscribe").await;
+        let snapshot = harness.next_update().await;
+        assert_eq!((snapshot.bid, snapshot.ask), (150.1, 150.2));
+
+        // The gapped update is dropped with the book and a fresh subscribe resyncs it
+        venue.expect_json("resubscribe").await;
+        let resynced = harness.next_update().await;
+        assert_eq!((resynced.bid, resynced.ask), (150.3, 150.4));
+        assert_eq!(harness.reconnects("OKX"), 1);
     }
 }
@@ -1,105 +1,568 @@
+use super::{
+    AccountUpdate, Connector, ConnectorContext, POLLING_FALLBACK, RESUBSCRIBE_AFTER, SlotClock,
+    SwapDirection, TickArray, VenueKind, Whirlpool, WhirlpoolQuoter, now_ms, pubsub_url,
+    solana_endpoints, tick_array_address,
+};
+use crate::assets::AssetRegistry;
+use crate::config::Config;
+use crate::orderbook::BookSnapshot;
 use crate::state::PriceUpdate;
-use std::convert::TryInto;
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::broadcast::Sender;
+use tokio::time::{Duration, Instant, sleep};
 
 // --- Constant Definitions (Standardized) ---
-const SOL_DECIMALS: i32 = 9;
-const USDC_DECIMALS: i32 = 6;
-const SQRT_PRICE_OFFSET: usize = 65;
+const SOLANA_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+/// Default period between two polls
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Tick arrays are read again after this long, liquidity moves when positions change
+const TICK_ARRAY_MAX_AGE: Duration = Duration::from_secs(30);
 
 // --- Mapping Structure ---
 struct PairConfig {
-    whirlpool_address: &'static str,
     base_decimals: i32,
+    quote_decimals: i32,
+    quote: String, // Quote token of the whirlpool, what its price is in
 }
 
-/// Helper function to map the canonical pair to its Orca configuration.
-fn get_orca_config(pair: &str) -> Option<PairConfig> {
-    match pair {
-        "SOL/USDC" => Some(PairConfig {
-            whirlpool_address: "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE",
-            base_decimals: SOL_DECIMALS, // 9
-        }),
-        "ETH/USDC" => Some(PairConfig {
-            whirlpool_address: "AU971DrPyhhrpRnmEBp5pDTWL2ny7nofb5vYBjDJkR2E",
-            base_decimals: 8, // Given 8 decimals for ETH
-        }),
-        "BTC/USDC" => Some(PairConfig {
-            whirlpool_address: "55BrDTCLWayM16GwrMEQU57o4PTm6ceF9wavSdNZcEiy",
-            base_decimals: 8, // Given 8 decimals for BTC
-        }),
-        _ => None,
+/// Token decimals of the whirlpool serving the canonical pair, from the asset registry
+fn get_orca_config(assets: &AssetRegistry, pair: &str) -> anyhow::Result<PairConfig> {
+    let pool = assets
+        .pool("Orca", pair)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported pair: {} for Orca connector", pair))?;
+    if pool.address.is_none() {
+        anyhow::bail!("Orca pool for {} has no whirlpool address", pair);
     }
+    let decimals = |code: &str| {
+        assets
+            .decimals(code)
+            .map(i32::from)
+            .ok_or_else(|| anyhow::anyhow!("No decimals known for {}", code))
+    };
+
+    Ok(PairConfig {
+        base_decimals: decimals(&pool.base)?,
+        quote_decimals: decimals(&pool.quote)?,
+        quote: pool.quote.clone(),
+    })
 }
 
-pub async fn run_orca_connector(tx: Sender<PriceUpdate>, pair: String) {
-    if let Err(e) = async {
-        let config = get_orca_config(&pair)
-            .ok_or_else(|| anyhow::anyhow!("Unsupported pair: {} for Orca connector", pair))?;
+/// Tick arrays around the whirlpool's current tick, read when the price moves into
+/// another array or the last read got old
+struct TickArrays {
+    whirlpool: Pubkey,
+    starts: Vec<i32>,
+    arrays: Vec<Option<TickArray>>, // At `starts`, `None` where no array exists
+    loaded: Option<Instant>,
+}
 
-        let rpc_url = "https://api.mainnet-beta.solana.com";
-        println!("ORCA Connecting to Solana RPC for {}: {rpc_url}", pair);
+impl TickArrays {
+    fn new(whirlpool: Pubkey) -> Self {
+        Self {
+            whirlpool,
+            starts: Vec::new(),
+            arrays: Vec::new(),
+            loaded: None,
+        }
+    }
 
-        let rpc_client =
-            solana_client::nonblocking::rpc_client::RpcClient::new(rpc_url.to_string());
+    /// Read the arrays around `pool`'s tick unless the ones held are still current.
+    /// On errors the old arrays are kept, and the curve falls back to the active
+    /// liquidity once they no longer cover the price.
+    async fn refresh(&mut self, ctx: &ConnectorContext, rpc: &RpcClient, pool: &Whirlpool) {
+        let starts = pool.tick_array_starts();
+        let fresh = self
+            .loaded
+            .is_some_and(|loaded| loaded.elapsed() < TICK_ARRAY_MAX_AGE);
+        if starts.is_empty() || (fresh && starts == self.starts) {
+            return;
+        }
 
-        let pool_pubkey = solana_sdk::pubkey::Pubkey::from_str(config.whirlpool_address)?;
+        let addresses: Vec<Pubkey> = starts
+            .iter()
+            .map(|start| tick_array_address(&self.whirlpool, *start))
+            .collect();
+        let read = ctx
+            .fetch_optional_accounts("Orca", "tick_arrays", rpc, &addresses)
+            .await;
+        let arrays = read.and_then(|accounts| {
+            accounts
+                .iter()
+                .map(|data| data.as_deref().map(TickArray::decode).transpose())
+                .collect::<anyhow::Result<Vec<_>>>()
+        });
+        match arrays {
+            Ok(arrays) => {
+                self.starts = starts;
+                self.arrays = arrays;
+                self.loaded = Some(Instant::now());
+            }
+            Err(err) => println!("ORCA Error reading tick arrays for {}: {:?}", ctx.pair, err),
+        }
+    }
 
-        let canonical_pair = pair.clone();
+    /// Swap simulator for `pool`, if the arrays held cover its current tick
+    fn quoter(&self, pool: &Whirlpool) -> Option<WhirlpoolQuoter> {
+        if self.starts != pool.tick_array_starts() {
+            return None;
+        }
+        WhirlpoolQuoter::new(pool, &self.arrays)
+    }
+}
 
-        loop {
-            match rpc_client.get_account_data(&pool_pubkey).await {
-                Ok(data_bytes) => {
-                    if data_bytes.len() < SQRT_PRICE_OFFSET + 16 {
-                        println!("ORCA Account data too short for {}", canonical_pair);
-                        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-                        continue;
-                    }
+/// Price a whirlpool account read at `slot` and publish it, unless a later slot was
+/// priced already. `false` once nobody listens.
+fn publish_whirlpool(
+    ctx: &ConnectorContext,
+    config: &PairConfig,
+    clock: &mut SlotClock,
+    ticks: &TickArrays,
+    pool: &Whirlpool,
+    slot: u64,
+) -> anyhow::Result<bool> {
+    let canonical_pair = &ctx.pair;
+    if clock.is_stale(slot) {
+        println!(
+            "ORCA Dropping {} state of slot {}, already at slot {:?}",
+            canonical_pair,
+            slot,
+            clock.last()
+        );
+        return Ok(true);
+    }
+
+    let sqrt_price = pool.sqrt_price();
+    let price_native = sqrt_price.powi(2);
+
+    let decimal_adjustment = 10f64.powi(config.base_decimals - config.quote_decimals);
+    let final_price = price_native * decimal_adjustment;
+
+    // Generate System Timestamp, the account carries none
+    let timestamp = now_ms();
+
+    ctx.fees
+        .set_pool_fee("Orca", canonical_pair, pool.fee_bps());
+
+    // Active liquidity behaves like a constant-product pool with virtual
+    // reserves x = L / sqrt(P), y = L * sqrt(P) until the next tick is crossed
+    let liquidity = pool.liquidity as f64;
+    if liquidity > 0.0 && sqrt_price > 0.0 {
+        let base_unit = 10f64.powi(config.base_decimals);
+        let quote_unit = 10f64.powi(config.quote_decimals);
+        let base_reserve = liquidity / sqrt_price / base_unit;
+        let quote_reserve = liquidity * sqrt_price / quote_unit;
+        let book = match ticks.quoter(pool) {
+            // Walk the ticks, fees are charged by the engine (token A is the base)
+            Some(quoter) => {
+                let quoter = quoter.without_fee();
+                let swap = |amount: f64, direction, unit_in: f64, unit_out: f64| {
+                    let quote = quoter.quote(amount * unit_in, direction);
+                    (quote.amount_in / unit_in, quote.amount_out / unit_out)
+                };
+                BookSnapshot::from_swaps(
+                    "Orca",
+                    canonical_pair,
+                    (base_reserve, quote_reserve),
+                    |base| swap(base, SwapDirection::AToB, base_unit, quote_unit),
+                    |quote| swap(quote, SwapDirection::BToA, quote_unit, base_unit),
+                    timestamp,
+                )
+            }
+            None => BookSnapshot::constant_product(
+                "Orca",
+                canonical_pair,
+                base_reserve,
+                quote_reserve,
+                timestamp,
+            ),
+        };
+        ctx.books.publish(book);
+    }
+
+    // Pool spot price (bid == ask), size lives in the curve book
+    // Priced in the pool's quote token whatever quote was requested
+    let update = PriceUpdate::single("Orca", canonical_pair, final_price, timestamp)
+        .in_native_quote(&config.quote)
+        .at_slot(Some(slot));
 
-                    let sqrt_price_bytes: &[u8] =
-                        &data_bytes[SQRT_PRICE_OFFSET..SQRT_PRICE_OFFSET + 16];
-                    let sqrt_price_u128 = u128::from_le_bytes(sqrt_price_bytes.try_into()?);
+    clock.admit(slot);
+    Ok(ctx.tx.send(update).is_ok())
+}
+
+/// Decode a whirlpool account, bring its tick arrays up to date and publish it
+async fn update_whirlpool(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    config: &PairConfig,
+    clock: &mut SlotClock,
+    ticks: &mut TickArrays,
+    data: &[u8],
+    slot: u64,
+) -> anyhow::Result<bool> {
+    let pool = Whirlpool::decode(data)
+        .map_err(|e| anyhow::anyhow!("Bad whirlpool account for {}: {}", ctx.pair, e))?;
+    if !clock.is_stale(slot) {
+        ticks.refresh(ctx, rpc, &pool).await;
+    }
+    publish_whirlpool(ctx, config, clock, ticks, &pool, slot)
+}
+
+/// One RPC read of the whirlpool, `false` once nobody listens
+async fn poll_whirlpool(
+    ctx: &ConnectorContext,
+    rpc_client: &RpcClient,
+    config: &PairConfig,
+    clock: &mut SlotClock,
+    ticks: &mut TickArrays,
+) -> anyhow::Result<bool> {
+    let pool_pubkey = ticks.whirlpool;
+    let account = ctx
+        .fetch_accounts("Orca", "pool_account", rpc_client, &[pool_pubkey])
+        .await;
+    let read = account?;
+    update_whirlpool(
+        ctx,
+        rpc_client,
+        config,
+        clock,
+        ticks,
+        &read.data[0],
+        read.slot,
+    )
+    .await
+}
 
-                    let price_x64 = sqrt_price_u128 as f64;
-                    let two_pow_64 = (1u128 << 64) as f64;
+/// A failed read leaves the venue down until prices flow again
+fn report_poll_error(ctx: &ConnectorContext, err: anyhow::Error) {
+    println!(
+        "ORCA Error fetching account data for {}: {:?}",
+        ctx.pair, err
+    );
+    ctx.monitor.disconnected("Orca", Some(format!("{:#}", err)));
+}
 
-                    let price_native = (price_x64 / two_pow_64).powi(2);
+/// Publish whirlpool notifications. `false` once nobody listens, `true` when the
+/// subscription ended.
+async fn stream_whirlpool(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    config: &PairConfig,
+    clock: &mut SlotClock,
+    ticks: &mut TickArrays,
+    mut updates: BoxStream<'static, AccountUpdate>,
+) -> bool {
+    while let Some(update) = updates.next().await {
+        let published =
+            update_whirlpool(ctx, rpc, config, clock, ticks, &update.data, update.slot).await;
+        match published {
+            Ok(true) => ctx.monitor.connected("Orca"),
+            Ok(false) => return false,
+            Err(err) => println!("ORCA Bad account update for {}: {:?}", ctx.pair, err),
+        }
+    }
+    true
+}
 
-                    let decimal_adjustment = 10f64.powi(config.base_decimals - USDC_DECIMALS);
-                    let final_price = price_native * decimal_adjustment;
+async fn run_orca_connector(
+    ctx: ConnectorContext,
+    rpc_url: String,
+    ws_url: String,
+    poll_interval: Duration,
+) {
+    let ConnectorContext {
+        pair,
+        symbol,
+        assets,
+        ..
+    } = &ctx;
+    let result: anyhow::Result<()> = async {
+        let config = get_orca_config(assets, pair)?;
 
-                    // Generate System Timestamp for RPC polling
-                    let timestamp = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64;
+        println!("ORCA Connecting to Solana RPC for {}: {rpc_url}", pair);
 
-                    let update = PriceUpdate {
-                        source: "Orca".into(),
-                        pair: canonical_pair.clone(),
-                        price: final_price,
-                        timestamp, // Added timestamp field
-                    };
+        let rpc = RpcClient::new(rpc_url.clone());
 
-                    let _ = tx.send(update);
+        let pool_pubkey = Pubkey::from_str(symbol)?;
+        let mut clock = SlotClock::default();
+        let mut ticks = TickArrays::new(pool_pubkey);
+
+        loop {
+            // Whirlpool changes are pushed, the RPC is only read to seed the price
+            match ctx
+                .subscribe_accounts("Orca", &ws_url, &[pool_pubkey])
+                .await
+            {
+                Ok(updates) => {
+                    println!("ORCA Subscribed to whirlpool {} for {}", pool_pubkey, pair);
+                    ctx.monitor.connected("Orca");
+                    let seeded =
+                        match poll_whirlpool(&ctx, &rpc, &config, &mut clock, &mut ticks).await {
+                            Ok(open) => open,
+                            Err(err) => {
+                                report_poll_error(&ctx, err);
+                                true
+                            }
+                        };
+                    let open = seeded
+                        && stream_whirlpool(&ctx, &rpc, &config, &mut clock, &mut ticks, updates)
+                            .await;
+                    if !open {
+                        break;
+                    }
+                    println!("ORCA Subscription for {} ended, polling", pair);
                 }
                 Err(err) => {
-                    println!(
-                        "ORCA Error fetching account data for {}: {:?}",
-                        canonical_pair, err
-                    );
+                    println!("ORCA Subscription failed for {}: {:#}. Polling", pair, err);
                 }
             }
+            ctx.monitor.degraded("Orca", POLLING_FALLBACK);
 
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            // Fallback: poll until it is time to subscribe again
+            let resubscribe = Instant::now() + RESUBSCRIBE_AFTER;
+            while Instant::now() < resubscribe {
+                match poll_whirlpool(&ctx, &rpc, &config, &mut clock, &mut ticks).await {
+                    Ok(true) => ctx.monitor.degraded("Orca", POLLING_FALLBACK),
+                    Ok(false) => {
+                        println!("ORCA No subscribers left for {}. Stopping", pair);
+                        return Ok(());
+                    }
+                    Err(err) => report_poll_error(&ctx, err),
+                }
+                sleep(poll_interval).await;
+            }
         }
-
-        Ok::<(), anyhow::Error>(())
+        println!("ORCA No subscribers left for {}. Stopping", pair);
+        Ok(())
     }
-    .await
-    {
+    .await;
+
+    if let Err(e) = result {
         eprintln!("Orca connector error for {}: {:?}", pair, e);
+        ctx.monitor.disconnected("Orca", Some(format!("{:#}", e)));
+    }
+}
+
+/// Streams the whirlpool account over Solana pubsub, polling JSON-RPC while that is down
+pub struct OrcaConnector {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub poll_interval: Duration,
+}
+
+impl Default for OrcaConnector {
+    fn default() -> Self {
+        Self {
+            rpc_url: SOLANA_RPC_URL.to_string(),
+            ws_url: pubsub_url(SOLANA_RPC_URL),
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+}
+
+impl Connector for OrcaConnector {
+    fn name(&self) -> &'static str {
+        "Orca"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Dex
+    }
+
+    fn supported_pairs(&self, assets: &AssetRegistry) -> Option<Vec<String>> {
+        Some(assets.pool_pairs(self.name()))
+    }
+
+    /// Whirlpool address
+    fn symbol(&self, assets: &AssetRegistry, pair: &str) -> Option<String> {
+        assets.pool(self.name(), pair)?.address.clone()
+    }
+
+    fn configure(&mut self, config: &Config) {
+        let venue = config.venue(self.name());
+        (self.rpc_url, self.ws_url) = solana_endpoints(config, self.name());
+        if let Some(interval) = venue.poll_interval() {
+            self.poll_interval = interval;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let (rpc_url, ws_url) = (self.rpc_url.clone(), self.ws_url.clone());
+        let poll_interval = self.poll_interval;
+        ctx.run(move |ctx| run_orca_connector(ctx, rpc_url, ws_url, poll_interval))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, MockRpc};
+    use crate::connectors::{TICK_ARRAY_SIZE, Tick};
+    use crate::orderbook::Side;
+
+    const SOL_DECIMALS: i32 = 9;
+    const USDC_DECIMALS: i32 = 6;
+    const SOL_USDC_POOL: &str = "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE";
+
+    /// Whirlpool state at a SOL/USDC price, without tick spacing unless set after
+    fn pool(price: f64, fee_rate: u16, liquidity: u128) -> Whirlpool {
+        let native = price / 10f64.powi(SOL_DECIMALS - USDC_DECIMALS);
+        Whirlpool {
+            fee_rate,
+            liquidity,
+            sqrt_price: (native.sqrt() * (1u128 << 64) as f64) as u128,
+            tick_current_index: (native.ln() / 1.0001f64.ln()).floor() as i32,
+            ..Default::default()
+        }
+    }
+
+    fn account(pool: &Whirlpool) -> Vec<u8> {
+        let mut data = borsh::to_vec(pool).unwrap();
+        data.resize(653, 0);
+        data
+    }
+
+    /// Whirlpool account bytes with the fields the connector reads
+    fn whirlpool(price: f64, fee_rate: u16, liquidity: u128) -> Vec<u8> {
+        account(&pool(price, fee_rate, liquidity))
+    }
+
+    #[tokio::test]
+    async fn prices_the_whirlpool_and_keeps_polling_through_rpc_errors() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+        let mut harness = Harness::start(
+            OrcaConnector {
+                rpc_url: rpc.url(),
+                ws_url: rpc.ws_url(),
+                ..Default::default()
+            },
+            "SOL/USDC",
+        );
+
+        // Unknown account: the poll fails, the venue is down, and the poll is retried
+        let status = harness
+            .wait_status("Orca", |s| s.last_error.is_some())
+            .await;
+        assert!(!status.connected);
+        assert!(status.last_error.unwrap().contains("not found"));
+        rpc.set_account(SOL_USDC_POOL, whirlpool(150.0, 400, 10_000_000_000_000));
+
+        let update = harness.next_update().await;
+        assert!((update.price - 150.0).abs() < 1e-6, "{}", update.price);
+        assert_eq!(update.native_quote, "USDC");
+        assert!(update.slot.is_some());
+        // fee_rate is in hundredths of a bps
+        assert_eq!(harness.fees.taker_bps("Orca", "SOL/USDC"), 4.0);
+        assert!(harness.books.get("Orca", "SOL/USDC").is_some());
+
+        rpc.set_account(SOL_USDC_POOL, whirlpool(151.5, 400, 10_000_000_000_000));
+        let mut update = harness.next_update().await;
+        while (update.price - 150.0).abs() < 1e-6 {
+            update = harness.next_update().await;
+        }
+        assert!((update.price - 151.5).abs() < 1e-6, "{}", update.price);
+
+        // Prices flow, but from polling
+        let status = harness.wait_status("Orca", |s| s.degraded).await;
+        assert!(status.connected);
+        assert_eq!(status.last_error.as_deref(), Some(POLLING_FALLBACK));
+    }
+
+    #[tokio::test]
+    async fn streams_whirlpool_notifications_with_their_slot() {
+        let rpc = MockRpc::start().await;
+        rpc.set_account(SOL_USDC_POOL, whirlpool(150.0, 400, 10_000_000_000_000));
+        let mut harness = Harness::start(
+            OrcaConnector {
+                rpc_url: rpc.url(),
+                ws_url: rpc.ws_url(),
+                ..Default::default()
+            },
+            "SOL/USDC",
+        );
+
+        // Seeded from one RPC read once subscribed
+        let update = harness.next_update().await;
+        assert!((update.price - 150.0).abs() < 1e-6, "{}", update.price);
+        assert_eq!(update.slot, Some(2));
+        assert_eq!(rpc.subscriptions(), 1);
+        let status = harness.wait_status("Orca", |s| s.connected).await;
+        assert!(!status.degraded);
+
+        rpc.set_account(SOL_USDC_POOL, whirlpool(151.5, 400, 10_000_000_000_000));
+        let update = harness.next_update().await;
+        assert!((update.price - 151.5).abs() < 1e-6, "{}", update.price);
+        assert_eq!(update.slot, Some(3));
+        assert_eq!(rpc.calls(), ["getMultipleAccounts"]);
+    }
+
+    #[tokio::test]
+    async fn walks_the_tick_arrays_around_the_price() {
+        const LIQUIDITY: u128 = 10_000_000_000_000;
+        let rpc = MockRpc::start().await;
+        let mut whirlpool = pool(150.0, 400, LIQUIDITY);
+        whirlpool.tick_spacing = 64;
+
+        // All liquidity sits above a tick five spacings under the price
+        let span = TICK_ARRAY_SIZE * 64;
+        let start = whirlpool.tick_current_index.div_euclid(span) * span;
+        let lower = (whirlpool.tick_current_index.div_euclid(64) - 5) * 64;
+        assert!(lower >= start);
+        let mut ticks: [Tick; TICK_ARRAY_SIZE as usize] = std::array::from_fn(|_| Tick::default());
+        ticks[((lower - start) / 64) as usize] = Tick {
+            initialized: true,
+            liquidity_net: LIQUIDITY as i128,
+            ..Default::default()
+        };
+        let array = TickArray {
+            discriminator: [0; 8],
+            start_tick_index: start,
+            ticks,
+            whirlpool: [0; 32],
+        };
+        let pool_pubkey = Pubkey::from_str(SOL_USDC_POOL).unwrap();
+        let array_address = tick_array_address(&pool_pubkey, start).to_string();
+        rpc.set_accounts(&[
+            (SOL_USDC_POOL, account(&whirlpool)),
+            (&array_address, borsh::to_vec(&array).unwrap()),
+        ]);
+
+        let mut harness = Harness::start(
+            OrcaConnector {
+                rpc_url: rpc.url(),
+                ws_url: rpc.ws_url(),
+                ..Default::default()
+            },
+            "SOL/USDC",
+        );
+        let update = harness.next_update().await;
+        assert!((update.price - 150.0).abs() < 1e-6, "{}", update.price);
+        assert_eq!(rpc.calls(), ["getMultipleAccounts", "getMultipleAccounts"]);
+
+        // Selling SOL empties the range at the lower tick, far short of the
+        // 10% of virtual reserves a single-range curve would take
+        let book = harness.books.get("Orca", "SOL/USDC").unwrap();
+        let sold: f64 = book.levels(Side::Bid).iter().map(|(_, size)| size).sum();
+        let lower_sqrt = 1.0001f64.powf(lower as f64 / 2.0);
+        let expected = LIQUIDITY as f64 * (1.0 / lower_sqrt - 1.0 / whirlpool.sqrt_price()) / 1e9;
+        assert!(
+            (sold - expected).abs() / expected < 1e-6,
+            "{} vs {}",
+            sold,
+            expected
+        );
+        let reserve = LIQUIDITY as f64 / whirlpool.sqrt_price() / 1e9;
+        assert!(sold < reserve * 0.05);
+
+        // Buying it keeps going, the price rising from the pool price
+        let (best_ask, _) = book.levels(Side::Ask)[0];
+        let (best_bid, _) = book.levels(Side::Bid)[0];
+        assert!(best_bid < 150.0 && 150.0 < best_ask);
+        assert!(best_ask - best_bid < 0.01);
+        assert!(book.levels(Side::Ask).len() > book.levels(Side::Bid).len());
     }
 }
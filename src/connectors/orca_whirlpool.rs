@@ -0,0 +1,339 @@
+//! Orca Whirlpool state, its tick arrays and concentrated-liquidity swap quotes
+
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+pub const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+pub const TICK_ARRAY_SIZE: i32 = 88;
+/// Arrays loaded on each side of the one holding the current tick. A swap crosses at
+/// most two more arrays, so this covers any single swap.
+pub const TICK_ARRAYS_AROUND: i32 = 2;
+
+/// Whirlpool account up to the current tick; fee growth and rewards follow. Unused
+/// fields are decoded to reach the ones after them.
+#[allow(dead_code)]
+#[derive(Debug, Clone, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct Whirlpool {
+    pub discriminator: [u8; 8],
+    pub whirlpools_config: [u8; 32],
+    pub whirlpool_bump: [u8; 1],
+    pub tick_spacing: u16,
+    pub fee_tier_index_seed: [u8; 2],
+    pub fee_rate: u16, // Hundredths of a basis point (3000 = 0.3%)
+    pub protocol_fee_rate: u16,
+    pub liquidity: u128,  // Active liquidity at the current tick
+    pub sqrt_price: u128, // Q64.64
+    pub tick_current_index: i32,
+}
+
+impl Whirlpool {
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::deserialize(&mut data)?)
+    }
+
+    pub fn fee_bps(&self) -> f64 {
+        self.fee_rate as f64 / 100.0
+    }
+
+    /// Square root of the raw price (token B units per token A unit)
+    pub fn sqrt_price(&self) -> f64 {
+        self.sqrt_price as f64 / (1u128 << 64) as f64
+    }
+
+    /// Start ticks of the current tick array and the `TICK_ARRAYS_AROUND` on each side,
+    /// ascending. Empty for a pool without tick spacing.
+    pub fn tick_array_starts(&self) -> Vec<i32> {
+        let span = TICK_ARRAY_SIZE * self.tick_spacing as i32;
+        if span == 0 {
+            return Vec::new();
+        }
+        let current = self.tick_current_index.div_euclid(span) * span;
+        (-TICK_ARRAYS_AROUND..=TICK_ARRAYS_AROUND)
+            .map(|offset| current + offset * span)
+            .collect()
+    }
+}
+
+/// Address of the whirlpool's tick array starting at `start_tick`
+pub fn tick_array_address(whirlpool: &Pubkey, start_tick: i32) -> Pubkey {
+    let program = Pubkey::from_str(WHIRLPOOL_PROGRAM_ID).expect("valid program id");
+    let start = start_tick.to_string();
+    let seeds: [&[u8]; 3] = [b"tick_array", whirlpool.as_ref(), start.as_bytes()];
+    Pubkey::find_program_address(&seeds, &program).0
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct Tick {
+    pub initialized: bool,
+    pub liquidity_net: i128, // Added crossing the tick upwards, removed downwards
+    pub liquidity_gross: u128,
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+    pub reward_growths_outside: [u128; 3],
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+pub struct TickArray {
+    pub discriminator: [u8; 8],
+    pub start_tick_index: i32,
+    pub ticks: [Tick; TICK_ARRAY_SIZE as usize],
+    pub whirlpool: [u8; 32],
+}
+
+impl TickArray {
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::deserialize(&mut data)?)
+    }
+
+    /// (tick index, liquidity net) of the initialized ticks
+    pub fn initialized(&self, tick_spacing: u16) -> impl Iterator<Item = (i32, i128)> + '_ {
+        (0..).zip(&self.ticks).filter_map(move |(i, tick)| {
+            let index = self.start_tick_index + i * tick_spacing as i32;
+            tick.initialized.then_some((index, tick.liquidity_net))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapDirection {
+    AToB, // Sell token A, the price goes down
+    BToA, // Sell token B, the price goes up
+}
+
+/// Outcome of an exact-input swap, in raw token units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapQuote {
+    pub amount_in: f64, // Used of the amount offered, fee included
+    pub amount_out: f64,
+    pub fee: f64,
+}
+
+/// Simulates swaps through a whirlpool's liquidity, tick by tick, over the range its
+/// loaded tick arrays cover. Past that range the swap stops, as the real one would
+/// without the arrays.
+#[derive(Debug, Clone)]
+pub struct WhirlpoolQuoter {
+    sqrt_price: f64,
+    tick_current: i32,
+    liquidity: f64,
+    fee_rate: f64,          // Share of the input
+    ticks: Vec<(i32, f64)>, // Initialized ticks and their liquidity net, ascending
+    range: (f64, f64),      // Lowest and highest sqrt price covered
+}
+
+impl WhirlpoolQuoter {
+    /// `arrays` are the accounts at `pool.tick_array_starts()`, `None` for arrays that
+    /// don't exist (no initialized ticks there). `None` for a pool without tick spacing.
+    pub fn new(pool: &Whirlpool, arrays: &[Option<TickArray>]) -> Option<Self> {
+        let starts = pool.tick_array_starts();
+        let (first, last) = (*starts.first()?, *starts.last()?);
+        let span = TICK_ARRAY_SIZE * pool.tick_spacing as i32;
+
+        let mut ticks: Vec<(i32, f64)> = arrays
+            .iter()
+            .flatten()
+            .flat_map(|array| array.initialized(pool.tick_spacing))
+            .map(|(index, net)| (index, net as f64))
+            .collect();
+        ticks.sort_by_key(|(index, _)| *index);
+
+        Some(Self {
+            sqrt_price: pool.sqrt_price(),
+            tick_current: pool.tick_current_index,
+            liquidity: pool.liquidity as f64,
+            fee_rate: pool.fee_rate as f64 / 1_000_000.0,
+            ticks,
+            range: (sqrt_price_at(first), sqrt_price_at(last + span)),
+        })
+    }
+
+    /// The same pool without swap fees, for curves the fee is charged on separately
+    pub fn without_fee(&self) -> Self {
+        Self {
+            fee_rate: 0.0,
+            ..self.clone()
+        }
+    }
+
+    /// Swap `amount_in` raw units of the input token
+    pub fn quote(&self, amount_in: f64, direction: SwapDirection) -> SwapQuote {
+        let offered = amount_in * (1.0 - self.fee_rate);
+        let mut remaining = offered;
+        let mut amount_out = 0.0;
+        let mut sqrt_price = self.sqrt_price;
+        let mut liquidity = self.liquidity;
+
+        // Initialized ticks in the swap's direction, then the end of the loaded range
+        let crossings: Box<dyn Iterator<Item = (f64, f64)>> = match direction {
+            SwapDirection::AToB => Box::new(
+                self.ticks
+                    .iter()
+                    .rev()
+                    .filter(|(index, _)| *index <= self.tick_current)
+                    .map(|&(index, net)| (sqrt_price_at(index), -net))
+                    .chain(std::iter::once((self.range.0, 0.0))),
+            ),
+            SwapDirection::BToA => Box::new(
+                self.ticks
+                    .iter()
+                    .filter(|(index, _)| *index > self.tick_current)
+                    .map(|&(index, net)| (sqrt_price_at(index), net))
+                    .chain(std::iter::once((self.range.1, 0.0))),
+            ),
+        };
+
+        for (target, liquidity_change) in crossings {
+            if remaining <= 0.0 {
+                break;
+            }
+            if liquidity > 0.0 {
+                let (needed, out) = match direction {
+                    SwapDirection::AToB => (
+                        liquidity * (1.0 / target - 1.0 / sqrt_price),
+                        liquidity * (sqrt_price - target),
+                    ),
+                    SwapDirection::BToA => (
+                        liquidity * (target - sqrt_price),
+                        liquidity * (1.0 / sqrt_price - 1.0 / target),
+                    ),
+                };
+                if remaining < needed {
+                    // The swap ends inside this range
+                    let end = match direction {
+                        SwapDirection::AToB => {
+                            liquidity * sqrt_price / (liquidity + remaining * sqrt_price)
+                        }
+                        SwapDirection::BToA => sqrt_price + remaining / liquidity,
+                    };
+                    amount_out += match direction {
+                        SwapDirection::AToB => liquidity * (sqrt_price - end),
+                        SwapDirection::BToA => liquidity * (1.0 / sqrt_price - 1.0 / end),
+                    };
+                    remaining = 0.0;
+                    break;
+                }
+                remaining -= needed.max(0.0);
+                amount_out += out.max(0.0);
+            }
+            sqrt_price = target;
+            liquidity = (liquidity + liquidity_change).max(0.0);
+        }
+
+        let used = offered - remaining;
+        let amount_in = if self.fee_rate < 1.0 {
+            used / (1.0 - self.fee_rate)
+        } else {
+            0.0
+        };
+        SwapQuote {
+            amount_in,
+            amount_out,
+            fee: amount_in - used,
+        }
+    }
+}
+
+fn sqrt_price_at(tick: i32) -> f64 {
+    1.0001f64.powf(tick as f64 / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_array(start_tick_index: i32, initialized: &[(usize, i128)]) -> TickArray {
+        let mut ticks: [Tick; TICK_ARRAY_SIZE as usize] = std::array::from_fn(|_| Tick::default());
+        for &(offset, liquidity_net) in initialized {
+            ticks[offset] = Tick {
+                initialized: true,
+                liquidity_net,
+                ..Default::default()
+            };
+        }
+        TickArray {
+            discriminator: [0; 8],
+            start_tick_index,
+            ticks,
+            whirlpool: [0; 32],
+        }
+    }
+
+    #[test]
+    fn layout_matches_the_program() {
+        let pool = Whirlpool {
+            tick_spacing: 64,
+            fee_rate: 3000,
+            liquidity: 7,
+            sqrt_price: 1 << 64,
+            tick_current_index: -1,
+            ..Default::default()
+        };
+        let data = borsh::to_vec(&pool).unwrap();
+        assert_eq!(data.len(), 85);
+        assert_eq!(data[41..43], 64u16.to_le_bytes());
+        assert_eq!(data[45..47], 3000u16.to_le_bytes());
+        assert_eq!(data[49], 7);
+        assert_eq!(data[65 + 8], 1);
+
+        // Tick -1 is in the array starting at -5632, floor division
+        let starts = pool.tick_array_starts();
+        assert_eq!(starts, [-16896, -11264, -5632, 0, 5632]);
+
+        let array = borsh::to_vec(&tick_array(0, &[(1, -5)])).unwrap();
+        assert_eq!(array.len(), 9988);
+        assert_eq!(array[12 + 113], 1);
+        let decoded = TickArray::decode(&array).unwrap();
+        assert_eq!(decoded.initialized(64).collect::<Vec<_>>(), [(64, -5)]);
+    }
+
+    #[test]
+    fn quotes_cross_initialized_ticks() {
+        // Price 1 with liquidity 1e9 between ticks -640 and 640, and none beyond
+        let pool = Whirlpool {
+            tick_spacing: 64,
+            fee_rate: 3000,
+            liquidity: 1_000_000_000,
+            sqrt_price: 1 << 64,
+            ..Default::default()
+        };
+        let arrays: Vec<Option<TickArray>> = pool
+            .tick_array_starts()
+            .into_iter()
+            .map(|start| match start {
+                -5632 => Some(tick_array(start, &[(78, 1_000_000_000)])),
+                0 => Some(tick_array(start, &[(10, -1_000_000_000)])),
+                _ => None,
+            })
+            .collect();
+        let quoter = WhirlpoolQuoter::new(&pool, &arrays).unwrap();
+
+        // A small swap pays the fee and moves along the curve: x * y = L^2
+        let quote = quoter.quote(1_000.0, SwapDirection::BToA);
+        assert!((quote.fee - 3.0).abs() < 1e-9);
+        let expected = 1e9 - 1e18 / (1e9 + 997.0);
+        assert!((quote.amount_out - expected).abs() < 1e-6, "{:?}", quote);
+
+        // Selling everything stops at tick -640, where the liquidity ends
+        let quote = quoter.quote(1e12, SwapDirection::AToB);
+        let floor = sqrt_price_at(-640);
+        assert!(
+            (quote.amount_out - 1e9 * (1.0 - floor)).abs() < 1e-3,
+            "{:?}",
+            quote
+        );
+        let used = 1e9 * (1.0 / floor - 1.0) / 0.997;
+        assert!((quote.amount_in - used).abs() < 1e-3, "{:?}", quote);
+
+        // Without the fee the whole input moves the price
+        let quote = quoter.without_fee().quote(5_000.0, SwapDirection::AToB);
+        assert_eq!(quote.fee, 0.0);
+        let expected = 1e9 * 5_000.0 / (1e9 + 5_000.0);
+        assert!((quote.amount_out - expected).abs() < 1e-6, "{:?}", quote);
+    }
+}
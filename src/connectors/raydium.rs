@@ -1,114 +1,1173 @@
 use std::str::FromStr;
 
-use crate::state::PriceUpdate;
+use super::{
+    AMM_COIN_MINT_OFFSET, AMM_INFO_SIZE, AMM_PC_MINT_OFFSET, AMM_V4_PROGRAM_ID, AccountUpdate,
+    AmmInfo, CLMM_MINT_0_OFFSET, CLMM_MINT_1_OFFSET, CLMM_POOL_SIZE, CLMM_PROGRAM_ID, COMMITMENT,
+    CPMM_MINT_0_OFFSET, CPMM_MINT_1_OFFSET, CPMM_POOL_SIZE, CPMM_PROGRAM_ID, ClmmConfig, ClmmPool,
+    Connector, ConnectorContext, CpmmConfig, CpmmPool, OpenOrders, POLLING_FALLBACK,
+    RESUBSCRIBE_AFTER, SlotAccounts, SlotClock, VenueKind, now_ms, pubsub_url, solana_endpoints,
+};
+use crate::assets::AssetRegistry;
+use crate::config::Config;
+use crate::orderbook::BookSnapshot;
+use crate::state::{PoolPrice, PriceUpdate};
 use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
 use solana_sdk::pubkey::Pubkey;
-use std::time::{SystemTime, UNIX_EPOCH}; // Added for timestamp generation
-use tokio::sync::broadcast::Sender;
-use tokio::time::{Duration, sleep};
-
-const USDC_DECIMALS: u32 = 6;
-const SOL_DECIMALS: u32 = 9;
-const ETH_DECIMALS: u32 = 7; // Assuming 8 decimals for ETH as per previous context
-const BTC_DECIMALS: u32 = 8; // Assuming 8 decimals for BTC as per previous context
-
-struct VaultConfig {
-    token_a_vault: &'static str,
-    token_b_vault: &'static str, // Always USDC vault in this context
-    token_a_decimals: u32,
-    token_b_decimals: u32, // Always USDC_DECIMALS
-}
-
-fn get_vault_config(pair: &str) -> Result<VaultConfig> {
-    match pair {
-        "SOL/USDC" => Ok(VaultConfig {
-            token_a_vault: "DQyrAcCrDXQ7NeoqGgDCZwBvWDcYmFCjSb9JtteuvPpz", // SOL_VAULT
-            token_b_vault: "HLmqeL62xR1QoZ1HKKbXRrdN1p3phKpxRMb2VVopvBBz", // USDC_VAULT (for SOL)
-            token_a_decimals: SOL_DECIMALS,
-            token_b_decimals: USDC_DECIMALS,
-        }),
-        "BTC/USDC" => Ok(VaultConfig {
-            token_a_vault: "HWTaEDR6BpWjmyeUyfGZjeppLnH7s8o225Saar7FYDt5", // BTC vault
-            token_b_vault: "7iGcnvoLAxthsXY3AFSgkTDoqnLiuti5fyPNm2VwZ3Wz", // USDC vault (for BTC)
-            token_a_decimals: BTC_DECIMALS,
-            token_b_decimals: USDC_DECIMALS,
-        }),
-        "ETH/USDC" => Ok(VaultConfig {
-            token_a_vault: "EHT99uYfAnVxWHPLUMJRTyhD4AyQZDDknKMEssHDtor5", // ETH vault
-            token_b_vault: "58tgdkogRoMsrXZJubnFPsFmNp5mpByEmE1fF6FTNvDL", // USDC vault (for ETH)
-            token_a_decimals: ETH_DECIMALS,
-            token_b_decimals: USDC_DECIMALS,
-        }),
-        _ => Err(anyhow!("Unsupported pair for Raydium connector: {}", pair)),
-    }
-}
-
-async fn fetch_raydium_price(rpc: &RpcClient, config: &VaultConfig) -> Result<f64> {
-    // Note: Token A is the base (e.g., SOL, BTC, ETH), Token B is the quote (USDC).
-    let token_a_vault = Pubkey::from_str(config.token_a_vault)?;
-    let token_b_vault = Pubkey::from_str(config.token_b_vault)?;
-
-    let token_a_acc = rpc.get_token_account_balance(&token_a_vault).await?;
-    let token_b_acc = rpc.get_token_account_balance(&token_b_vault).await?;
-
-    let token_a_raw = token_a_acc.amount.parse::<f64>()?;
-    let token_b_raw = token_b_acc.amount.parse::<f64>()?;
-
-    // Apply decimal correction
-    let token_a = token_a_raw / 10f64.powi(config.token_a_decimals as i32);
-    let token_b = token_b_raw / 10f64.powi(config.token_b_decimals as i32); // USDC decimals
-
-    if token_a == 0.0 {
-        anyhow::bail!("Base token reserve ({}) is zero", token_a_vault.to_string());
-    }
-
-    // Price = Quote Reserve / Base Reserve (USDC / SOL or USDC / BTC)
-    let price = token_b / token_a;
-    Ok(price)
-}
-
-pub async fn run_raydium_connector(tx: Sender<PriceUpdate>, pair: String) {
+use tokio::time::{Duration, Instant, sleep, sleep_until};
+
+const SOLANA_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+/// Default period between two polls
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// SPL token account layout: mint, owner, then the raw amount
+const TOKEN_AMOUNT_OFFSET: usize = 64;
+/// Longest an update waits for the rest of its swap's updates of the same slot
+const PAIR_WAIT: Duration = Duration::from_millis(400);
+/// Pause before looking for the pair's pools again when none could be read
+const RESOLVE_RETRY: Duration = Duration::from_secs(5);
+/// Pools with less quote liquidity than this share of the deepest pool are dust, not priced on
+const MIN_LIQUIDITY_SHARE: f64 = 0.01;
+/// Pools further than this from the median pool price are not priced on
+const MAX_PRICE_DEVIATION: f64 = 0.1;
+
+/// Mints of the canonical pair, from the asset registry
+struct PairConfig {
+    base_mint: Pubkey,
+    quote_mint: Pubkey,
+    quote: String,           // What the pool prices are in
+    pool_id: Option<Pubkey>, // Pinned pool, every pool of the mints when unset
+}
+
+/// Mints of the pool serving the canonical pair. `symbol` is the pool id, empty when
+/// the registry leaves the pair to discovery.
+fn get_pair_config(assets: &AssetRegistry, pair: &str, symbol: &str) -> Result<PairConfig> {
+    let pool = assets
+        .pool("Raydium", pair)
+        .ok_or_else(|| anyhow!("Unsupported pair for Raydium connector: {}", pair))?;
+    let mint = |code: &str| -> Result<Pubkey> {
+        let mint = assets
+            .mint(code)
+            .ok_or_else(|| anyhow!("No mint known for {}", code))?;
+        Ok(Pubkey::from_str(mint)?)
+    };
+
+    Ok(PairConfig {
+        base_mint: mint(&pool.base)?,
+        quote_mint: mint(&pool.quote)?,
+        quote: pool.quote.clone(),
+        pool_id: match symbol {
+            "" => None,
+            id => Some(Pubkey::from_str(id).map_err(|e| anyhow!("Pool id {}: {}", id, e))?),
+        },
+    })
+}
+
+/// Raydium pool programs
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PoolKind {
+    AmmV4,
+    Cpmm,
+    Clmm,
+}
+
+impl PoolKind {
+    const ALL: [Self; 3] = [Self::AmmV4, Self::Cpmm, Self::Clmm];
+
+    /// Program, pool account size and offsets of the two mints
+    fn layout(self) -> (&'static str, usize, [usize; 2]) {
+        match self {
+            Self::AmmV4 => (
+                AMM_V4_PROGRAM_ID,
+                AMM_INFO_SIZE,
+                [AMM_COIN_MINT_OFFSET, AMM_PC_MINT_OFFSET],
+            ),
+            Self::Cpmm => (
+                CPMM_PROGRAM_ID,
+                CPMM_POOL_SIZE,
+                [CPMM_MINT_0_OFFSET, CPMM_MINT_1_OFFSET],
+            ),
+            Self::Clmm => (
+                CLMM_PROGRAM_ID,
+                CLMM_POOL_SIZE,
+                [CLMM_MINT_0_OFFSET, CLMM_MINT_1_OFFSET],
+            ),
+        }
+    }
+
+    /// Kind of a pool account, told apart by size
+    fn of(data: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.layout().1 == data.len())
+    }
+}
+
+/// A pool of the pair and the accounts its price is read from
+struct RaydiumPool {
+    kind: PoolKind,
+    accounts: Vec<Pubkey>, // Pool account first, see `RaydiumPool::new`
+    inverted: bool,        // Token 0 is the quote token, e.g. a USDC/BTC pool for BTC/USDC
+}
+
+/// Whole-token reserves of a pool at its current price, and its swap fee
+struct PoolReserves {
+    base: f64,
+    quote: f64,
+    price: f64,
+    fee_bps: Option<f64>,
+}
+
+impl PoolReserves {
+    fn constant_product(base: f64, quote: f64, fee_bps: Option<f64>) -> Self {
+        Self {
+            base,
+            quote,
+            price: quote / base,
+            fee_bps,
+        }
+    }
+
+    /// The same reserves with base and quote swapped
+    fn inverted(self) -> Self {
+        Self {
+            base: self.quote,
+            quote: self.base,
+            price: 1.0 / self.price,
+            fee_bps: self.fee_bps,
+        }
+    }
+}
+
+impl RaydiumPool {
+    /// Pool at `address` from its account data, `None` while it can't swap. Prices
+    /// come from, after the pool account:
+    /// - AMM v4: coin vault, pc vault, open orders
+    /// - CP-Swap: token 0 vault, token 1 vault, fee config
+    /// - CLMM: fee config (the pool account holds the price)
+    fn new(address: Pubkey, data: &[u8], config: &PairConfig) -> Result<Option<Self>> {
+        let kind = PoolKind::of(data)
+            .ok_or_else(|| anyhow!("Account {} is not a Raydium pool", address))?;
+        let (mints, swappable, accounts) = match kind {
+            PoolKind::AmmV4 => {
+                let amm = AmmInfo::decode(data)?;
+                let [coin_vault, pc_vault, open_orders] = amm.reserve_accounts();
+                let accounts = vec![address, coin_vault, pc_vault, open_orders];
+                (
+                    [amm.coin_mint(), amm.pc_mint()],
+                    amm.is_swappable(),
+                    accounts,
+                )
+            }
+            PoolKind::Cpmm => {
+                let pool = CpmmPool::decode(data)?;
+                let [vault_0, vault_1] = pool.vaults();
+                let accounts = vec![address, vault_0, vault_1, pool.amm_config()];
+                (pool.mints(), pool.is_swappable(), accounts)
+            }
+            PoolKind::Clmm => {
+                let pool = ClmmPool::decode(data)?;
+                let accounts = vec![address, pool.amm_config()];
+                (pool.mints(), pool.is_swappable(), accounts)
+            }
+        };
+        let inverted = match mints {
+            mints if mints == [config.base_mint, config.quote_mint] => false,
+            mints if mints == [config.quote_mint, config.base_mint] => true,
+            [mint_0, mint_1] => {
+                anyhow::bail!(
+                    "Pool {} trades {}/{}, not the pair",
+                    address,
+                    mint_0,
+                    mint_1
+                )
+            }
+        };
+
+        Ok(swappable.then_some(Self {
+            kind,
+            accounts,
+            inverted,
+        }))
+    }
+
+    fn address(&self) -> Pubkey {
+        self.accounts[0]
+    }
+
+    /// Accounts a swap changes together, as indices into `accounts`
+    fn swap_accounts(&self) -> &'static [usize] {
+        match self.kind {
+            PoolKind::Clmm => &[0],
+            PoolKind::AmmV4 | PoolKind::Cpmm => &[1, 2],
+        }
+    }
+
+    /// Reserves from the data of `accounts`
+    fn reserves(&self, data: &[&[u8]]) -> Result<PoolReserves> {
+        let reserves = match self.kind {
+            PoolKind::AmmV4 => amm_v4_reserves(data)?,
+            PoolKind::Cpmm => cpmm_reserves(data)?,
+            PoolKind::Clmm => clmm_reserves(data)?,
+        };
+        Ok(if self.inverted {
+            reserves.inverted()
+        } else {
+            reserves
+        })
+    }
+}
+
+/// Effective reserves the AMM swaps against, at the decimals the pool records
+fn amm_v4_reserves(data: &[&[u8]]) -> Result<PoolReserves> {
+    let &[amm, coin_vault, pc_vault, open_orders] = data else {
+        anyhow::bail!("AMM v4 pool read without its reserve accounts");
+    };
+    let amm = AmmInfo::decode(amm)?;
+    let open_orders = OpenOrders::decode(open_orders)?;
+    let (coin, pc) = amm.effective_reserves(
+        vault_amount(coin_vault)?,
+        vault_amount(pc_vault)?,
+        &open_orders,
+    );
+    Ok(PoolReserves::constant_product(
+        whole(coin, amm.coin_decimals),
+        whole(pc, amm.pc_decimals),
+        amm.swap_fee_bps(),
+    ))
+}
+
+/// Vault balances net of the fees they hold for the protocol, fund and creator
+fn cpmm_reserves(data: &[&[u8]]) -> Result<PoolReserves> {
+    let &[pool, vault_0, vault_1, config] = data else {
+        anyhow::bail!("CP-Swap pool read without its vaults and config");
+    };
+    let pool = CpmmPool::decode(pool)?;
+    let (token_0, token_1) = pool.reserves(vault_amount(vault_0)?, vault_amount(vault_1)?);
+    Ok(PoolReserves::constant_product(
+        whole(token_0, pool.mint_0_decimals.into()),
+        whole(token_1, pool.mint_1_decimals.into()),
+        Some(CpmmConfig::decode(config)?.fee_bps()),
+    ))
+}
+
+/// Price from the square root price, reserves of the active liquidity
+fn clmm_reserves(data: &[&[u8]]) -> Result<PoolReserves> {
+    let &[pool, config] = data else {
+        anyhow::bail!("CLMM pool read without its config");
+    };
+    let pool = ClmmPool::decode(pool)?;
+    let (token_0, token_1) = pool.virtual_reserves();
+    Ok(PoolReserves {
+        base: token_0,
+        quote: token_1,
+        price: pool.price(),
+        fee_bps: Some(ClmmConfig::decode(config)?.fee_bps()),
+    })
+}
+
+/// Pools the pair is priced on: no dust next to the others and none far from the
+/// median price, so a tiny or skewed pool can't move the price
+fn priced_pools(mut pools: Vec<PoolReserves>) -> Vec<PoolReserves> {
+    let deepest = pools.iter().map(|p| p.quote).fold(0.0, f64::max);
+    pools.retain(|p| p.quote >= MIN_LIQUIDITY_SHARE * deepest);
+
+    // Median by quote liquidity: the price half of the liquidity sits at or below
+    pools.sort_by(|a, b| a.price.total_cmp(&b.price));
+    let total: f64 = pools.iter().map(|p| p.quote).sum();
+    let mut below = 0.0;
+    let Some(median) = pools.iter().find_map(|p| {
+        below += p.quote;
+        (below >= total / 2.0).then_some(p.price)
+    }) else {
+        return pools;
+    };
+
+    pools.retain(|p| (p.price / median - 1.0).abs() <= MAX_PRICE_DEVIATION);
+    pools
+}
+
+/// Price and fee of `pools` weighted by their quote liquidity
+fn weighted(pools: &[PoolReserves]) -> (f64, Option<f64>) {
+    let liquidity: f64 = pools.iter().map(|p| p.quote).sum();
+    let price = pools.iter().map(|p| p.price * p.quote).sum::<f64>() / liquidity;
+
+    let with_fee = pools.iter().filter_map(|p| Some((p.fee_bps?, p.quote)));
+    let (fee_sum, fee_weight) = with_fee.fold((0.0, 0.0), |(sum, weight), (fee, quote)| {
+        (sum + fee * quote, weight + quote)
+    });
+    (price, (fee_weight > 0.0).then(|| fee_sum / fee_weight))
+}
+
+fn vault_amount(data: &[u8]) -> Result<u64> {
+    token_amount(data).ok_or_else(|| anyhow!("Pool vault is not a token account"))
+}
+
+fn whole(amount: u64, decimals: u64) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// Every pool pricing the pair, read and subscribed to as one account list so a
+/// price never mixes slots
+struct PoolSet {
+    pools: Vec<RaydiumPool>,
+    accounts: Vec<Pubkey>, // Accounts of every pool, in pool order
+    quote: String,         // What the pool prices are in
+}
+
+impl PoolSet {
+    fn new(pools: Vec<RaydiumPool>, quote: &str) -> Self {
+        let accounts = pools
+            .iter()
+            .flat_map(|pool| pool.accounts.iter().copied())
+            .collect();
+        Self {
+            pools,
+            accounts,
+            quote: quote.to_string(),
+        }
+    }
+
+    /// Pool owning account `index`, and the index of the pool's first account
+    fn locate(&self, index: usize) -> (&RaydiumPool, usize) {
+        let mut start = 0;
+        for pool in &self.pools {
+            if index < start + pool.accounts.len() {
+                return (pool, start);
+            }
+            start += pool.accounts.len();
+        }
+        panic!("account {} of {} pool accounts", index, self.accounts.len());
+    }
+
+    /// Per-account `items` split per pool
+    fn split<'a, T>(&'a self, items: &'a [T]) -> impl Iterator<Item = (&'a RaydiumPool, &'a [T])> {
+        let mut rest = items;
+        self.pools.iter().map(move |pool| {
+            let (own, tail) = rest.split_at(pool.accounts.len().min(rest.len()));
+            rest = tail;
+            (pool, own)
+        })
+    }
+}
+
+/// Query finding the pools of `kind` with these mints, in this order. Only the
+/// addresses are wanted, the pools are read at a pinned commitment afterwards.
+fn discovery_query(kind: PoolKind, mints: [Pubkey; 2]) -> RpcProgramAccountsConfig {
+    let (_, size, offsets) = kind.layout();
+    let mut filters = vec![RpcFilterType::DataSize(size as u64)];
+    for (offset, mint) in offsets.into_iter().zip(mints) {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            offset,
+            mint.as_ref(),
+        )));
+    }
+    RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            data_slice: Some(UiDataSliceConfig {
+                offset: 0,
+                length: 0,
+            }),
+            commitment: Some(COMMITMENT),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Pools of the pair's mints, in either order, across the AMM v4, CP-Swap and CLMM
+/// programs
+async fn discover_pools(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    config: &PairConfig,
+) -> Result<Vec<Pubkey>> {
+    let found: Vec<String> = ctx
+        .fetch("Raydium", "pool_discovery", async {
+            let mut found = Vec::new();
+            for kind in PoolKind::ALL {
+                let program = Pubkey::from_str(kind.layout().0)?;
+                for mints in [
+                    [config.base_mint, config.quote_mint],
+                    [config.quote_mint, config.base_mint],
+                ] {
+                    let accounts = rpc
+                        .get_program_ui_accounts_with_config(&program, discovery_query(kind, mints))
+                        .await?;
+                    found.extend(accounts.into_iter().map(|(pubkey, _)| pubkey.to_string()));
+                }
+            }
+            Ok(found)
+        })
+        .await?;
+
+    found
+        .iter()
+        .map(|pubkey| Ok(Pubkey::from_str(pubkey)?))
+        .collect()
+}
+
+/// The pinned pool, or every swappable pool of the pair
+async fn resolve_pools(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    config: &PairConfig,
+) -> Result<PoolSet> {
+    let candidates = match config.pool_id {
+        Some(pool_id) => vec![pool_id],
+        None => discover_pools(ctx, rpc, config).await?,
+    };
+    if candidates.is_empty() {
+        anyhow::bail!("No Raydium pool trades {}", ctx.pair);
+    }
+
+    let read = ctx
+        .fetch_accounts("Raydium", "pool_states", rpc, &candidates)
+        .await?;
+    let mut pools = Vec::new();
+    for (address, data) in candidates.into_iter().zip(&read.data) {
+        match RaydiumPool::new(address, data, config)? {
+            Some(pool) => pools.push(pool),
+            None => println!(
+                "RAYDIUM Skipping pool {} for {}, swaps disabled",
+                address, ctx.pair
+            ),
+        }
+    }
+    if pools.is_empty() {
+        anyhow::bail!("No swappable Raydium pool trades {}", ctx.pair);
+    }
+    Ok(PoolSet::new(pools, &config.quote))
+}
+
+/// Price the pools from their accounts read at `slot` and publish it, unless a later
+/// slot was priced already. Constant-product pools count by their base reserves, which
+/// makes the price the mid of their combined curve. CLMM reserves only hold until the
+/// next tick, so CLMM pools are reported in `pools` but left out of the price, fee and
+/// book, unless no other pool has liquidity. `false` once nobody listens.
+fn publish_pools(
+    ctx: &ConnectorContext,
+    set: &PoolSet,
+    clock: &mut SlotClock,
+    data: &[&[u8]],
+    slot: u64,
+) -> bool {
+    if clock.is_stale(slot) {
+        println!(
+            "RAYDIUM Dropping {} reserves of slot {}, already at slot {:?}",
+            ctx.pair,
+            slot,
+            clock.last()
+        );
+        return true;
+    }
+
+    let mut curve = Vec::new();
+    let mut clmm = Vec::new();
+    let mut pools = Vec::new();
+    for (pool, data) in set.split(data) {
+        let reserves = match pool.reserves(data) {
+            Ok(reserves) => reserves,
+            Err(err) => {
+                println!(
+                    "RAYDIUM Bad state of pool {} for {}: {:#}",
+                    pool.address(),
+                    ctx.pair,
+                    err
+                );
+                continue;
+            }
+        };
+        // No liquidity at the current price
+        if reserves.base <= 0.0 || reserves.quote <= 0.0 {
+            continue;
+        }
+        pools.push(PoolPrice {
+            address: pool.address().to_string(),
+            price: reserves.price,
+            liquidity: reserves.quote,
+        });
+        match pool.kind {
+            PoolKind::Clmm => clmm.push(reserves),
+            PoolKind::AmmV4 | PoolKind::Cpmm => curve.push(reserves),
+        }
+    }
+    if pools.is_empty() {
+        println!("RAYDIUM No pool of {} has liquidity", ctx.pair);
+        return true;
+    }
+    clock.admit(slot);
+
+    // Generate timestamp, pool accounts carry none
+    let timestamp = now_ms();
+
+    let priced = if curve.is_empty() {
+        // Only CLMM pools: their spot price holds, their depth isn't known
+        priced_pools(clmm)
+    } else {
+        // Depth comes from each pool's constant-product curve, walked in price order
+        let curve = priced_pools(curve);
+        let books = curve.iter().map(|p| {
+            BookSnapshot::constant_product("Raydium", &ctx.pair, p.base, p.quote, timestamp)
+        });
+        let book = BookSnapshot::merged("Raydium", &ctx.pair, books, timestamp);
+        ctx.books.publish(book);
+        curve
+    };
+    let (price, fee_bps) = weighted(&priced);
+
+    // The fee schedule default applies when no pool has one
+    if let Some(fee_bps) = fee_bps {
+        ctx.fees.set_pool_fee("Raydium", &ctx.pair, fee_bps);
+    }
+
+    // Pools' spot price (bid == ask), size lives in the curve book. Every pool with
+    // liquidity is listed, including those left out of the price.
+    // Priced in the pools' quote token whatever quote was requested
+    let update = PriceUpdate::single("Raydium", &ctx.pair, price, timestamp)
+        .in_native_quote(&set.quote)
+        .at_slot(Some(slot))
+        .with_pools(pools);
+    ctx.tx.send(update).is_ok()
+}
+
+/// Accounts of every pool in one call, so the reserves never mix two slots
+async fn read_pools(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    set: &PoolSet,
+) -> Result<SlotAccounts> {
+    ctx.fetch_accounts("Raydium", "pool_accounts", rpc, &set.accounts)
+        .await
+}
+
+/// Price a `read_pools` result, `false` once nobody listens
+fn publish_read(
+    ctx: &ConnectorContext,
+    set: &PoolSet,
+    clock: &mut SlotClock,
+    read: &SlotAccounts,
+) -> bool {
+    let data: Vec<&[u8]> = read.data.iter().map(Vec::as_slice).collect();
+    publish_pools(ctx, set, clock, &data, read.slot)
+}
+
+/// One RPC read of the pools, `false` once nobody listens
+async fn poll_pools(
+    ctx: &ConnectorContext,
+    rpc: &RpcClient,
+    set: &PoolSet,
+    clock: &mut SlotClock,
+) -> Result<bool> {
+    let read = read_pools(ctx, rpc, set).await?;
+    Ok(publish_read(ctx, set, clock, &read))
+}
+
+/// A failed read leaves the venue down until prices flow again
+fn report_read_error(ctx: &ConnectorContext, err: anyhow::Error) {
+    println!("RAYDIUM Error fetching {}: {:?}", ctx.pair, err);
+    ctx.monitor
+        .disconnected("Raydium", Some(format!("{:#}", err)));
+}
+
+/// Raw amount of an SPL token account
+fn token_amount(data: &[u8]) -> Option<u64> {
+    let bytes = data.get(TOKEN_AMOUNT_OFFSET..TOKEN_AMOUNT_OFFSET + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Publish pool account notifications as a price. A swap changes both vaults of a
+/// constant-product pool (the pool account of a CLMM pool) in one slot, so an update
+/// leaving them at different slots waits up to `PAIR_WAIT` for the rest. Without a
+/// `seed`, nothing is published until every account was notified. Returns `false`
+/// once nobody listens, `true` when the subscription ended.
+async fn stream_pools(
+    ctx: &ConnectorContext,
+    set: &PoolSet,
+    clock: &mut SlotClock,
+    mut updates: BoxStream<'static, AccountUpdate>,
+    seed: Option<SlotAccounts>,
+) -> bool {
+    // (data, slot) per pool account
+    let mut accounts: Vec<Option<(Vec<u8>, u64)>> = vec![None; set.accounts.len()];
+    if let Some(read) = seed {
+        for (account, data) in accounts.iter_mut().zip(read.data) {
+            *account = Some((data, read.slot));
+        }
+    }
+    let mut pending: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            update = updates.next() => {
+                let Some(update) = update else { return true };
+                // Older than what was priced already, pairing it would mix slots
+                if clock.is_stale(update.slot) || update.index >= accounts.len() {
+                    continue;
+                }
+                let slot = update.slot;
+                accounts[update.index] = Some((update.data, slot));
+                let (pool, start) = set.locate(update.index);
+                let swapped = pool.swap_accounts().iter().all(|i| {
+                    accounts[start + i].as_ref().is_some_and(|(_, s)| *s == slot)
+                });
+                if !swapped {
+                    pending.get_or_insert_with(|| Instant::now() + PAIR_WAIT);
+                    continue;
+                }
+            }
+            _ = sleep_until(pending.unwrap_or_else(Instant::now)), if pending.is_some() => {}
+        }
+
+        pending = None;
+        let Some(known) = accounts
+            .iter()
+            .map(Option::as_ref)
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let slot = known
+            .iter()
+            .map(|(_, slot)| *slot)
+            .max()
+            .unwrap_or_default();
+        let data: Vec<&[u8]> = known.iter().map(|(data, _)| data.as_slice()).collect();
+        if !publish_pools(ctx, set, clock, &data, slot) {
+            return false;
+        }
+        ctx.monitor.connected("Raydium");
+    }
+}
+
+async fn run_raydium_connector(
+    ctx: ConnectorContext,
+    rpc_url: String,
+    ws_url: String,
+    poll_interval: Duration,
+) {
+    let ConnectorContext {
+        pair,
+        symbol,
+        assets,
+        ..
+    } = &ctx;
     let canonical_pair = pair.clone();
 
-    let config = match get_vault_config(&canonical_pair) {
+    let config = match get_pair_config(assets, &canonical_pair, symbol) {
         Ok(c) => c,
         Err(e) => {
             eprintln!("RAYDIUM Configuration Error: {:?}", e);
+            ctx.monitor
+                .disconnected("Raydium", Some(format!("{:#}", e)));
             return;
         }
     };
-
     println!(
         "RAYDIUM Starting Raydium price feed for {}...",
         canonical_pair
     );
 
-    let rpc = RpcClient::new("https://api.mainnet-beta.solana.com".into());
+    let rpc = RpcClient::new(rpc_url);
+    let mut clock = SlotClock::default();
+
+    let set = loop {
+        match resolve_pools(&ctx, &rpc, &config).await {
+            Ok(set) => break set,
+            Err(err) => {
+                println!(
+                    "RAYDIUM No pool for {}: {:#}. Retrying",
+                    canonical_pair, err
+                );
+                ctx.monitor
+                    .disconnected("Raydium", Some(format!("{:#}", err)));
+                sleep(RESOLVE_RETRY).await;
+            }
+        }
+    };
+    let pools: Vec<String> = set
+        .pools
+        .iter()
+        .map(|pool| format!("{} ({:?})", pool.address(), pool.kind))
+        .collect();
+    println!(
+        "RAYDIUM Pricing {} from {}",
+        canonical_pair,
+        pools.join(", ")
+    );
 
     loop {
-        // Pass the dynamic configuration to the fetch function
-        match fetch_raydium_price(&rpc, &config).await {
-            Ok(price) => {
-                // Generate timestamp for RPC poll
-                let timestamp = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as u64;
-
-                let update = PriceUpdate {
-                    source: "Raydium".into(),
-                    pair: canonical_pair.clone(), // Use the original canonical pair
-                    price,
-                    timestamp, // Added timestamp field
+        // Pool changes are pushed, the RPC is only read to seed the reserves
+        match ctx
+            .subscribe_accounts("Raydium", &ws_url, &set.accounts)
+            .await
+        {
+            Ok(updates) => {
+                println!("RAYDIUM Subscribed to pools of {}", canonical_pair);
+                ctx.monitor.connected("Raydium");
+                let seed = match read_pools(&ctx, &rpc, &set).await {
+                    Ok(seed) => Some(seed),
+                    Err(err) => {
+                        report_read_error(&ctx, err);
+                        None
+                    }
                 };
-                let _ = tx.send(update);
+                let seeded = seed
+                    .as_ref()
+                    .is_none_or(|read| publish_read(&ctx, &set, &mut clock, read));
+                if !seeded || !stream_pools(&ctx, &set, &mut clock, updates, seed).await {
+                    break;
+                }
+                println!("RAYDIUM Subscription for {} ended, polling", canonical_pair);
             }
             Err(err) => {
-                println!("RAYDIUM Error fetching {}: {:?}", canonical_pair, err);
+                println!(
+                    "RAYDIUM Subscription failed for {}: {:#}. Polling",
+                    canonical_pair, err
+                );
+            }
+        }
+        ctx.monitor.degraded("Raydium", POLLING_FALLBACK);
+
+        // Fallback: poll until it is time to subscribe again
+        let resubscribe = Instant::now() + RESUBSCRIBE_AFTER;
+        while Instant::now() < resubscribe {
+            match poll_pools(&ctx, &rpc, &set, &mut clock).await {
+                Ok(true) => ctx.monitor.degraded("Raydium", POLLING_FALLBACK),
+                Ok(false) => {
+                    println!(
+                        "RAYDIUM No subscribers left for {}. Stopping",
+                        canonical_pair
+                    );
+                    return;
+                }
+                Err(err) => report_read_error(&ctx, err),
+            }
+            sleep(poll_interval).await;
+        }
+    }
+    println!(
+        "RAYDIUM No subscribers left for {}. Stopping",
+        canonical_pair
+    );
+}
+
+/// Streams every Raydium pool of the pair (AMM v4, CP-Swap, CLMM) over Solana pubsub,
+/// polling JSON-RPC while that is down
+pub struct RaydiumConnector {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub poll_interval: Duration,
+}
+
+impl Default for RaydiumConnector {
+    fn default() -> Self {
+        Self {
+            rpc_url: SOLANA_RPC_URL.to_string(),
+            ws_url: pubsub_url(SOLANA_RPC_URL),
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+}
+
+impl Connector for RaydiumConnector {
+    fn name(&self) -> &'static str {
+        "Raydium"
+    }
+
+    fn kind(&self) -> VenueKind {
+        VenueKind::Dex
+    }
+
+    fn supported_pairs(&self, assets: &AssetRegistry) -> Option<Vec<String>> {
+        Some(assets.pool_pairs(self.name()))
+    }
+
+    /// Pinned pool id, unset when every pool of the mints is priced
+    fn symbol(&self, assets: &AssetRegistry, pair: &str) -> Option<String> {
+        assets.pool(self.name(), pair)?.address.clone()
+    }
+
+    fn configure(&mut self, config: &Config) {
+        let venue = config.venue(self.name());
+        (self.rpc_url, self.ws_url) = solana_endpoints(config, self.name());
+        if let Some(interval) = venue.poll_interval() {
+            self.poll_interval = interval;
+        }
+    }
+
+    fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+        let (rpc_url, ws_url) = (self.rpc_url.clone(), self.ws_url.clone());
+        let poll_interval = self.poll_interval;
+        ctx.run(move |ctx| run_raydium_connector(ctx, rpc_url, ws_url, poll_interval))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::mock::{Harness, MockRpc};
+    use bytemuck::Zeroable;
+
+    const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+    const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const BTC_MINT: &str = "3NZ9JMVBmGAqocybic2c7LQCJScmgsAZ6vQqTDzcqmJh";
+
+    fn key(pubkey: &str) -> [u8; 32] {
+        Pubkey::from_str(pubkey).unwrap().to_bytes()
+    }
+
+    fn new_keys<const N: usize>() -> [String; N] {
+        std::array::from_fn(|_| Pubkey::new_unique().to_string())
+    }
+
+    fn padded(mut data: Vec<u8>, size: usize) -> Vec<u8> {
+        data.resize(size, 0);
+        data
+    }
+
+    /// Swappable AMM v4 pool of `coin` / `pc` (mint, decimals) with a 25 bps swap fee,
+    /// at `ids` (AMM, coin vault, pc vault, open orders)
+    fn amm(coin: (&str, u64), pc: (&str, u64), ids: &[String; 4]) -> AmmInfo {
+        let mut amm = AmmInfo::zeroed();
+        amm.status = 1;
+        (amm.coin_decimals, amm.pc_decimals) = (coin.1, pc.1);
+        amm.fees.swap_fee_numerator = 25;
+        amm.fees.swap_fee_denominator = 10_000;
+        (amm.coin_vault_mint, amm.pc_vault_mint) = (key(coin.0), key(pc.0));
+        amm.coin_vault = key(&ids[1]);
+        amm.pc_vault = key(&ids[2]);
+        amm.open_orders = key(&ids[3]);
+        amm
+    }
+
+    fn open_orders(coin_total: u64, pc_total: u64) -> Vec<u8> {
+        let open_orders = OpenOrders {
+            native_coin_total: coin_total,
+            native_pc_total: pc_total,
+            ..Default::default()
+        };
+        padded(borsh::to_vec(&open_orders).unwrap(), 3228)
+    }
+
+    /// AMM v4 SOL/USDC pool at 1000 SOL / 150k USDC, the only pool of the pair
+    fn sol_pool(rpc: &MockRpc) -> [String; 4] {
+        let ids = new_keys();
+        let info = amm((SOL_MINT, 9), (USDC_MINT, 6), &ids);
+        rpc.set_owned_account(
+            &ids[0],
+            AMM_V4_PROGRAM_ID,
+            bytemuck::bytes_of(&info).to_vec(),
+        );
+        rpc.set_accounts(&[
+            (&ids[1], MockRpc::token_account(1_000_000_000_000)),
+            (&ids[2], MockRpc::token_account(150_000_000_000)),
+            (&ids[3], open_orders(0, 0)),
+        ]);
+        ids
+    }
+
+    fn connector(rpc: &MockRpc) -> RaydiumConnector {
+        RaydiumConnector {
+            rpc_url: rpc.url(),
+            ws_url: rpc.ws_url(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn prices_effective_reserves_and_keeps_polling_through_rpc_errors() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+        // 10 SOL and 1000 USDC of PnL are owed to the protocol, 20 SOL and 2000 USDC
+        // sit in open orders
+        let ids = new_keys();
+        let mut info = amm((SOL_MINT, 9), (USDC_MINT, 6), &ids);
+        info.state_data.need_take_pnl_coin = 10_000_000_000;
+        info.state_data.need_take_pnl_pc = 1_000_000_000;
+        rpc.set_owned_account(
+            &ids[0],
+            AMM_V4_PROGRAM_ID,
+            bytemuck::bytes_of(&info).to_vec(),
+        );
+        rpc.set_account(&ids[3], open_orders(20_000_000_000, 2_000_000_000));
+        // The pc vault is missing until the first poll failed
+        rpc.set_balance(&ids[1], 990_000_000_000);
+
+        let mut harness = Harness::start(connector(&rpc), "SOL/USDC");
+        let reads = |rpc: &MockRpc| {
+            let calls = rpc.calls();
+            calls.iter().filter(|c| *c == "getMultipleAccounts").count()
+        };
+        // The pool read, then the failed read of its accounts
+        while reads(&rpc) < 2 {
+            sleep(Duration::from_millis(10)).await;
+        }
+        let status = harness.wait_status("Raydium", |s| !s.connected).await;
+        assert!(status.last_error.unwrap().contains("not found"));
+        rpc.set_balance(&ids[2], 149_000_000_000);
+
+        let update = harness.next_update().await;
+        assert_eq!(update.price, 150.0);
+        let status = harness.wait_status("Raydium", |s| s.degraded).await;
+        assert_eq!(status.last_error.as_deref(), Some(POLLING_FALLBACK));
+        assert_eq!(update.native_quote, "USDC");
+        assert!(update.slot.is_some());
+        assert_eq!(harness.fees.taker_bps("Raydium", "SOL/USDC"), 25.0);
+        assert_eq!(
+            update.pools,
+            [PoolPrice {
+                address: ids[0].clone(),
+                price: 150.0,
+                liquidity: 150_000.0,
+            }]
+        );
+
+        let book = harness.books.get("Raydium", "SOL/USDC").unwrap();
+        assert!(book.asks[0].0 > 150.0 && book.bids[0].0 < 150.0);
+    }
+
+    #[tokio::test]
+    async fn prices_across_every_pool_program_of_the_mints() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+
+        // AMM v4 at 60k: 10 BTC / 600k USDC, 25 bps
+        let amm_ids = new_keys();
+        let info = amm((BTC_MINT, 8), (USDC_MINT, 6), &amm_ids);
+        rpc.set_owned_account(
+            &amm_ids[0],
+            AMM_V4_PROGRAM_ID,
+            bytemuck::bytes_of(&info).to_vec(),
+        );
+        rpc.set_balance(&amm_ids[1], 1_000_000_000);
+        rpc.set_balance(&amm_ids[2], 600_000_000_000);
+        rpc.set_account(&amm_ids[3], open_orders(0, 0));
+
+        // CP-Swap at 65k with the mints the other way round: 1.3M USDC (plus 1000 of
+        // protocol fees) / 20 BTC, 25 bps
+        let [cpmm_id, vault_0, vault_1, cpmm_config] = new_keys();
+        let pool = CpmmPool {
+            amm_config: key(&cpmm_config),
+            token_0_vault: key(&vault_0),
+            token_1_vault: key(&vault_1),
+            token_0_mint: key(USDC_MINT),
+            token_1_mint: key(BTC_MINT),
+            mint_0_decimals: 6,
+            mint_1_decimals: 8,
+            protocol_fees_token_0: 1_000_000_000,
+            ..Default::default()
+        };
+        let pool = padded(borsh::to_vec(&pool).unwrap(), CPMM_POOL_SIZE);
+        rpc.set_owned_account(&cpmm_id, CPMM_PROGRAM_ID, pool);
+        rpc.set_balance(&vault_0, 1_301_000_000_000);
+        rpc.set_balance(&vault_1, 2_000_000_000);
+        let config = CpmmConfig {
+            trade_fee_rate: 2_500,
+            ..Default::default()
+        };
+        rpc.set_account(&cpmm_config, padded(borsh::to_vec(&config).unwrap(), 236));
+
+        // CLMM at 62.5k (25^2 raw USDC per raw BTC), 10 BTC / 625k USDC in range, 5 bps
+        let [clmm_id, clmm_config, disabled_id] = new_keys();
+        let clmm = |liquidity: u128, status: u8| ClmmPool {
+            amm_config: key(&clmm_config),
+            token_mint_0: key(BTC_MINT),
+            token_mint_1: key(USDC_MINT),
+            mint_decimals_0: 8,
+            mint_decimals_1: 6,
+            sqrt_price_x64: 25 << 64,
+            liquidity,
+            status,
+            ..Default::default()
+        };
+        let pool = padded(
+            borsh::to_vec(&clmm(25_000_000_000, 0)).unwrap(),
+            CLMM_POOL_SIZE,
+        );
+        rpc.set_owned_account(&clmm_id, CLMM_PROGRAM_ID, pool);
+        let config = ClmmConfig {
+            trade_fee_rate: 500,
+            ..Default::default()
+        };
+        rpc.set_account(&clmm_config, padded(borsh::to_vec(&config).unwrap(), 117));
+        // Deeper, but its swaps are disabled
+        let disabled = clmm(25_000_000_000_000, 1 << 4);
+        let disabled = padded(borsh::to_vec(&disabled).unwrap(), CLMM_POOL_SIZE);
+        rpc.set_owned_account(&disabled_id, CLMM_PROGRAM_ID, disabled);
+
+        // Other mints
+        sol_pool(&rpc);
+
+        let mut harness = Harness::start(connector(&rpc), "BTC/USDC");
+        let update = harness.next_update().await;
+        // 60k and 65k weighted by 600k and 1.3M USDC, the CLMM pool's range is no curve
+        // to price on
+        let expected = (60_000.0 * 600_000.0 + 65_000.0 * 1_300_000.0) / 1_900_000.0;
+        assert!((update.price - expected).abs() < 1e-6, "{}", update.price);
+        assert_eq!(update.native_quote, "USDC");
+        assert_eq!(harness.fees.taker_bps("Raydium", "BTC/USDC"), 25.0);
+        // Both curves in one book, each walked to 10% of its base reserve
+        let book = harness.books.get("Raydium", "BTC/USDC").unwrap();
+        let depth: f64 = book.bids.iter().map(|(_, size)| size).sum();
+        assert!(depth < 0.1 * 30.0 + 1e-9, "{}", depth);
+        assert!(book.bids.windows(2).all(|w| w[0].0 >= w[1].0));
+        assert!(book.asks.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(book.asks[0].0 > 60_000.0 && book.asks[0].0 < 60_100.0);
+
+        assert_eq!(update.pools.len(), 3);
+        let clmm = update.pools.iter().find(|p| p.address == clmm_id).unwrap();
+        assert_eq!((clmm.price, clmm.liquidity), (62_500.0, 625_000.0));
+        let cpmm = update.pools.iter().find(|p| p.address == cpmm_id).unwrap();
+        assert_eq!((cpmm.price, cpmm.liquidity), (65_000.0, 1_300_000.0));
+
+        // Both mint orders of each program
+        let calls = rpc.calls();
+        let searches = calls.iter().filter(|c| *c == "getProgramAccounts");
+        assert_eq!(searches.count(), 6);
+    }
+
+    #[tokio::test]
+    async fn dust_and_outlier_pools_do_not_move_the_price() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+        sol_pool(&rpc);
+        // One lamport against 1 USDC, and a real but skewed pool at 500 USDC per SOL
+        for (sol, usdc) in [(1, 1_000_000), (100_000_000_000, 50_000_000_000)] {
+            let ids: [String; 4] = new_keys();
+            let info = amm((SOL_MINT, 9), (USDC_MINT, 6), &ids);
+            rpc.set_owned_account(
+                &ids[0],
+                AMM_V4_PROGRAM_ID,
+                bytemuck::bytes_of(&info).to_vec(),
+            );
+            rpc.set_accounts(&[
+                (&ids[1], MockRpc::token_account(sol)),
+                (&ids[2], MockRpc::token_account(usdc)),
+                (&ids[3], open_orders(0, 0)),
+            ]);
+        }
+
+        let mut harness = Harness::start(connector(&rpc), "SOL/USDC");
+        let update = harness.next_update().await;
+        assert_eq!(update.price, 150.0);
+        // Still listed, just not priced on
+        assert_eq!(update.pools.len(), 3);
+
+        // The book is the 150 pool's curve alone
+        let book = harness.books.get("Raydium", "SOL/USDC").unwrap();
+        let depth: f64 = book.asks.iter().map(|(_, size)| size).sum();
+        assert!(depth < 0.1 * 1_000.0 + 1e-9, "{}", depth);
+        assert!(book.asks.iter().all(|(price, _)| *price < 200.0));
+    }
+
+    #[tokio::test]
+    async fn prices_clmm_pools_alone_without_a_curve_book() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+        let [clmm_id, clmm_config] = new_keys();
+        let pool = ClmmPool {
+            amm_config: key(&clmm_config),
+            token_mint_0: key(BTC_MINT),
+            token_mint_1: key(USDC_MINT),
+            mint_decimals_0: 8,
+            mint_decimals_1: 6,
+            sqrt_price_x64: 25 << 64,
+            liquidity: 25_000_000_000,
+            ..Default::default()
+        };
+        let pool = padded(borsh::to_vec(&pool).unwrap(), CLMM_POOL_SIZE);
+        rpc.set_owned_account(&clmm_id, CLMM_PROGRAM_ID, pool);
+        let config = ClmmConfig {
+            trade_fee_rate: 500,
+            ..Default::default()
+        };
+        rpc.set_account(&clmm_config, padded(borsh::to_vec(&config).unwrap(), 117));
+
+        let mut harness = Harness::start(connector(&rpc), "BTC/USDC");
+        let update = harness.next_update().await;
+        assert_eq!(update.price, 62_500.0);
+        assert_eq!(update.pools.len(), 1);
+        assert_eq!(harness.fees.taker_bps("Raydium", "BTC/USDC"), 5.0);
+        assert!(harness.books.get("Raydium", "BTC/USDC").is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_reads_older_than_the_last_priced_slot() {
+        let rpc = MockRpc::start().await;
+        rpc.disable_pubsub();
+        let ids = sol_pool(&rpc);
+        let mut harness = Harness::start(
+            RaydiumConnector {
+                poll_interval: Duration::from_millis(20),
+                ..connector(&rpc)
+            },
+            "SOL/USDC",
+        );
+        let mut last = harness.next_update().await.slot.unwrap();
+
+        // A node behind the last one answers from now on, with the new price
+        rpc.set_slot(0);
+        rpc.set_balance(&ids[2], 200_000_000_000);
+        loop {
+            let update = harness.next_update().await;
+            let slot = update.slot.unwrap();
+            assert!(slot >= last, "slot {} after slot {}", slot, last);
+            last = slot;
+            if update.price == 200.0 {
+                break;
             }
         }
+    }
+
+    #[tokio::test]
+    async fn streams_pool_notifications_paired_by_slot() {
+        let rpc = MockRpc::start().await;
+        let ids = sol_pool(&rpc);
+        let mut harness = Harness::start(connector(&rpc), "SOL/USDC");
+
+        // Seeded from one RPC read once subscribed
+        let update = harness.next_update().await;
+        assert_eq!(update.price, 150.0);
+        let seeded = update.slot.unwrap();
+        assert_eq!(rpc.subscriptions(), 4);
+        let status = harness.wait_status("Raydium", |s| s.connected).await;
+        assert!(!status.degraded);
+
+        // A swap moves the pool and both vaults in one slot
+        let info = amm((SOL_MINT, 9), (USDC_MINT, 6), &ids);
+        rpc.set_accounts(&[
+            (&ids[0], bytemuck::bytes_of(&info).to_vec()),
+            (&ids[1], MockRpc::token_account(800_000_000_000)),
+            (&ids[2], MockRpc::token_account(160_000_000_000)),
+        ]);
+        let update = harness.next_update().await;
+        assert_eq!(update.price, 200.0);
+        assert_eq!(update.slot, Some(seeded + 1));
+
+        // A lone vault change goes out once the other vault had its chance. The pool
+        // notification may have repriced the swap's slot in between.
+        rpc.set_balance(&ids[2], 240_000_000_000);
+        let mut update = harness.next_update().await;
+        while update.slot == Some(seeded + 1) {
+            update = harness.next_update().await;
+        }
+        assert_eq!(update.price, 300.0);
+        assert_eq!(update.slot, Some(seeded + 2));
 
-        sleep(Duration::from_millis(500)).await;
+        // Discovery aside, the pool states and then one seed read
+        let calls = rpc.calls();
+        let reads: Vec<&String> = calls
+            .iter()
+            .filter(|c| *c != "getProgramAccounts")
+            .collect();
+        assert_eq!(reads, ["getMultipleAccounts", "getMultipleAccounts"]);
     }
 }
@@ -0,0 +1,192 @@
+//! Raydium AMM v4 pool state (`AmmInfo`) and the OpenBook open-orders account
+//! holding part of its reserves
+
+use borsh::BorshDeserialize;
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const AMM_INFO_SIZE: usize = 752;
+/// Offsets of the mints, for `getProgramAccounts` filters
+pub const AMM_COIN_MINT_OFFSET: usize = offset_of!(AmmInfo, coin_vault_mint);
+pub const AMM_PC_MINT_OFFSET: usize = offset_of!(AmmInfo, pc_vault_mint);
+
+// AmmStatus values that allow swaps: Initialized, SwapOnly, WaitingTrade
+const STATUS_INITIALIZED: u64 = 1;
+const STATUS_SWAP_ONLY: u64 = 6;
+const STATUS_WAITING_TRADE: u64 = 7;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct AmmFees {
+    pub min_separate_numerator: u64,
+    pub min_separate_denominator: u64,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub pnl_numerator: u64,
+    pub pnl_denominator: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+}
+
+/// u128 counters are kept as `[u64; 2]`: the on-chain layout only aligns them to 8
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct AmmStateData {
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub total_pnl_pc: u64,
+    pub total_pnl_coin: u64,
+    pub pool_open_time: u64,
+    pub padding: [u64; 2],
+    pub orderbook_to_init_time: u64,
+    pub swap_coin_in_amount: [u64; 2],
+    pub swap_pc_out_amount: [u64; 2],
+    pub swap_acc_pc_fee: u64,
+    pub swap_pc_in_amount: [u64; 2],
+    pub swap_coin_out_amount: [u64; 2],
+    pub swap_acc_coin_fee: u64,
+}
+
+/// AMM v4 pool account. Coin is the pool's first token, pc its second.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct AmmInfo {
+    pub status: u64,
+    pub nonce: u64,
+    pub order_num: u64,
+    pub depth: u64,
+    pub coin_decimals: u64,
+    pub pc_decimals: u64,
+    pub state: u64,
+    pub reset_flag: u64,
+    pub min_size: u64,
+    pub vol_max_cut_ratio: u64,
+    pub amount_wave: u64,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub min_price_multiplier: u64,
+    pub max_price_multiplier: u64,
+    pub sys_decimal_value: u64,
+    pub fees: AmmFees,
+    pub state_data: AmmStateData,
+    pub coin_vault: [u8; 32],
+    pub pc_vault: [u8; 32],
+    pub coin_vault_mint: [u8; 32],
+    pub pc_vault_mint: [u8; 32],
+    pub lp_mint: [u8; 32],
+    pub open_orders: [u8; 32],
+    pub market: [u8; 32],
+    pub market_program: [u8; 32],
+    pub target_orders: [u8; 32],
+    pub padding1: [u64; 8],
+    pub amm_owner: [u8; 32],
+    pub lp_amount: u64,
+    pub client_order_id: u64,
+    pub recent_epoch: u64,
+    pub padding2: u64,
+}
+
+const _: () = assert!(size_of::<AmmInfo>() == AMM_INFO_SIZE);
+
+impl AmmInfo {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let bytes = data.get(..AMM_INFO_SIZE).ok_or_else(|| {
+            anyhow::anyhow!("AMM account is {} bytes, not {}", data.len(), AMM_INFO_SIZE)
+        })?;
+        bytemuck::try_pod_read_unaligned(bytes).map_err(|e| anyhow::anyhow!("AMM account: {}", e))
+    }
+
+    pub fn coin_mint(&self) -> Pubkey {
+        Pubkey::new_from_array(self.coin_vault_mint)
+    }
+
+    pub fn pc_mint(&self) -> Pubkey {
+        Pubkey::new_from_array(self.pc_vault_mint)
+    }
+
+    /// Coin vault, pc vault and open orders, the accounts holding the reserves
+    pub fn reserve_accounts(&self) -> [Pubkey; 3] {
+        [self.coin_vault, self.pc_vault, self.open_orders].map(Pubkey::new_from_array)
+    }
+
+    pub fn is_swappable(&self) -> bool {
+        matches!(
+            self.status,
+            STATUS_INITIALIZED | STATUS_SWAP_ONLY | STATUS_WAITING_TRADE
+        )
+    }
+
+    /// Swap fee in bps, `None` without a denominator
+    pub fn swap_fee_bps(&self) -> Option<f64> {
+        let fees = &self.fees;
+        (fees.swap_fee_denominator > 0)
+            .then(|| fees.swap_fee_numerator as f64 / fees.swap_fee_denominator as f64 * 10_000.0)
+    }
+
+    /// Raw (coin, pc) amounts a swap trades against: the vaults, plus what sits in
+    /// open orders while the order book is on, minus the PnL owed to the protocol
+    pub fn effective_reserves(
+        &self,
+        coin_vault: u64,
+        pc_vault: u64,
+        open_orders: &OpenOrders,
+    ) -> (u64, u64) {
+        let (mut coin, mut pc) = (coin_vault, pc_vault);
+        if self.status != STATUS_SWAP_ONLY {
+            coin += open_orders.native_coin_total;
+            pc += open_orders.native_pc_total;
+        }
+        (
+            coin.saturating_sub(self.state_data.need_take_pnl_coin),
+            pc.saturating_sub(self.state_data.need_take_pnl_pc),
+        )
+    }
+}
+
+/// Serum / OpenBook open-orders account, up to the token totals. Only the totals are
+/// used, the other fields are decoded to reach them.
+#[allow(dead_code)]
+#[derive(Debug, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct OpenOrders {
+    pub head_padding: [u8; 5], // "serum"
+    pub account_flags: u64,
+    pub market: [u8; 32],
+    pub owner: [u8; 32],
+    pub native_coin_free: u64,
+    pub native_coin_total: u64,
+    pub native_pc_free: u64,
+    pub native_pc_total: u64,
+}
+
+impl OpenOrders {
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::deserialize(&mut data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_the_program() {
+        assert_eq!(
+            offset_of!(AmmInfo, fees) + offset_of!(AmmFees, swap_fee_numerator),
+            176
+        );
+        assert_eq!(offset_of!(AmmInfo, state_data), 192);
+        assert_eq!(offset_of!(AmmInfo, coin_vault), 336);
+        assert_eq!(AMM_COIN_MINT_OFFSET, 400);
+        assert_eq!(AMM_PC_MINT_OFFSET, 432);
+        assert_eq!(offset_of!(AmmInfo, lp_amount), 720);
+
+        let mut open_orders = vec![0u8; 3228];
+        open_orders[85..93].copy_from_slice(&7u64.to_le_bytes());
+        open_orders[101..109].copy_from_slice(&9u64.to_le_bytes());
+        let decoded = OpenOrders::decode(&open_orders).unwrap();
+        assert_eq!((decoded.native_coin_total, decoded.native_pc_total), (7, 9));
+    }
+}
@@ -0,0 +1,154 @@
+//! Raydium concentrated-liquidity (CLMM) pool state and its fee config
+
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+
+pub const CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const CLMM_POOL_SIZE: usize = 1544;
+/// Offsets of the mints, for `getProgramAccounts` filters
+pub const CLMM_MINT_0_OFFSET: usize = 73;
+pub const CLMM_MINT_1_OFFSET: usize = 105;
+
+/// Pool status bit set while swaps are disabled
+const STATUS_SWAP_DISABLED: u8 = 1 << 4;
+
+/// CLMM `PoolState` up to the status byte; reward infos, tick bitmap and fee totals
+/// follow. Unused fields are decoded to reach the ones after them.
+#[allow(dead_code)]
+#[derive(Debug, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct ClmmPool {
+    pub discriminator: [u8; 8],
+    pub bump: u8,
+    pub amm_config: [u8; 32],
+    pub owner: [u8; 32],
+    pub token_mint_0: [u8; 32],
+    pub token_mint_1: [u8; 32],
+    pub token_vault_0: [u8; 32],
+    pub token_vault_1: [u8; 32],
+    pub observation_key: [u8; 32],
+    pub mint_decimals_0: u8,
+    pub mint_decimals_1: u8,
+    pub tick_spacing: u16,
+    pub liquidity: u128, // Active liquidity at the current tick
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    pub padding3: u16,
+    pub padding4: u16,
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub swap_in_amount_token_0: u128,
+    pub swap_out_amount_token_1: u128,
+    pub swap_in_amount_token_1: u128,
+    pub swap_out_amount_token_0: u128,
+    pub status: u8,
+}
+
+impl ClmmPool {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() != CLMM_POOL_SIZE {
+            anyhow::bail!("CLMM pool is {} bytes, not {}", data.len(), CLMM_POOL_SIZE);
+        }
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+
+    pub fn mints(&self) -> [Pubkey; 2] {
+        [self.token_mint_0, self.token_mint_1].map(Pubkey::new_from_array)
+    }
+
+    pub fn amm_config(&self) -> Pubkey {
+        Pubkey::new_from_array(self.amm_config)
+    }
+
+    pub fn is_swappable(&self) -> bool {
+        self.status & STATUS_SWAP_DISABLED == 0
+    }
+
+    /// Token 1 per token 0 in whole tokens, from the Q64.64 square root price
+    pub fn price(&self) -> f64 {
+        let sqrt_price = self.sqrt_price();
+        sqrt_price
+            * sqrt_price
+            * 10f64.powi(self.mint_decimals_0 as i32 - self.mint_decimals_1 as i32)
+    }
+
+    /// Whole-token reserves of a constant-product pool with the active liquidity,
+    /// x = L / sqrt(P) and y = L * sqrt(P), valid until the next tick is crossed
+    pub fn virtual_reserves(&self) -> (f64, f64) {
+        let sqrt_price = self.sqrt_price();
+        if sqrt_price == 0.0 {
+            return (0.0, 0.0);
+        }
+        let liquidity = self.liquidity as f64;
+        (
+            liquidity / sqrt_price / 10f64.powi(self.mint_decimals_0 as i32),
+            liquidity * sqrt_price / 10f64.powi(self.mint_decimals_1 as i32),
+        )
+    }
+
+    fn sqrt_price(&self) -> f64 {
+        self.sqrt_price_x64 as f64 / (1u128 << 64) as f64
+    }
+}
+
+/// CLMM `AmmConfig` up to the trade fee rate
+#[allow(dead_code)]
+#[derive(Debug, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct ClmmConfig {
+    pub discriminator: [u8; 8],
+    pub bump: u8,
+    pub index: u16,
+    pub owner: [u8; 32],
+    pub protocol_fee_rate: u32,
+    pub trade_fee_rate: u32,
+}
+
+impl ClmmConfig {
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::deserialize(&mut data)?)
+    }
+
+    pub fn fee_bps(&self) -> f64 {
+        // Millionths, i.e. hundredths of a basis point
+        self.trade_fee_rate as f64 / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_the_program() {
+        let pool = ClmmPool {
+            token_mint_0: [1; 32],
+            token_mint_1: [2; 32],
+            // 5^2 = 25 raw units of token 1 per raw unit of token 0
+            mint_decimals_0: 9,
+            mint_decimals_1: 6,
+            sqrt_price_x64: 5 << 64,
+            liquidity: 1_000_000,
+            status: STATUS_SWAP_DISABLED,
+            ..Default::default()
+        };
+        let mut data = borsh::to_vec(&pool).unwrap();
+        assert_eq!(data.len(), 390);
+        assert_eq!(data[CLMM_MINT_0_OFFSET..CLMM_MINT_0_OFFSET + 32], [1; 32]);
+        assert_eq!(data[CLMM_MINT_1_OFFSET..CLMM_MINT_1_OFFSET + 32], [2; 32]);
+        assert_eq!(data[253], 0); // sqrt_price_x64 low half
+        assert_eq!(data[261], 5); // and its integer part
+
+        data.resize(CLMM_POOL_SIZE, 0);
+        let decoded = ClmmPool::decode(&data).unwrap();
+        assert_eq!(decoded.price(), 25_000.0);
+        assert_eq!(decoded.virtual_reserves(), (0.0002, 5.0));
+        assert!(!decoded.is_swappable());
+
+        let mut config = vec![0u8; 117];
+        config[47..51].copy_from_slice(&2_500u32.to_le_bytes());
+        assert_eq!(ClmmConfig::decode(&config).unwrap().trade_fee_rate, 2_500);
+    }
+}
@@ -0,0 +1,141 @@
+//! Raydium CP-Swap (CPMM) pool state and its fee config
+
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+
+pub const CPMM_PROGRAM_ID: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+pub const CPMM_POOL_SIZE: usize = 637;
+/// Offsets of the mints, for `getProgramAccounts` filters
+pub const CPMM_MINT_0_OFFSET: usize = 168;
+pub const CPMM_MINT_1_OFFSET: usize = 200;
+
+/// Pool status bit set while swaps are disabled
+const STATUS_SWAP_DISABLED: u8 = 1 << 2;
+
+/// CP-Swap `PoolState` up to the creator fees, padding follows. Unused fields are
+/// decoded to reach the ones after them.
+#[allow(dead_code)]
+#[derive(Debug, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct CpmmPool {
+    pub discriminator: [u8; 8],
+    pub amm_config: [u8; 32],
+    pub pool_creator: [u8; 32],
+    pub token_0_vault: [u8; 32],
+    pub token_1_vault: [u8; 32],
+    pub lp_mint: [u8; 32],
+    pub token_0_mint: [u8; 32],
+    pub token_1_mint: [u8; 32],
+    pub token_0_program: [u8; 32],
+    pub token_1_program: [u8; 32],
+    pub observation_key: [u8; 32],
+    pub auth_bump: u8,
+    pub status: u8,
+    pub lp_mint_decimals: u8,
+    pub mint_0_decimals: u8,
+    pub mint_1_decimals: u8,
+    pub lp_supply: u64,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    pub open_time: u64,
+    pub recent_epoch: u64,
+    pub creator_fee_on: u8,
+    pub enable_creator_fee: bool,
+    pub padding1: [u8; 6],
+    pub creator_fees_token_0: u64,
+    pub creator_fees_token_1: u64,
+}
+
+impl CpmmPool {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() != CPMM_POOL_SIZE {
+            anyhow::bail!("CPMM pool is {} bytes, not {}", data.len(), CPMM_POOL_SIZE);
+        }
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+
+    pub fn mints(&self) -> [Pubkey; 2] {
+        [self.token_0_mint, self.token_1_mint].map(Pubkey::new_from_array)
+    }
+
+    pub fn vaults(&self) -> [Pubkey; 2] {
+        [self.token_0_vault, self.token_1_vault].map(Pubkey::new_from_array)
+    }
+
+    pub fn amm_config(&self) -> Pubkey {
+        Pubkey::new_from_array(self.amm_config)
+    }
+
+    pub fn is_swappable(&self) -> bool {
+        self.status & STATUS_SWAP_DISABLED == 0
+    }
+
+    /// Raw (token 0, token 1) amounts a swap trades against: the vaults minus the
+    /// protocol, fund and creator fees they still hold
+    pub fn reserves(&self, vault_0: u64, vault_1: u64) -> (u64, u64) {
+        let owed_0 =
+            self.protocol_fees_token_0 + self.fund_fees_token_0 + self.creator_fees_token_0;
+        let owed_1 =
+            self.protocol_fees_token_1 + self.fund_fees_token_1 + self.creator_fees_token_1;
+        (
+            vault_0.saturating_sub(owed_0),
+            vault_1.saturating_sub(owed_1),
+        )
+    }
+}
+
+/// CP-Swap `AmmConfig` up to the trade fee rate
+#[allow(dead_code)]
+#[derive(Debug, BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize, Default))]
+pub struct CpmmConfig {
+    pub discriminator: [u8; 8],
+    pub bump: u8,
+    pub disable_create_pool: bool,
+    pub index: u16,
+    pub trade_fee_rate: u64,
+}
+
+impl CpmmConfig {
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::deserialize(&mut data)?)
+    }
+
+    pub fn fee_bps(&self) -> f64 {
+        // Millionths, i.e. hundredths of a basis point
+        self.trade_fee_rate as f64 / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_the_program() {
+        let pool = CpmmPool {
+            token_0_mint: [1; 32],
+            token_1_mint: [2; 32],
+            protocol_fees_token_0: 5,
+            fund_fees_token_1: 7,
+            creator_fees_token_1: 1,
+            ..Default::default()
+        };
+        let mut data = borsh::to_vec(&pool).unwrap();
+        assert_eq!(data.len(), 413);
+        assert_eq!(data[CPMM_MINT_0_OFFSET..CPMM_MINT_0_OFFSET + 32], [1; 32]);
+        assert_eq!(data[CPMM_MINT_1_OFFSET..CPMM_MINT_1_OFFSET + 32], [2; 32]);
+        assert_eq!(data[341], 5); // protocol_fees_token_0
+
+        data.resize(CPMM_POOL_SIZE, 0);
+        let decoded = CpmmPool::decode(&data).unwrap();
+        assert_eq!(decoded.reserves(100, 100), (95, 92));
+        assert!(decoded.is_swappable());
+
+        let mut config = vec![0u8; 236];
+        config[12..20].copy_from_slice(&2_500u64.to_le_bytes());
+        assert_eq!(CpmmConfig::decode(&config).unwrap().fee_bps(), 25.0);
+    }
+}
@@ -0,0 +1,163 @@
+use super::{
+    BackpackConnector, BinanceConnector, BitfinexConnector, BitgetConnector, BitstampConnector,
+    BybitConnector, CoinbaseConnector, Connector, HtxConnector, JupiterConnector, KrakenConnector,
+    KucoinConnector, OkxConnector, OrcaConnector, RaydiumConnector, Tape, VenueKind,
+};
+use crate::assets::AssetRegistry;
+use crate::config::Config;
+use crate::state::VenueStatus;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Venue description served over the API
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueInfo {
+    pub name: &'static str,
+    pub kind: VenueKind,
+    pub enabled: bool,
+    pub supported_pairs: Option<Vec<String>>,
+    /// Only set when the venue list was requested for a specific pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_pair: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// Connection state per streamed pair, filled in by the caller from the hub
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub connections: HashMap<String, VenueStatus>,
+}
+
+/// Every known connector plus the set switched off by configuration
+pub struct ConnectorRegistry {
+    connectors: Vec<Arc<dyn Connector>>,
+    disabled: HashSet<String>,
+    symbols: HashMap<(String, String), String>, // (lowercase venue, pair) -> configured symbol
+    assets: Arc<AssetRegistry>,
+    tape: Option<Arc<Tape>>,
+}
+
+impl ConnectorRegistry {
+    pub fn new(assets: Arc<AssetRegistry>) -> Self {
+        Self {
+            assets,
+            connectors: Vec::new(),
+            disabled: HashSet::new(),
+            symbols: HashMap::new(),
+            tape: None,
+        }
+    }
+
+    /// All built-in venues, all enabled
+    pub fn with_defaults() -> Self {
+        Self::from_config(&Config::default(), Arc::new(AssetRegistry::builtin()))
+    }
+
+    /// Built-in venues with their configured endpoints, symbols and enabled state
+    pub fn from_config(config: &Config, assets: Arc<AssetRegistry>) -> Self {
+        let connectors: Vec<Box<dyn Connector>> = vec![
+            Box::new(BinanceConnector::default()),
+            Box::new(BackpackConnector::default()),
+            Box::new(BitfinexConnector::default()),
+            Box::new(BitgetConnector::default()),
+            Box::new(BybitConnector::default()),
+            Box::new(CoinbaseConnector::default()),
+            Box::new(HtxConnector::default()),
+            Box::new(JupiterConnector::default()),
+            Box::new(KrakenConnector::default()),
+            Box::new(KucoinConnector::default()),
+            Box::new(OkxConnector::default()),
+            Box::new(RaydiumConnector::default()),
+            Box::new(OrcaConnector::default()),
+            Box::new(BitstampConnector::default()),
+        ];
+
+        let mut registry = Self::new(assets);
+        for mut connector in connectors {
+            connector.configure(config);
+            let venue = config.venue(connector.name());
+            if !venue.enabled {
+                registry.disable(connector.name());
+            }
+            for (pair, symbol) in venue.symbols {
+                registry
+                    .symbols
+                    .insert((connector.name().to_lowercase(), pair), symbol);
+            }
+            registry.connectors.push(Arc::from(connector));
+        }
+        registry
+    }
+
+    pub fn register<C: Connector + 'static>(&mut self, connector: C) {
+        self.connectors.push(Arc::new(connector));
+    }
+
+    pub fn disable(&mut self, name: &str) {
+        self.disabled.insert(name.to_lowercase());
+    }
+
+    /// Record or replay the raw traffic of every connector started from now on
+    pub fn set_tape(&mut self, tape: Option<Arc<Tape>>) {
+        self.tape = tape;
+    }
+
+    pub fn assets(&self) -> Arc<AssetRegistry> {
+        Arc::clone(&self.assets)
+    }
+
+    pub fn tape(&self) -> Option<Arc<Tape>> {
+        self.tape.clone()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(&name.to_lowercase())
+    }
+
+    /// Venue-native symbol of `pair`: the configured override, else the connector's own
+    pub fn symbol(&self, connector: &dyn Connector, pair: &str) -> Option<String> {
+        self.symbols
+            .get(&(connector.name().to_lowercase(), pair.to_string()))
+            .cloned()
+            .or_else(|| connector.symbol(&self.assets, pair))
+    }
+
+    /// A symbol override also makes the pair available on venues without a fixed list
+    fn supports(&self, connector: &dyn Connector, pair: &str) -> bool {
+        connector.supports(&self.assets, pair)
+            || (connector.supported_pairs(&self.assets).is_none()
+                && self
+                    .symbols
+                    .contains_key(&(connector.name().to_lowercase(), pair.to_string())))
+    }
+
+    /// Enabled connectors able to serve `pair`
+    pub fn for_pair(&self, pair: &str) -> Vec<Arc<dyn Connector>> {
+        self.connectors
+            .iter()
+            .filter(|c| self.is_enabled(c.name()) && self.supports(c.as_ref(), pair))
+            .cloned()
+            .collect()
+    }
+
+    /// Describe every registered venue, optionally against a requested pair
+    pub fn venues(&self, pair: Option<&str>) -> Vec<VenueInfo> {
+        self.connectors
+            .iter()
+            .map(|c| VenueInfo {
+                name: c.name(),
+                kind: c.kind(),
+                enabled: self.is_enabled(c.name()),
+                supported_pairs: c.supported_pairs(&self.assets),
+                supports_pair: pair.map(|p| self.supports(c.as_ref(), p)),
+                symbol: pair.and_then(|p| self.symbol(c.as_ref(), p)),
+                connections: HashMap::new(),
+            })
+            .collect()
+    }
+}
+
+impl Default for ConnectorRegistry {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
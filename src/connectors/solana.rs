@@ -0,0 +1,262 @@
+//! Solana account subscriptions shared by the on-chain connectors
+
+use super::ConnectorContext;
+use crate::config::Config;
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_pubsub_client::nonblocking::pubsub_client::PubsubClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Duration;
+
+/// Commitment of every account read and subscription, so polled and pushed state agree
+pub const COMMITMENT: CommitmentConfig = CommitmentConfig::confirmed();
+/// How long a connector polls after losing its subscription before subscribing again
+pub const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(30);
+/// Venue status reason while a connector polls in place of its subscription
+pub const POLLING_FALLBACK: &str = "Subscription down, polling RPC";
+/// Notifications buffered between the pubsub client and the connector
+const UPDATE_BUFFER: usize = 256;
+
+/// New data of one subscribed account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub index: usize, // Position in the subscribed account list
+    pub slot: u64,
+    pub data: Vec<u8>,
+}
+
+/// Data of accounts read together, all at `slot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotAccounts {
+    pub slot: u64,
+    pub data: Vec<Vec<u8>>, // In requested order
+}
+
+/// Last slot a connector priced at, so a lagging RPC node or a late notification
+/// can't move its price back in time
+#[derive(Debug, Default)]
+pub struct SlotClock {
+    last: Option<u64>,
+}
+
+impl SlotClock {
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// `slot` is older than the last admitted one
+    pub fn is_stale(&self, slot: u64) -> bool {
+        self.last.is_some_and(|last| slot < last)
+    }
+
+    /// Move to `slot` unless it is stale
+    pub fn admit(&mut self, slot: u64) -> bool {
+        if self.is_stale(slot) {
+            return false;
+        }
+        self.last = Some(slot);
+        true
+    }
+}
+
+/// Pubsub endpoint served next to a JSON-RPC endpoint (http -> ws, https -> wss)
+pub fn pubsub_url(rpc_url: &str) -> String {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
+
+/// JSON-RPC and pubsub endpoints of an on-chain venue: next to its own `url` when
+/// set, the `[rpc]` ones otherwise
+pub fn solana_endpoints(config: &Config, venue: &str) -> (String, String) {
+    match config.venue(venue).url {
+        Some(url) => {
+            let ws_url = pubsub_url(&url);
+            (url, ws_url)
+        }
+        None => {
+            let rpc = &config.rpc;
+            let ws_url = rpc
+                .solana_ws_url
+                .clone()
+                .unwrap_or_else(|| pubsub_url(&rpc.solana_url));
+            (rpc.solana_url.clone(), ws_url)
+        }
+    }
+}
+
+impl ConnectorContext {
+    /// Read `accounts` in one `getMultipleAccounts` at `COMMITMENT`, so they all come
+    /// from the same slot. Fails if any of them doesn't exist.
+    pub async fn fetch_accounts(
+        &self,
+        venue: &str,
+        call: &str,
+        rpc: &RpcClient,
+        accounts: &[Pubkey],
+    ) -> anyhow::Result<SlotAccounts> {
+        self.fetch(venue, call, async {
+            let response = rpc
+                .get_multiple_accounts_with_commitment(accounts, COMMITMENT)
+                .await?;
+            let data = response
+                .value
+                .into_iter()
+                .zip(accounts)
+                .map(|(account, pubkey)| {
+                    let account =
+                        account.ok_or_else(|| anyhow::anyhow!("Account {} not found", pubkey))?;
+                    Ok(account.data)
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok(SlotAccounts {
+                slot: response.context.slot,
+                data,
+            })
+        })
+        .await
+    }
+
+    /// Like `fetch_accounts`, with `None` for accounts that don't exist
+    pub async fn fetch_optional_accounts(
+        &self,
+        venue: &str,
+        call: &str,
+        rpc: &RpcClient,
+        accounts: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        self.fetch(venue, call, async {
+            let response = rpc
+                .get_multiple_accounts_with_commitment(accounts, COMMITMENT)
+                .await?;
+            Ok(response
+                .value
+                .into_iter()
+                .map(|account| account.map(|a| a.data))
+                .collect())
+        })
+        .await
+    }
+
+    /// `accountSubscribe` to every account (base64, at `COMMITMENT`). The stream
+    /// ends when the pubsub connection drops.
+    pub async fn subscribe_accounts(
+        &self,
+        venue: &str,
+        ws_url: &str,
+        accounts: &[Pubkey],
+    ) -> anyhow::Result<BoxStream<'static, AccountUpdate>> {
+        let open = open_account_stream(ws_url.to_string(), accounts.to_vec());
+        self.subscribe(venue, "account_update", open).await
+    }
+}
+
+/// The pubsub client lives in its own task since its streams borrow it; setup errors
+/// come back before the stream is handed out
+async fn open_account_stream(
+    ws_url: String,
+    accounts: Vec<Pubkey>,
+) -> anyhow::Result<BoxStream<'static, AccountUpdate>> {
+    let client = PubsubClient::new(ws_url.as_str()).await?;
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let (tx, rx) = mpsc::channel(UPDATE_BUFFER);
+
+    tokio::spawn(async move {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(COMMITMENT),
+            ..Default::default()
+        };
+
+        let mut subscriptions = Vec::with_capacity(accounts.len());
+        for (index, account) in accounts.iter().enumerate() {
+            match client
+                .account_subscribe(account, Some(config.clone()))
+                .await
+            {
+                Ok((notifications, _unsubscribe)) => {
+                    subscriptions.push(notifications.map(move |n| (index, n)));
+                }
+                Err(e) => {
+                    let _ =
+                        ready_tx.send(Err(anyhow::anyhow!("accountSubscribe {}: {}", account, e)));
+                    return;
+                }
+            }
+        }
+        let _ = ready_tx.send(Ok(()));
+
+        let mut notifications = stream::select_all(subscriptions);
+        loop {
+            tokio::select! {
+                notification = notifications.next() => {
+                    let Some((index, response)) = notification else { break };
+                    let Some(data) = response.value.data.decode() else {
+                        continue;
+                    };
+                    let update = AccountUpdate { index, slot: response.context.slot, data };
+                    if tx.send(update).await.is_err() {
+                        break;
+                    }
+                }
+                // The connector stopped listening
+                _ = tx.closed() => break,
+            }
+        }
+        drop(notifications);
+        let _ = client.shutdown().await;
+    });
+
+    ready_rx.await??;
+    let updates = stream::unfold(rx, |mut rx| async move {
+        let update = rx.recv().await?;
+        Some((update, rx))
+    });
+    Ok(updates.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pubsub_endpoint_follows_the_rpc_endpoint() {
+        let config = Config::default();
+        assert_eq!(
+            solana_endpoints(&config, "Orca").1,
+            "wss://api.mainnet-beta.solana.com"
+        );
+
+        let config = Config::from_toml(
+            r#"
+            [rpc]
+            solana_ws_url = "wss://stream.example.com"
+
+            [venues.raydium]
+            url = "http://127.0.0.1:8899"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            solana_endpoints(&config, "Raydium"),
+            (
+                "http://127.0.0.1:8899".to_string(),
+                "ws://127.0.0.1:8899".to_string()
+            )
+        );
+        assert_eq!(
+            solana_endpoints(&config, "Orca").1,
+            "wss://stream.example.com"
+        );
+    }
+}
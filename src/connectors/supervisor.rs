@@ -0,0 +1,104 @@
+use super::ConnectorContext;
+use rand::Rng;
+use std::future::Future;
+use tokio::time::{Duration, Instant, sleep};
+
+/// First retry delay after a dropped connection
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// Upper bound for the retry delay
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// A session that stayed up this long resets the backoff
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+/// Capped exponential backoff with "equal jitter": half of the delay is fixed,
+/// the other half is random so venues don't all reconnect in lock-step.
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            attempt: 0,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let exp = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = exp / 2;
+        let jitter_ms = rand::rng().random_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_ms)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(BASE_DELAY, MAX_DELAY)
+    }
+}
+
+impl ConnectorContext {
+    /// Keep a venue session alive for the lifetime of the connector.
+    ///
+    /// `session` connects, (re)subscribes and reads until the socket drops.
+    /// Every drop is recorded on the venue monitor and followed by a backoff
+    /// sleep before the next session starts. Returns once the context is
+    /// cancelled or the price channel has no receivers left.
+    pub async fn supervise<F, Fut>(&self, venue: &str, mut session: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let mut backoff = Backoff::default();
+
+        loop {
+            // Nobody left to feed: the pair was torn down
+            if self.cancel.is_cancelled() || self.tx.receiver_count() == 0 {
+                return;
+            }
+
+            let started = Instant::now();
+            let result = session().await;
+
+            let error = result.err().map(|e| e.to_string());
+            self.monitor.disconnected(venue, error.clone());
+            // The next session rebuilds the book from a fresh snapshot
+            self.books.remove(venue, &self.pair);
+
+            if started.elapsed() >= STABLE_SESSION {
+                backoff.reset();
+            }
+
+            let delay = backoff.next_delay();
+            match error {
+                Some(e) => eprintln!(
+                    "{} {} error: {}. Reconnecting in {:?}",
+                    venue, self.pair, e, delay
+                ),
+                None => eprintln!(
+                    "{} {} disconnected. Reconnecting in {:?}",
+                    venue, self.pair, delay
+                ),
+            }
+
+            tokio::select! {
+                _ = self.cancel.cancelled() => return,
+                _ = sleep(delay) => {}
+            }
+            self.monitor.reconnecting(venue);
+        }
+    }
+}
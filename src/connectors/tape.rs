@@ -0,0 +1,538 @@
+use super::{ConnectorContext, now_ms};
+use anyhow::Context as _;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures_util::stream::{self, BoxStream};
+use futures_util::{Sink, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant, sleep_until};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+// --- Frames ---
+
+/// One raw inbound message of a venue, as written to a tape (one JSON object per line)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub received: u64, // Unix ms
+    pub venue: String,
+    pub pair: String,
+    #[serde(flatten)]
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Payload {
+    Text {
+        data: String,
+    },
+    Binary {
+        data: String,
+    }, // Base64
+    Close,
+    /// Result of an HTTP or RPC call, labelled by the connector
+    Response {
+        call: String,
+        data: serde_json::Value,
+    },
+}
+
+impl Payload {
+    fn is_socket(&self) -> bool {
+        !matches!(self, Payload::Response { .. })
+    }
+}
+
+// --- Tape ---
+
+/// Where raw venue traffic goes: recorded to a file, or replayed from one instead of the network
+pub enum Tape {
+    Record(Mutex<LineWriter<File>>),
+    Replay(Replay),
+}
+
+impl Tape {
+    /// `RECORD_FILE` records every venue's traffic, `REPLAY_FILE` replays it
+    pub fn from_env() -> anyhow::Result<Option<Arc<Self>>> {
+        if let Ok(path) = std::env::var("REPLAY_FILE") {
+            let tape = Self::replay(&path).with_context(|| format!("Replaying {}", path))?;
+            return Ok(Some(Arc::new(tape)));
+        }
+        if let Ok(path) = std::env::var("RECORD_FILE") {
+            let tape = Self::record(&path).with_context(|| format!("Recording to {}", path))?;
+            return Ok(Some(Arc::new(tape)));
+        }
+        Ok(None)
+    }
+
+    pub fn record(path: &str) -> anyhow::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Tape::Record(Mutex::new(LineWriter::new(file))))
+    }
+
+    pub fn replay(path: &str) -> anyhow::Result<Self> {
+        Replay::load(BufReader::new(File::open(path)?)).map(Tape::Replay)
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Tape::Replay(_))
+    }
+
+    /// A replay has handed out every recorded frame
+    pub fn is_drained(&self) -> bool {
+        matches!(self, Tape::Replay(replay) if replay.is_drained())
+    }
+
+    fn write(&self, venue: &str, pair: &str, payload: Payload) {
+        let Tape::Record(file) = self else {
+            return;
+        };
+
+        let frame = Frame {
+            received: now_ms(),
+            venue: venue.to_string(),
+            pair: pair.to_string(),
+            payload,
+        };
+        if let Ok(line) = serde_json::to_string(&frame)
+            && let Err(e) = writeln!(file.lock().unwrap(), "{}", line)
+        {
+            eprintln!("TAPE Failed to record {} frame: {}", venue, e);
+        }
+    }
+}
+
+/// Recorded frames per (venue, pair), handed out at the pace they were received
+pub struct Replay {
+    frames: Mutex<HashMap<(String, String), VecDeque<Frame>>>,
+    first_received: u64,
+    started: Mutex<Option<Instant>>,
+}
+
+impl Replay {
+    pub fn load(reader: impl BufRead) -> anyhow::Result<Self> {
+        let mut frames: HashMap<(String, String), VecDeque<Frame>> = HashMap::new();
+        let mut first_received = u64::MAX;
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: Frame = serde_json::from_str(&line)?;
+            first_received = first_received.min(frame.received);
+            frames
+                .entry((frame.venue.clone(), frame.pair.clone()))
+                .or_default()
+                .push_back(frame);
+        }
+
+        Ok(Self {
+            frames: Mutex::new(frames),
+            first_received,
+            started: Mutex::new(None),
+        })
+    }
+
+    /// Every frame has been handed out
+    pub fn is_drained(&self) -> bool {
+        self.frames.lock().unwrap().values().all(VecDeque::is_empty)
+    }
+
+    /// Some frame of `venue`/`pair` is accepted by `wanted`
+    fn has(&self, venue: &str, pair: &str, wanted: impl Fn(&Payload) -> bool) -> bool {
+        self.frames
+            .lock()
+            .unwrap()
+            .get(&(venue.to_string(), pair.to_string()))
+            .is_some_and(|queue| queue.iter().any(|f| wanted(&f.payload)))
+    }
+
+    /// Next frame of `venue`/`pair` accepted by `wanted`, once its receive time comes up
+    /// on the replay clock. Never resolves when there is none left.
+    async fn next(&self, venue: &str, pair: &str, wanted: impl Fn(&Payload) -> bool) -> Frame {
+        let frame = {
+            let mut frames = self.frames.lock().unwrap();
+            frames
+                .get_mut(&(venue.to_string(), pair.to_string()))
+                .and_then(|queue| {
+                    let index = queue.iter().position(|f| wanted(&f.payload))?;
+                    queue.remove(index)
+                })
+        };
+        let Some(frame) = frame else {
+            return std::future::pending().await;
+        };
+
+        let started = *self
+            .started
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+        let offset = Duration::from_millis(frame.received.saturating_sub(self.first_received));
+        sleep_until(started + offset).await;
+
+        frame
+    }
+}
+
+// --- Venue Socket ---
+
+type LiveStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A venue WebSocket: live (recording inbound frames when a tape records) or replayed
+pub struct VenueSocket {
+    inner: Inner,
+}
+
+enum Inner {
+    Live {
+        ws: Box<LiveStream>,
+        recorder: Option<(Arc<Tape>, String, String)>,
+    },
+    // Outbound messages (subscriptions, pings) are dropped
+    Replay(BoxStream<'static, Result<Message, WsError>>),
+}
+
+impl Stream for VenueSocket {
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.inner {
+            Inner::Live { ws, recorder } => {
+                let item = std::task::ready!(Pin::new(ws.as_mut()).poll_next(cx));
+                if let (Some((tape, venue, pair)), Some(Ok(msg))) = (recorder, &item) {
+                    let payload = match msg {
+                        Message::Text(text) => Some(Payload::Text {
+                            data: text.to_string(),
+                        }),
+                        Message::Binary(bin) => Some(Payload::Binary {
+                            data: BASE64.encode(bin),
+                        }),
+                        Message::Close(_) => Some(Payload::Close),
+                        _ => None,
+                    };
+                    if let Some(payload) = payload {
+                        tape.write(venue, pair, payload);
+                    }
+                }
+                Poll::Ready(item)
+            }
+            Inner::Replay(frames) => frames.as_mut().poll_next(cx),
+        }
+    }
+}
+
+impl Sink<Message> for VenueSocket {
+    type Error = WsError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        match &mut self.inner {
+            Inner::Live { ws, .. } => Pin::new(ws.as_mut()).poll_ready(cx),
+            Inner::Replay(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), WsError> {
+        match &mut self.inner {
+            Inner::Live { ws, .. } => Pin::new(ws.as_mut()).start_send(item),
+            Inner::Replay(_) => Ok(()),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        match &mut self.inner {
+            Inner::Live { ws, .. } => Pin::new(ws.as_mut()).poll_flush(cx),
+            Inner::Replay(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        match &mut self.inner {
+            Inner::Live { ws, .. } => Pin::new(ws.as_mut()).poll_close(cx),
+            Inner::Replay(_) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+// --- Context Helpers ---
+
+impl ConnectorContext {
+    /// Open the venue's WebSocket, or its recorded frames when replaying
+    pub async fn connect_ws(&self, venue: &str, url: &str) -> anyhow::Result<VenueSocket> {
+        if let Some(tape) = &self.tape
+            && tape.is_replay()
+        {
+            let state = (Arc::clone(tape), venue.to_string(), self.pair.clone());
+            let frames = stream::unfold(state, |(tape, venue, pair)| async move {
+                let Tape::Replay(replay) = tape.as_ref() else {
+                    return None;
+                };
+                let frame = replay.next(&venue, &pair, Payload::is_socket).await;
+                let msg = match frame.payload {
+                    Payload::Text { data } => Ok(Message::Text(data.into())),
+                    Payload::Binary { data } => match BASE64.decode(data) {
+                        Ok(bin) => Ok(Message::Binary(bin.into())),
+                        Err(e) => Err(WsError::Io(std::io::Error::other(e))),
+                    },
+                    _ => Ok(Message::Close(None)),
+                };
+                Some((msg, (tape, venue, pair)))
+            });
+
+            return Ok(VenueSocket {
+                inner: Inner::Replay(frames.boxed()),
+            });
+        }
+
+        let (ws, _) = connect_async(url).await?;
+        let recorder = self
+            .tape
+            .clone()
+            .map(|tape| (tape, venue.to_string(), self.pair.clone()));
+
+        Ok(VenueSocket {
+            inner: Inner::Live {
+                ws: Box::new(ws),
+                recorder,
+            },
+        })
+    }
+
+    /// Run an HTTP/RPC call through the tape: recorded when recording, answered from the
+    /// tape (by `call` label, in order) when replaying
+    pub async fn fetch<T, F>(&self, venue: &str, call: &str, request: F) -> anyhow::Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let Some(tape) = &self.tape else {
+            return request.await;
+        };
+
+        if let Tape::Replay(replay) = tape.as_ref() {
+            let wanted = |p: &Payload| matches!(p, Payload::Response { call: c, .. } if c == call);
+            let Payload::Response { data, .. } =
+                replay.next(venue, &self.pair, wanted).await.payload
+            else {
+                unreachable!("replay handed out a frame that was not asked for");
+            };
+            return Ok(serde_json::from_value(data)?);
+        }
+
+        let result = request.await?;
+        tape.write(
+            venue,
+            &self.pair,
+            Payload::Response {
+                call: call.to_string(),
+                data: serde_json::to_value(&result)?,
+            },
+        );
+        Ok(result)
+    }
+
+    /// Run a subscription through the tape: each item is recorded under `call` when
+    /// recording, and the recorded items come back when replaying. A replay without any
+    /// such item fails, so the connector falls back to its polled calls.
+    pub async fn subscribe<T, F>(
+        &self,
+        venue: &str,
+        call: &str,
+        open: F,
+    ) -> anyhow::Result<BoxStream<'static, T>>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Future<Output = anyhow::Result<BoxStream<'static, T>>>,
+    {
+        let Some(tape) = &self.tape else {
+            return open.await;
+        };
+        let call = call.to_string();
+
+        if let Tape::Replay(replay) = tape.as_ref() {
+            let wanted =
+                move |p: &Payload| matches!(p, Payload::Response { call: c, .. } if *c == call);
+            if !replay.has(venue, &self.pair, &wanted) {
+                anyhow::bail!("no {} subscription recorded", venue);
+            }
+            let state = (Arc::clone(tape), venue.to_string(), self.pair.clone());
+            let items = stream::unfold(state, move |(tape, venue, pair)| {
+                let wanted = wanted.clone();
+                async move {
+                    let Tape::Replay(replay) = tape.as_ref() else {
+                        return None;
+                    };
+                    let Payload::Response { data, .. } =
+                        replay.next(&venue, &pair, wanted).await.payload
+                    else {
+                        return None;
+                    };
+                    let item = serde_json::from_value(data).ok()?;
+                    Some((item, (tape, venue, pair)))
+                }
+            });
+            return Ok(items.boxed());
+        }
+
+        let (tape, venue, pair) = (Arc::clone(tape), venue.to_string(), self.pair.clone());
+        let items = open.await?.inspect(move |item| {
+            if let Ok(data) = serde_json::to_value(item) {
+                let call = call.clone();
+                tape.write(&venue, &pair, Payload::Response { call, data });
+            }
+        });
+        Ok(items.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetRegistry;
+    use crate::connectors::{Connector, HtxConnector, KrakenConnector, OrcaConnector};
+    use crate::fees::FeeModel;
+    use crate::orderbook::BookStore;
+    use crate::state::{PriceUpdate, VenueMonitor};
+    use tokio::sync::broadcast;
+    use tokio::time::timeout;
+    use tokio_util::sync::CancellationToken;
+
+    struct Replayed {
+        updates: Vec<PriceUpdate>,
+        monitor: Arc<VenueMonitor>,
+        fees: Arc<FeeModel>,
+        tape: Arc<Tape>,
+    }
+
+    /// Run `connector` for `pair` against a recorded tape until `count` updates came out
+    async fn replay(
+        connector: impl Connector,
+        pair: &str,
+        fixture: &str,
+        count: usize,
+    ) -> Replayed {
+        let path = format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), fixture);
+        let tape = Arc::new(Tape::replay(&path).unwrap());
+        let (tx, mut rx) = broadcast::channel(100);
+        let cancel = CancellationToken::new();
+        let monitor = Arc::new(VenueMonitor::default());
+        let fees = Arc::new(FeeModel::default());
+        let assets = Arc::new(AssetRegistry::builtin());
+
+        let task = tokio::spawn(connector.run(ConnectorContext {
+            tx,
+            pair: pair.to_string(),
+            symbol: connector.symbol(&assets, pair).unwrap_or_default(),
+            cancel: cancel.clone(),
+            monitor: Arc::clone(&monitor),
+            books: Arc::new(BookStore::default()),
+            fees: Arc::clone(&fees),
+            assets,
+            tape: Some(Arc::clone(&tape)),
+        }));
+
+        let mut updates = Vec::new();
+        while updates.len() < count {
+            let update = timeout(Duration::from_secs(5), rx.recv()).await;
+            updates.push(update.expect("replay stalled").unwrap());
+        }
+        cancel.cancel();
+        task.await.unwrap();
+
+        Replayed {
+            updates,
+            monitor,
+            fees,
+            tape,
+        }
+    }
+
+    fn quotes(updates: &[PriceUpdate]) -> Vec<(f64, f64)> {
+        updates.iter().map(|u| (u.bid, u.ask)).collect()
+    }
+
+    #[test]
+    fn frames_round_trip_through_the_tape_format() {
+        let frame = Frame {
+            received: 1_700_000_000_000,
+            venue: "HTX".into(),
+            pair: "SOL/USDT".into(),
+            payload: Payload::Binary {
+                data: BASE64.encode([0x1f, 0x8b]),
+            },
+        };
+        let line = serde_json::to_string(&frame).unwrap();
+        assert_eq!(
+            line,
+            r#"{"received":1700000000000,"venue":"HTX","pair":"SOL/USDT","kind":"binary","data":"H4s="}"#
+        );
+
+        let replay = Replay::load(format!("{}\n\n", line).as_bytes()).unwrap();
+        assert!(!replay.is_drained());
+    }
+
+    #[tokio::test]
+    async fn kraken_replay_checks_the_book_and_resyncs_on_a_bad_checksum() {
+        let replayed = replay(
+            KrakenConnector::default(),
+            "SOL/USD",
+            "kraken_sol_usd.jsonl",
+            3,
+        )
+        .await;
+
+        assert_eq!(
+            quotes(&replayed.updates),
+            vec![(150.0, 150.1), (150.0, 150.05), (150.5, 151.0)]
+        );
+        assert_eq!(replayed.updates[1].timestamp, 1_700_000_001_000);
+
+        assert_eq!(replayed.monitor.snapshot()["Kraken"].reconnects, 1);
+        assert!(replayed.tape.is_drained());
+    }
+
+    #[tokio::test]
+    async fn htx_replay_decodes_gzip_frames_and_skips_heartbeats() {
+        let replayed = replay(HtxConnector::default(), "SOL/USDT", "htx_sol_usdt.jsonl", 2).await;
+
+        assert_eq!(
+            quotes(&replayed.updates),
+            vec![(150.1, 150.12), (150.11, 150.15)]
+        );
+        // Quote time first, then the envelope timestamp
+        let timestamps: Vec<u64> = replayed.updates.iter().map(|u| u.timestamp).collect();
+        assert_eq!(timestamps, vec![1_700_000_000_004, 1_700_000_000_008]);
+        assert_eq!(replayed.updates[0].bid_size, Some(2.25));
+    }
+
+    #[tokio::test]
+    async fn orca_replay_decodes_recorded_pool_accounts() {
+        let replayed = replay(
+            OrcaConnector::default(),
+            "SOL/USDC",
+            "orca_sol_usdc.jsonl",
+            2,
+        )
+        .await;
+
+        let prices: Vec<f64> = replayed.updates.iter().map(|u| u.price).collect();
+        assert!((prices[0] - 150.0).abs() < 1e-6, "{:?}", prices);
+        assert!((prices[1] - 151.5).abs() < 1e-6, "{:?}", prices);
+        assert_eq!(replayed.updates[0].native_quote, "USDC");
+        let slots: Vec<Option<u64>> = replayed.updates.iter().map(|u| u.slot).collect();
+        assert_eq!(slots, vec![Some(250_000_000), Some(250_000_002)]);
+        // fee_rate 400 is in hundredths of a bps
+        assert_eq!(replayed.fees.taker_bps("Orca", "SOL/USDC"), 4.0);
+    }
+}
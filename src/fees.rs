@@ -0,0 +1,198 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Volume tier: applies once the 30-day volume reaches `min_volume`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub min_volume: f64,
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+}
+
+/// Fee schedule of one venue. `maker_bps`/`taker_bps` are the base tier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+    #[serde(default)]
+    pub tiers: Vec<FeeTier>,
+    /// Our 30-day volume on the venue, selects the tier
+    #[serde(default)]
+    pub volume_30d: f64,
+}
+
+impl FeeSchedule {
+    pub fn flat(maker_bps: f64, taker_bps: f64) -> Self {
+        Self {
+            maker_bps,
+            taker_bps,
+            tiers: Vec::new(),
+            volume_30d: 0.0,
+        }
+    }
+
+    /// (maker, taker) bps for the highest tier our volume qualifies for
+    pub fn effective(&self) -> (f64, f64) {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.min_volume <= self.volume_30d)
+            .max_by(|a, b| a.min_volume.total_cmp(&b.min_volume))
+            .map_or((self.maker_bps, self.taker_bps), |tier| {
+                (tier.maker_bps, tier.taker_bps)
+            })
+    }
+}
+
+/// Effective fees of a venue as served over the API
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueFees {
+    pub maker_bps: f64,
+    pub taker_bps: f64,
+    /// Pool fee read on-chain, per pair (DEX venues only)
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub pool_fee_bps: HashMap<String, f64>,
+}
+
+/// Per-venue fee schedules plus pool fees reported by the DEX connectors
+pub struct FeeModel {
+    schedules: HashMap<String, FeeSchedule>,
+    pool_fees: Mutex<HashMap<(String, String), f64>>,
+}
+
+impl FeeModel {
+    /// No schedules, so every venue trades free until one is set. Real fees come
+    /// from `with_defaults` / `from_config`.
+    pub fn empty() -> Self {
+        Self {
+            schedules: HashMap::new(),
+            pool_fees: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Published base-tier spot fees of the built-in venues. DEX entries are the
+    /// usual pool fee, used until the pool account has been read.
+    pub fn with_defaults() -> Self {
+        let mut model = Self::empty();
+        model.set_schedule("Binance", FeeSchedule::flat(10.0, 10.0));
+        model.set_schedule("Backpack", FeeSchedule::flat(8.0, 10.0));
+        model.set_schedule("Bitfinex", FeeSchedule::flat(10.0, 20.0));
+        model.set_schedule("Bitget", FeeSchedule::flat(10.0, 10.0));
+        model.set_schedule("Bybit", FeeSchedule::flat(10.0, 10.0));
+        model.set_schedule("Coinbase", FeeSchedule::flat(40.0, 60.0));
+        model.set_schedule("HTX", FeeSchedule::flat(20.0, 20.0));
+        model.set_schedule("Jupiter", FeeSchedule::flat(0.0, 0.0)); // Quoted prices already include route fees
+        model.set_schedule("Kraken", FeeSchedule::flat(25.0, 40.0));
+        model.set_schedule("KuCoin", FeeSchedule::flat(10.0, 10.0));
+        model.set_schedule("OKX", FeeSchedule::flat(8.0, 10.0));
+        model.set_schedule("Raydium", FeeSchedule::flat(25.0, 25.0));
+        model.set_schedule("Orca", FeeSchedule::flat(30.0, 30.0));
+        model.set_schedule("Bitstamp", FeeSchedule::flat(30.0, 40.0));
+        model
+    }
+
+    /// Built-in schedules, overridden per venue by the `[fees]` configuration
+    pub fn from_config(config: &Config) -> Self {
+        let mut model = Self::with_defaults();
+        for (venue, schedule) in &config.fees {
+            model.set_schedule(venue, schedule.clone());
+        }
+        model
+    }
+
+    pub fn set_schedule(&mut self, venue: &str, schedule: FeeSchedule) {
+        self.schedules.insert(venue.to_lowercase(), schedule);
+    }
+
+    /// Record the fee read from a pool account; takes precedence over the schedule
+    pub fn set_pool_fee(&self, venue: &str, pair: &str, fee_bps: f64) {
+        self.pool_fees
+            .lock()
+            .unwrap()
+            .insert((venue.to_lowercase(), pair.to_string()), fee_bps);
+    }
+
+    /// Fee paid for taking liquidity on `venue` (crossing the book or swapping in the pool)
+    pub fn taker_bps(&self, venue: &str, pair: &str) -> f64 {
+        let venue = venue.to_lowercase();
+
+        if let Some(fee) = self
+            .pool_fees
+            .lock()
+            .unwrap()
+            .get(&(venue.clone(), pair.to_string()))
+        {
+            return *fee;
+        }
+
+        self.schedules
+            .get(&venue)
+            .map_or(0.0, |schedule| schedule.effective().1)
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, VenueFees> {
+        let pool_fees = self.pool_fees.lock().unwrap();
+
+        self.schedules
+            .iter()
+            .map(|(venue, schedule)| {
+                let (maker_bps, taker_bps) = schedule.effective();
+                let pool_fee_bps = pool_fees
+                    .iter()
+                    .filter(|((v, _), _)| v == venue)
+                    .map(|((_, pair), fee)| (pair.clone(), *fee))
+                    .collect();
+
+                (
+                    venue.clone(),
+                    VenueFees {
+                        maker_bps,
+                        taker_bps,
+                        pool_fee_bps,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for FeeModel {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_fee_takes_precedence_over_the_schedule() {
+        let mut model = FeeModel::empty();
+        model.set_schedule(
+            "Orca",
+            FeeSchedule {
+                tiers: vec![FeeTier {
+                    min_volume: 1_000.0,
+                    maker_bps: 20.0,
+                    taker_bps: 25.0,
+                }],
+                volume_30d: 5_000.0,
+                ..FeeSchedule::flat(30.0, 30.0)
+            },
+        );
+        // The tier our volume reaches
+        assert_eq!(model.taker_bps("Orca", "SOL/USDC"), 25.0);
+
+        model.set_pool_fee("ORCA", "SOL/USDC", 4.0);
+        assert_eq!(model.taker_bps("orca", "SOL/USDC"), 4.0);
+        // Other pools of the venue keep the schedule
+        assert_eq!(model.taker_bps("Orca", "SOL/USDT"), 25.0);
+        assert_eq!(model.taker_bps("Unknown", "SOL/USDC"), 0.0);
+
+        let fees = &model.snapshot()["orca"];
+        assert_eq!((fees.maker_bps, fees.taker_bps), (20.0, 25.0));
+        assert_eq!(fees.pool_fee_bps["SOL/USDC"], 4.0);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::arbitrage_engine::ArbitrageFeed;
+use crate::hub::ConnectorHub;
+use crate::state::{PriceUpdate, quote_currency};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Stablecoin/fiat markets streamed to price conversions between quote currencies
+pub const FX_PAIRS: [&str; 3] = ["USDT/USD", "USDC/USD", "USDC/USDT"];
+
+/// Conversion rates between quote currencies, taken from the venues' own FX markets
+#[derive(Default)]
+pub struct FxTracker {
+    rates: Mutex<HashMap<(String, String), f64>>, // (base, quote) -> quote units per base unit
+}
+
+impl FxTracker {
+    /// Follow every FX pair through the hub for the lifetime of the process
+    pub fn spawn(self: &Arc<Self>, hub: &Arc<ConnectorHub>) {
+        for pair in FX_PAIRS {
+            let tracker = Arc::clone(self);
+            let mut subscription = hub.subscribe(pair);
+
+            tokio::spawn(async move {
+                while let Some(feed) = subscription.recv().await {
+                    if let Some(rate) = median_mid(&feed) {
+                        tracker.set_rate(pair, rate);
+                    }
+                }
+            });
+        }
+    }
+
+    pub fn set_rate(&self, pair: &str, rate: f64) {
+        if let Some((base, quote)) = pair.split_once('/') {
+            self.rates
+                .lock()
+                .unwrap()
+                .insert((base.to_string(), quote.to_string()), rate);
+        }
+    }
+
+    /// Units of `to` per unit of `from`: direct, inverted, or through one intermediate currency
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        let rates = self.rates.lock().unwrap();
+        let direct = |a: &str, b: &str| {
+            rates
+                .get(&(a.to_string(), b.to_string()))
+                .copied()
+                .or_else(|| rates.get(&(b.to_string(), a.to_string())).map(|r| 1.0 / r))
+        };
+
+        direct(from, to).or_else(|| {
+            rates
+                .keys()
+                .flat_map(|(a, b)| [a, b])
+                .filter(|via| via.as_str() != from && via.as_str() != to)
+                .find_map(|via| Some(direct(from, via)? * direct(via, to)?))
+        })
+    }
+
+    /// Express `update` in its pair's quote currency. `None` while the needed
+    /// rate is unknown, since comparing USD and USDT prices 1:1 is what this avoids.
+    pub fn normalize(&self, mut update: PriceUpdate) -> Option<PriceUpdate> {
+        let requested = quote_currency(&update.pair).to_string();
+        if update.native_quote == requested {
+            return Some(update);
+        }
+
+        let rate = self.rate(&update.native_quote, &requested)?;
+        update.convert(rate);
+        Some(update)
+    }
+}
+
+/// Median mid of the venues that are still fresh
+fn median_mid(feed: &ArbitrageFeed) -> Option<f64> {
+    let mut mids: Vec<f64> = feed
+        .prices
+        .iter()
+        .filter(|p| !feed.stale_sources.contains(&p.source))
+        .map(|p| p.price)
+        .collect();
+    if mids.is_empty() {
+        return None;
+    }
+    mids.sort_by(f64::total_cmp);
+    Some(mids[mids.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PoolPrice;
+
+    fn tracker(rates: &[(&str, f64)]) -> FxTracker {
+        let tracker = FxTracker::default();
+        for (pair, rate) in rates {
+            tracker.set_rate(pair, *rate);
+        }
+        tracker
+    }
+
+    fn close(a: Option<f64>, b: f64) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-12)
+    }
+
+    #[test]
+    fn rates_are_direct_inverted_or_through_one_currency() {
+        let fx = tracker(&[("USDT/USD", 0.998), ("USDC/USD", 1.0005)]);
+
+        assert_eq!(fx.rate("USDT", "USDT"), Some(1.0));
+        assert_eq!(fx.rate("USDT", "USD"), Some(0.998));
+        assert!(close(fx.rate("USD", "USDT"), 1.0 / 0.998));
+        // USDC -> USD -> USDT
+        assert!(close(fx.rate("USDC", "USDT"), 1.0005 / 0.998));
+        assert!(close(fx.rate("USDT", "USDC"), 0.998 / 1.0005));
+        assert_eq!(fx.rate("EUR", "USD"), None);
+
+        // A direct market wins over the path through USD
+        fx.set_rate("USDC/USDT", 1.001);
+        assert_eq!(fx.rate("USDC", "USDT"), Some(1.001));
+    }
+
+    #[test]
+    fn rate_is_the_median_of_fresh_venues() {
+        let feed = |prices: &[(&str, f64)], stale: &[&str]| ArbitrageFeed {
+            prices: prices
+                .iter()
+                .map(|(venue, price)| PriceUpdate::single(venue, "USDT/USD", *price, 1))
+                .collect(),
+            opportunity: None,
+            venues: HashMap::new(),
+            stale_sources: stale.iter().map(|s| s.to_string()).collect(),
+        };
+
+        let venues = [("Kraken", 0.999), ("Coinbase", 1.0), ("Bitstamp", 0.9)];
+        assert_eq!(median_mid(&feed(&venues, &[])), Some(0.999));
+        // Without Kraken the upper of the two is taken
+        assert_eq!(median_mid(&feed(&venues, &["Kraken"])), Some(1.0));
+        assert_eq!(median_mid(&feed(&venues[..1], &["Kraken"])), None);
+    }
+
+    #[test]
+    fn normalize_waits_for_a_rate_then_converts_every_price() {
+        let fx = FxTracker::default();
+        let mut update = PriceUpdate::quote("Kraken", "SOL/USDT", 150.0, 2.0, 151.0, 3.0, 1);
+        update.native_quote = "USD".to_string();
+        update.pools = vec![PoolPrice {
+            address: "pool".to_string(),
+            price: 150.5,
+            liquidity: 1_000.0,
+        }];
+
+        assert!(fx.normalize(update.clone()).is_none());
+
+        // Already in the requested quote: untouched
+        let native = PriceUpdate::single("Binance", "SOL/USDT", 150.0, 1);
+        assert_eq!(fx.normalize(native).unwrap().fx_rate, 1.0);
+
+        fx.set_rate("USDT/USD", 0.5);
+        let converted = fx.normalize(update).unwrap();
+        assert_eq!(
+            (converted.price, converted.bid, converted.ask),
+            (301.0, 300.0, 302.0)
+        );
+        assert_eq!(converted.pools[0].price, 301.0);
+        assert_eq!(converted.pools[0].liquidity, 2_000.0);
+        assert_eq!(converted.fx_rate, 2.0);
+        // Still says what the venue priced in, and sizes are in the base currency
+        assert_eq!(converted.native_quote, "USD");
+        assert_eq!(
+            (converted.bid_size, converted.ask_size),
+            (Some(2.0), Some(3.0))
+        );
+    }
+}
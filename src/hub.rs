@@ -0,0 +1,472 @@
+use crate::arbitrage_engine::{ArbitrageEngine, ArbitrageFeed, EngineConfig};
+use crate::connectors::{ConnectorContext, ConnectorRegistry};
+use crate::fees::FeeModel;
+use crate::fx::FxTracker;
+use crate::orderbook::BookStore;
+use crate::persistence::Persistence;
+use crate::state::{MarketCache, PriceUpdate, VenueMonitor, VenueStatus};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinSet;
+use tokio::time::{Duration, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// How long connectors get to wind down before their tasks are aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+// --- Per-Pair Feed ---
+
+/// One running set of connectors + engine, shared by every client watching the pair
+struct PairFeed {
+    feed_tx: broadcast::Sender<ArbitrageFeed>,
+    price_tx: broadcast::Sender<PriceUpdate>, // Every venue price, after FX normalisation
+    latest: Arc<Mutex<Option<ArbitrageFeed>>>, // Last feed the engine produced
+    monitor: Arc<VenueMonitor>,
+    subscribers: usize,
+    cancel: CancellationToken,
+    tasks: JoinSet<()>,
+}
+
+impl PairFeed {
+    /// Cancel every task of the pair, then abort whatever hasn't finished after the grace period
+    fn shutdown(self) {
+        let PairFeed {
+            cancel, mut tasks, ..
+        } = self;
+        cancel.cancel();
+
+        tokio::spawn(async move {
+            let drained = timeout(SHUTDOWN_GRACE, async {
+                while tasks.join_next().await.is_some() {}
+            })
+            .await;
+
+            if drained.is_err() {
+                eprintln!(
+                    "HUB Aborting {} connector tasks after grace period",
+                    tasks.len()
+                );
+                tasks.shutdown().await;
+            }
+        });
+    }
+}
+
+// --- Hub ---
+
+/// Pair-keyed hub: starts connectors on the first subscriber of a pair,
+/// fans the resulting `ArbitrageFeed` out to every subscriber and tears
+/// the connectors down when the last one leaves.
+pub struct ConnectorHub {
+    registry: Arc<ConnectorRegistry>,
+    cache: Arc<Mutex<MarketCache>>,
+    books: Arc<BookStore>,
+    fees: Arc<FeeModel>,
+    fx: Arc<FxTracker>,
+    engine_config: EngineConfig,
+    persistence: Option<Arc<Persistence>>,
+    feeds: Mutex<HashMap<String, PairFeed>>,
+}
+
+impl ConnectorHub {
+    pub fn new(
+        registry: Arc<ConnectorRegistry>,
+        cache: Arc<Mutex<MarketCache>>,
+        books: Arc<BookStore>,
+        fees: Arc<FeeModel>,
+        fx: Arc<FxTracker>,
+        engine_config: EngineConfig,
+        persistence: Option<Arc<Persistence>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            registry,
+            cache,
+            books,
+            fees,
+            fx,
+            engine_config,
+            persistence,
+            feeds: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Join the feed for `pair`, spawning its connectors if nobody is watching it yet
+    pub fn subscribe(self: &Arc<Self>, pair: &str) -> Subscription {
+        let mut feeds = self.feeds.lock().unwrap();
+
+        let feed = feeds.entry(pair.to_string()).or_insert_with(|| {
+            println!("HUB Starting connectors for {}", pair);
+            self.spawn_pair(pair)
+        });
+        feed.subscribers += 1;
+
+        Subscription {
+            hub: Arc::clone(self),
+            pair: pair.to_string(),
+            rx: feed.feed_tx.subscribe(),
+        }
+    }
+
+    /// Join the feed for `pair` like `subscribe`, receiving each venue price as it
+    /// arrives rather than the engine's feeds
+    pub fn subscribe_prices(self: &Arc<Self>, pair: &str) -> PriceSubscription {
+        let membership = self.subscribe(pair);
+        let rx = self.feeds.lock().unwrap()[pair].price_tx.subscribe();
+        PriceSubscription { membership, rx }
+    }
+
+    /// Pairs currently streamed, with their subscriber count
+    pub fn pairs(&self) -> Vec<(String, usize)> {
+        self.feeds
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pair, feed)| (pair.clone(), feed.subscribers))
+            .collect()
+    }
+
+    /// Last feed of a streamed pair, `None` if nobody watches it or no feed was produced yet
+    pub fn latest(&self, pair: &str) -> Option<ArbitrageFeed> {
+        let feeds = self.feeds.lock().unwrap();
+        feeds.get(pair)?.latest.lock().unwrap().clone()
+    }
+
+    /// Connection state per venue, per streamed pair
+    pub fn venue_status(&self) -> HashMap<String, HashMap<String, VenueStatus>> {
+        self.feeds
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pair, feed)| (pair.clone(), feed.monitor.snapshot()))
+            .collect()
+    }
+
+    fn release(&self, pair: &str) {
+        let mut feeds = self.feeds.lock().unwrap();
+
+        let Some(feed) = feeds.get_mut(pair) else {
+            return;
+        };
+
+        feed.subscribers -= 1;
+        if feed.subscribers == 0
+            && let Some(feed) = feeds.remove(pair)
+        {
+            println!("HUB Last subscriber left {}. Stopping connectors", pair);
+            feed.shutdown();
+            self.books.remove_pair(pair);
+        }
+    }
+
+    fn spawn_pair(&self, pair: &str) -> PairFeed {
+        let (tx_price_raw, mut rx_price_raw) =
+            broadcast::channel::<PriceUpdate>(self.engine_config.channel_capacity);
+        let (feed_tx, _) = broadcast::channel::<ArbitrageFeed>(self.engine_config.channel_capacity);
+        let (price_tx, _) = broadcast::channel::<PriceUpdate>(self.engine_config.channel_capacity);
+        let cancel = CancellationToken::new();
+        let monitor = Arc::new(VenueMonitor::default());
+        let mut tasks = JoinSet::new();
+
+        for connector in self.registry.for_pair(pair) {
+            let ctx = ConnectorContext {
+                tx: tx_price_raw.clone(),
+                pair: pair.to_string(),
+                symbol: self
+                    .registry
+                    .symbol(connector.as_ref(), pair)
+                    .unwrap_or_default(),
+                cancel: cancel.child_token(),
+                monitor: Arc::clone(&monitor),
+                books: Arc::clone(&self.books),
+                fees: Arc::clone(&self.fees),
+                assets: self.registry.assets(),
+                tape: self.registry.tape(),
+            };
+            tasks.spawn(connector.run(ctx));
+        }
+        drop(tx_price_raw);
+
+        // Single engine per pair feeding every subscriber
+        let cache = Arc::clone(&self.cache);
+        let engine_tx = feed_tx.clone();
+        let engine_price_tx = price_tx.clone();
+        let engine_cancel = cancel.clone();
+        let books = Arc::clone(&self.books);
+        let fees = Arc::clone(&self.fees);
+        let fx = Arc::clone(&self.fx);
+        let engine_config = self.engine_config.clone();
+        let latest = Arc::new(Mutex::new(None));
+        let engine_latest = Arc::clone(&latest);
+        let engine_monitor = Arc::clone(&monitor);
+        let persistence = self.persistence.clone();
+        tasks.spawn(async move {
+            let mut engine =
+                ArbitrageEngine::new(engine_tx, engine_monitor, books, fees, engine_config);
+            let mut last_recorded: Option<(String, String, f64)> = None;
+            loop {
+                let received = tokio::select! {
+                    _ = engine_cancel.cancelled() => break,
+                    received = rx_price_raw.recv() => received,
+                };
+                let update = match received {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                // Prices in another quote currency are dropped until the FX rate is known
+                let Some(update) = fx.normalize(update) else {
+                    continue;
+                };
+
+                let _ = engine_price_tx.send(update.clone());
+
+                // A. Update the Global Cache
+                let closed = {
+                    let mut lock = cache.lock().unwrap();
+                    lock.add(update.clone())
+                };
+                if let Some(db) = &persistence {
+                    db.record_price(&update);
+                    for candle in closed {
+                        db.record_candle(&update.pair, candle);
+                    }
+                }
+
+                // B. Calculate Arbitrage
+                let feed = engine.process_price(update);
+                // Store the opportunity whenever it moved
+                if let Some(opp) = &feed.opportunity {
+                    let key = (
+                        opp.best_buy_source.clone(),
+                        opp.best_sell_source.clone(),
+                        opp.net_spread_percent,
+                    );
+                    if let Some(db) = &persistence
+                        && last_recorded.as_ref() != Some(&key)
+                    {
+                        db.record_opportunity(opp);
+                        last_recorded = Some(key);
+                    }
+                }
+                *engine_latest.lock().unwrap() = Some(feed);
+            }
+        });
+
+        PairFeed {
+            feed_tx,
+            price_tx,
+            latest,
+            monitor,
+            subscribers: 0,
+            cancel,
+            tasks,
+        }
+    }
+}
+
+// --- Subscription Handle ---
+
+/// A client's membership in a pair feed. Dropping it releases the pair.
+pub struct Subscription {
+    hub: Arc<ConnectorHub>,
+    pair: String,
+    rx: broadcast::Receiver<ArbitrageFeed>,
+}
+
+impl Subscription {
+    /// Next feed for this pair. Skips over feeds missed by a slow client,
+    /// returns `None` once the pair feed has shut down.
+    pub async fn recv(&mut self) -> Option<ArbitrageFeed> {
+        loop {
+            match self.rx.recv().await {
+                Ok(feed) => return Some(feed),
+                Err(RecvError::Lagged(skipped)) => {
+                    eprintln!(
+                        "HUB Subscriber on {} lagged by {} feeds",
+                        self.pair, skipped
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.hub.release(&self.pair);
+    }
+}
+
+/// A client's membership in a pair feed, reading venue prices one by one
+pub struct PriceSubscription {
+    membership: Subscription, // Releases the pair when dropped
+    rx: broadcast::Receiver<PriceUpdate>,
+}
+
+impl PriceSubscription {
+    /// Next venue price. Skips over prices missed by a slow client, returns `None`
+    /// once the pair feed has shut down.
+    pub async fn recv(&mut self) -> Option<PriceUpdate> {
+        loop {
+            match self.rx.recv().await {
+                Ok(update) => return Some(update),
+                Err(RecvError::Lagged(skipped)) => {
+                    eprintln!(
+                        "HUB Price subscriber on {} lagged by {} prices",
+                        self.membership.pair, skipped
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetRegistry;
+    use crate::connectors::{Connector, VenueKind, now_ms};
+    use futures_util::future::BoxFuture;
+    use tokio::time::{Instant, sleep};
+
+    /// Emits a fixed price every few ms and ignores send errors, so only cancellation stops it
+    struct TickingConnector {
+        name: &'static str,
+        price: f64,
+    }
+
+    impl Connector for TickingConnector {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn kind(&self) -> VenueKind {
+            VenueKind::Cex
+        }
+
+        fn symbol(&self, _assets: &AssetRegistry, pair: &str) -> Option<String> {
+            Some(pair.to_string())
+        }
+
+        fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+            let (name, price) = (self.name, self.price);
+            ctx.run(move |ctx| async move {
+                loop {
+                    let _ = ctx
+                        .tx
+                        .send(PriceUpdate::single(name, &ctx.pair, price, now_ms()));
+                    sleep(Duration::from_millis(5)).await;
+                }
+            })
+        }
+    }
+
+    /// Never manages to connect, so it sits in the reconnect backoff
+    struct UnreachableConnector;
+
+    impl Connector for UnreachableConnector {
+        fn name(&self) -> &'static str {
+            "Unreachable"
+        }
+
+        fn kind(&self) -> VenueKind {
+            VenueKind::Cex
+        }
+
+        fn symbol(&self, _assets: &AssetRegistry, pair: &str) -> Option<String> {
+            Some(pair.to_string())
+        }
+
+        fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+            ctx.run(|ctx| async move {
+                ctx.supervise("Unreachable", || async {
+                    anyhow::bail!("connection refused")
+                })
+                .await;
+            })
+        }
+    }
+
+    fn test_hub() -> Arc<ConnectorHub> {
+        let mut registry = ConnectorRegistry::new(Arc::new(AssetRegistry::builtin()));
+        registry.register(TickingConnector {
+            name: "VenueA",
+            price: 100.0,
+        });
+        registry.register(TickingConnector {
+            name: "VenueB",
+            price: 101.0,
+        });
+        registry.register(UnreachableConnector);
+
+        ConnectorHub::new(
+            Arc::new(registry),
+            Arc::new(Mutex::new(MarketCache::new(10, &EngineConfig::default()))),
+            Arc::new(BookStore::default()),
+            Arc::new(FeeModel::empty()),
+            Arc::new(FxTracker::default()),
+            EngineConfig::default(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn last_subscriber_leaving_stops_every_task() {
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let baseline = metrics.num_alive_tasks();
+        let hub = test_hub();
+
+        let mut first = hub.subscribe("SOL/USDC");
+        let second = hub.subscribe("SOL/USDC");
+
+        // Both clients share one feed fed by both ticking venues
+        let feed = timeout(Duration::from_secs(5), async {
+            loop {
+                let feed = first.recv().await.expect("feed closed");
+                if feed.prices.len() == 2 {
+                    return feed;
+                }
+            }
+        })
+        .await
+        .expect("no feed received");
+        let opportunity = feed.opportunity.expect("two fresh venues");
+        assert_eq!(opportunity.best_buy_source, "VenueA");
+        assert_eq!(opportunity.best_sell_source, "VenueB");
+
+        // 3 connectors + 1 engine
+        assert_eq!(metrics.num_alive_tasks(), baseline + 4);
+
+        drop(first);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(hub.feeds.lock().unwrap().len(), 1);
+        assert_eq!(metrics.num_alive_tasks(), baseline + 4);
+
+        drop(second);
+        assert!(hub.feeds.lock().unwrap().is_empty());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while metrics.num_alive_tasks() > baseline && Instant::now() < deadline {
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(metrics.num_alive_tasks(), baseline, "leaked tasks");
+    }
+
+    #[tokio::test]
+    async fn resubscribing_after_teardown_restarts_the_pair() {
+        let hub = test_hub();
+
+        drop(hub.subscribe("SOL/USDC"));
+        assert!(hub.feeds.lock().unwrap().is_empty());
+
+        let mut again = hub.subscribe("SOL/USDC");
+        let feed = timeout(Duration::from_secs(5), again.recv())
+            .await
+            .expect("no feed received");
+        assert!(feed.is_some());
+    }
+}
@@ -1,77 +1,408 @@
 pub mod arbitrage_engine;
+pub mod assets;
+pub mod candles;
+pub mod config;
 mod connectors;
+pub mod fees;
+pub mod fx;
+pub mod hub;
+pub mod orderbook;
+pub mod persistence;
+pub mod session;
 pub mod state;
+pub mod triangular;
 #[allow(unused_imports)]
 pub use state::*;
 
-use arbitrage_engine::{ArbitrageEngine, ArbitrageFeed};
+use arbitrage_engine::{ArbitrageOpportunity, EngineConfig};
+use assets::AssetRegistry;
 use axum::{
+    Json,
     Router,
+    extract::Path,
+    extract::Query,
     extract::State, // Use Axum State instead of Extension for cleaner architecture
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    http::StatusCode,
     response::IntoResponse,
     routing::get,
 };
-use connectors::{
-    backpack::run_backpack_connector, binance::run_binance_connector,
-    bitfinex::run_bitfinex_connector, bitget::run_bitget_connector,
-    bitstamp::run_bitstamp_connector, bybit::run_bybit_connector, coinbase::run_coinbase_connector,
-    htx::run_htx_connector, jupiter::run_jupiter_connector, kraken::run_kraken_connector,
-    kucoin::run_kucoin_connector, okx::run_okx_connector, orca::run_orca_connector,
-    raydium::run_raydium_connector,
-};
+use candles::{COMPOSITE, Candle, Interval};
+use config::Config;
+use connectors::{ConnectorRegistry, Tape, VenueInfo};
+use fees::{FeeModel, VenueFees};
 use futures_util::{SinkExt, StreamExt};
-use serde::Deserialize;
-use state::{MarketCache, PriceUpdate};
-use std::net::SocketAddr;
+use fx::FxTracker;
+use hub::ConnectorHub;
+use orderbook::{BookSnapshot, BookStore, Side};
+use persistence::Persistence;
+use serde::{Deserialize, Serialize};
+use session::ClientSession;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
-use tokio::sync::broadcast;
 
 // --- Types ---
 
 #[derive(Debug, Deserialize)]
-struct PairRequest {
-    token_a: String,
-    token_b: String,
+struct VenueQuery {
+    pair: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    from: Option<u64>, // Unix ms, inclusive
+    to: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CandleQuery {
+    interval: Option<Interval>, // 1s, 1m (default), 5m, 1h
+    venue: Option<String>,      // Defaults to the composite of every venue
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct PairInfo {
+    pair: String,
+    venues: Vec<&'static str>, // Enabled venues able to serve it
+    streaming: bool,
+    subscribers: usize,
+}
+
+/// Error body of the REST routes
+type ApiError = (StatusCode, String);
+
+#[derive(Debug, Deserialize)]
+struct BookQuery {
+    pair: String,
+    venue: Option<String>,
+    depth: Option<usize>,
+    within_bps: Option<f64>, // Report size resting within this many bps of the best price
+}
+
+#[derive(Debug, Serialize)]
+struct BookResponse {
+    #[serde(flatten)]
+    book: BookSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bid_size_within: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ask_size_within: Option<f64>,
 }
 
 // Shared State Container
 struct AppState {
+    registry: Arc<ConnectorRegistry>,
     cache: Arc<Mutex<MarketCache>>,
+    books: Arc<BookStore>,
+    fees: Arc<FeeModel>,
+    engine_config: EngineConfig,
+    hub: Arc<ConnectorHub>,
+    db: Option<Arc<Persistence>>, // Set when DATABASE_URL is configured
 }
 
 #[tokio::main]
 async fn main() {
-    // 1. Initialize In-Memory Cache (No DB)
-    // Holds 500 prices per pair in RAM
-    let market_cache = Arc::new(Mutex::new(MarketCache::new(500)));
+    // 0. Settings: defaults < CONFIG_FILE (or ./config.toml) < environment, see config.example.toml
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    });
+
+    // Raw venue traffic is recorded to RECORD_FILE or replayed from REPLAY_FILE
+    let tape = Tape::from_env().unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    });
+    let replaying = tape.as_ref().is_some_and(|t| t.is_replay());
+
+    // Venue symbols, Solana mints and DEX pools (ASSETS_FILE or the built-in assets.toml),
+    // with decimals read from the mint accounts unless replaying
+    let mut assets = AssetRegistry::load(config.assets.file.as_deref()).unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    });
+    if !replaying {
+        assets.refresh_decimals(&config.rpc.solana_url).await;
+    }
+
+    // Venue endpoints, symbols and enabled state ([venues], ENABLED_VENUES / DISABLED_VENUES)
+    let mut registry = ConnectorRegistry::from_config(&config, Arc::new(assets));
+    registry.set_tape(tape);
+    let registry = Arc::new(registry);
+    if let Some(tape) = registry.tape().filter(|t| t.is_replay()) {
+        tokio::spawn(async move {
+            while !tape.is_drained() {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+            println!("TAPE Replay finished");
+        });
+    }
+
+    // Latest L2 book of every CEX venue, per pair
+    let books = Arc::new(BookStore::default());
+
+    // Taker/pool fees per venue, overridable with [fees] / FEE_CONFIG
+    let fees = Arc::new(FeeModel::from_config(&config));
+
+    // Target spread for sizing, max price age per venue and channel capacities
+    let mut engine_config = config.engine_config();
+    if replaying {
+        engine_config = engine_config.without_age_limits();
+    }
+
+    // 1. Initialize In-Memory Cache
+    // Holds `cache.history_size` prices per pair in RAM, older history comes from Postgres when configured
+    let market_cache = Arc::new(Mutex::new(MarketCache::new(
+        config.cache.history_size,
+        &engine_config,
+    )));
+
+    // USDT/USD, USDC/USD and USDC/USDT rates used to bring every venue into the requested quote
+    let fx = Arc::new(FxTracker::default());
+
+    // Optional Postgres history ([database] url / DATABASE_URL), in-memory only otherwise
+    let db = Persistence::connect(config.database.url.as_deref())
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("DB {:#}. Keeping history in memory only", e);
+            None
+        });
+
+    // One set of connectors per pair, shared by every client
+    let hub = ConnectorHub::new(
+        Arc::clone(&registry),
+        Arc::clone(&market_cache),
+        Arc::clone(&books),
+        Arc::clone(&fees),
+        Arc::clone(&fx),
+        engine_config.clone(),
+        db.clone(),
+    );
+    fx.spawn(&hub);
     let app_state = Arc::new(AppState {
+        registry,
         cache: market_cache,
+        books,
+        fees,
+        engine_config,
+        hub,
+        db: db.clone(),
     });
 
     // 2. Build Router
     let app = Router::new()
         .route("/", get(get_handler))
+        .route("/pairs", get(pairs_handler))
+        .route("/venues", get(venues_handler))
+        .route("/prices/{*pair}", get(prices_handler))
+        .route("/history/{*pair}", get(history_handler))
+        .route("/opportunities/{*pair}", get(opportunities_handler))
+        .route("/candles/{*pair}", get(candles_handler))
+        .route("/books", get(books_handler))
+        .route("/fees", get(fees_handler))
         .route("/ws/subscribe", get(ws_handler_subscribe))
         .with_state(app_state);
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8081));
+    let addr = config.server.listen;
     println!(
-        "Engine running on ws://{}/ws/subscribe (In-Memory Mode)",
-        addr
+        "Engine running on ws://{}/ws/subscribe ({})",
+        addr,
+        if db.is_some() {
+            "Postgres History"
+        } else {
+            "In-Memory Mode"
+        }
     );
+    if replaying {
+        println!("Replaying recorded venue traffic, venues stay silent once it runs out");
+    }
 
     let listener = TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
 
-// --- WebSocket Handlers ---
+// --- HTTP Handlers ---
 
 async fn get_handler() -> &'static str {
     "Engine is ON"
 }
 
+/// `SOL/USDT` or `sol-usdt` from a path, as the canonical pair
+fn path_pair(raw: &str) -> Result<String, ApiError> {
+    session::normalize_pair(&raw.replace('-', "/")).ok_or((
+        StatusCode::BAD_REQUEST,
+        format!("Invalid pair {:?}, expected BASE/QUOTE", raw),
+    ))
+}
+
+/// Pairs with a fixed venue listing, cached prices or live subscribers
+async fn pairs_handler(State(state): State<Arc<AppState>>) -> Json<Vec<PairInfo>> {
+    let mut pairs: BTreeMap<String, usize> = state
+        .registry
+        .venues(None)
+        .into_iter()
+        .flat_map(|venue| venue.supported_pairs.unwrap_or_default())
+        .map(|pair| (pair.to_string(), 0))
+        .collect();
+    for pair in state.cache.lock().unwrap().pairs() {
+        pairs.entry(pair).or_default();
+    }
+    let streamed = state.hub.pairs();
+    for (pair, subscribers) in &streamed {
+        pairs.insert(pair.clone(), *subscribers);
+    }
+
+    let pairs = pairs
+        .into_iter()
+        .map(|(pair, subscribers)| PairInfo {
+            venues: state
+                .registry
+                .for_pair(&pair)
+                .iter()
+                .map(|c| c.name())
+                .collect(),
+            streaming: streamed.iter().any(|(p, _)| *p == pair),
+            subscribers,
+            pair,
+        })
+        .collect();
+
+    Json(pairs)
+}
+
+/// Registered venues with their connection state per streamed pair;
+/// `?pair=SOL/USDC` also reports which of them serve that pair
+async fn venues_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VenueQuery>,
+) -> Json<Vec<VenueInfo>> {
+    let pair = query.pair.map(|p| p.to_uppercase());
+    let status = state.hub.venue_status();
+
+    let venues = state
+        .registry
+        .venues(pair.as_deref())
+        .into_iter()
+        .map(|mut venue| {
+            venue.connections = status
+                .iter()
+                .filter_map(|(pair, venues)| Some((pair.clone(), venues.get(venue.name)?.clone())))
+                .collect();
+            venue
+        })
+        .collect();
+
+    Json(venues)
+}
+
+/// Latest price of every venue, e.g. `/prices/SOL/USDT`
+async fn prices_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pair): Path<String>,
+) -> Result<Json<Vec<PriceUpdate>>, ApiError> {
+    let pair = path_pair(&pair)?;
+    Ok(Json(state.cache.lock().unwrap().latest(&pair)))
+}
+
+/// Prices of a pair (cache, then Postgres), `/history/SOL/USDT?from=1700000000000&to=1700000060000`
+async fn history_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pair): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<PriceUpdate>>, ApiError> {
+    let pair = path_pair(&pair)?;
+    persistence::load_history(
+        &state.cache,
+        state.db.as_deref(),
+        &pair,
+        query.from,
+        query.to,
+    )
+    .await
+    .map(Json)
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// OHLCV candles, `/candles/SOL/USDT?interval=5m&venue=Binance&from=..&to=..`
+async fn candles_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pair): Path<String>,
+    Query(query): Query<CandleQuery>,
+) -> Result<Json<Vec<Candle>>, ApiError> {
+    let pair = path_pair(&pair)?;
+    let candles = state.cache.lock().unwrap().candles.get(
+        &pair,
+        query.venue.as_deref().unwrap_or(COMPOSITE),
+        query.interval.unwrap_or(Interval::Min1),
+        query.from,
+        query.to,
+    );
+    Ok(Json(candles))
+}
+
+/// Current opportunity of a pair that is streamed (i.e. has a WebSocket subscriber)
+async fn opportunities_handler(
+    State(state): State<Arc<AppState>>,
+    Path(pair): Path<String>,
+) -> Result<Json<ArbitrageOpportunity>, ApiError> {
+    let pair = path_pair(&pair)?;
+    state
+        .hub
+        .latest(&pair)
+        .and_then(|feed| feed.opportunity)
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!(
+                "No opportunity for {} (is it subscribed, with two fresh venues?)",
+                pair
+            ),
+        ))
+}
+
+/// Order books of a pair that is currently streamed (i.e. has a WebSocket subscriber).
+/// `?pair=SOL/USDT&venue=Binance&depth=20&within_bps=10`
+async fn books_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<BookQuery>,
+) -> Json<Vec<BookResponse>> {
+    let pair = query.pair.to_uppercase();
+    let depth = query.depth.unwrap_or(20);
+
+    let books = state
+        .books
+        .for_pair(&pair)
+        .into_iter()
+        .filter(|book| {
+            query
+                .venue
+                .as_ref()
+                .is_none_or(|venue| book.venue.eq_ignore_ascii_case(venue))
+        })
+        .map(|book| BookResponse {
+            bid_size_within: query
+                .within_bps
+                .map(|bps| book.size_within_bps(Side::Bid, bps)),
+            ask_size_within: query
+                .within_bps
+                .map(|bps| book.size_within_bps(Side::Ask, bps)),
+            book: book.truncated(depth),
+        })
+        .collect();
+
+    Json(books)
+}
+
+/// Effective fee schedule per venue, including pool fees read on-chain
+async fn fees_handler(State(state): State<Arc<AppState>>) -> Json<HashMap<String, VenueFees>> {
+    Json(state.fees.snapshot())
+}
+
+// --- WebSocket Handlers ---
+
 async fn ws_handler_subscribe(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
@@ -79,85 +410,41 @@ async fn ws_handler_subscribe(
     ws.on_upgrade(move |socket| handle_socket_subscribe(socket, state))
 }
 
+/// Versioned multi-pair protocol, see `session::Action`. The original single
+/// `{"token_a", "token_b"}` request is still accepted and answered in its own frames:
+/// the cached history array, then every bare `ArbitrageFeed`.
 async fn handle_socket_subscribe(socket: WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
 
-    // 1. Wait for Client to request a pair (e.g., SOL/USDT)
-    let pair = match receiver.next().await {
-        Some(Ok(Message::Text(text))) => match serde_json::from_str::<PairRequest>(&text) {
-            Ok(req) => format!(
-                "{}/{}",
-                req.token_a.to_uppercase(),
-                req.token_b.to_uppercase()
-            ),
-            _ => return, // Invalid JSON
-        },
-        _ => return, // Disconnected or error
-    };
-
-    println!("Client subscribed to: {}", pair);
-
-    // 2. INSTANTLY send Cached History (Get data, Drop lock, THEN Send)
-    let history_json = {
-        // Open a new scope just for locking
-        let lock = state.cache.lock().unwrap();
-        let history = lock.get_history(&pair);
-
-        if !history.is_empty() {
-            serde_json::to_string(&history).ok()
-        } else {
-            None
-        }
-    };
-
-    if let Some(json) = history_json {
-        if let Err(e) = sender.send(Message::Text(json.into())).await {
-            println!("Failed to send history: {}", e);
-            return;
-        }
-    }
+    // Dropping the session releases every pair (and their connectors if we were the last)
+    let (mut session, mut outbound) = ClientSession::new(
+        Arc::clone(&state.registry),
+        Arc::clone(&state.cache),
+        Arc::clone(&state.books),
+        Arc::clone(&state.fees),
+        state.engine_config.clone(),
+        Arc::clone(&state.hub),
+        state.db.clone(),
+    );
 
-    // 3. Setup Broadcast Channels for Live Data
-    let (tx_price_raw, mut rx_price_raw) = broadcast::channel::<PriceUpdate>(5000);
-    let (tx_arb_feed, mut rx_arb_feed) = broadcast::channel::<ArbitrageFeed>(5000);
-
-    // 4. Spawn Connectors
-    tokio::spawn(run_binance_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_backpack_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_bitfinex_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_bitget_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_bybit_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_coinbase_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_htx_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_jupiter_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_kraken_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_kucoin_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_okx_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_raydium_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_orca_connector(tx_price_raw.clone(), pair.clone()));
-    tokio::spawn(run_bitstamp_connector(tx_price_raw, pair.clone()));
-    // ... spawn others ...
-
-    // 5. Spawn Arbitrage Engine
-    tokio::spawn(async move {
-        let mut engine = ArbitrageEngine::new(tx_arb_feed);
-        while let Ok(update) = rx_price_raw.recv().await {
-            // A. Update the Global Cache
-            {
-                let mut lock = state.cache.lock().unwrap();
-                lock.add(update.clone());
+    loop {
+        let messages = tokio::select! {
+            message = outbound.recv() => {
+                let Some(message) = message else { break };
+                vec![message]
             }
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Text(text))) => session.handle(&text).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
 
-            // B. Calculate Arbitrage
-            engine.process_price(update);
-        }
-    });
-
-    // 6. Stream Final Results to Client
-    while let Ok(feed) = rx_arb_feed.recv().await {
-        if let Ok(json) = serde_json::to_string(&feed) {
-            if sender.send(Message::Text(json.into())).await.is_err() {
-                break;
+        for message in messages {
+            if let Ok(json) = serde_json::to_string(&message)
+                && sender.send(Message::Text(json.into())).await.is_err()
+            {
+                return;
             }
         }
     }
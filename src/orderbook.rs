@@ -0,0 +1,458 @@
+use crate::state::PriceUpdate;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Levels per side kept in the shared store (connectors may hold deeper books locally)
+pub const PUBLISHED_DEPTH: usize = 100;
+
+/// AMM curves are sliced into levels covering this share of the base reserve,
+/// starting with a tiny slice and growing geometrically
+const CURVE_FIRST_SLICE: f64 = 0.00001;
+const CURVE_SLICE_GROWTH: f64 = 1.5;
+const CURVE_MAX_FRACTION: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// f64 price with a total order so it can key a BTreeMap
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// One price level. The venue's own strings are kept for checksum validation.
+#[derive(Debug, Clone)]
+struct Level {
+    size: f64,
+    raw_price: String,
+    raw_size: String,
+}
+
+impl Level {
+    fn raw(&self) -> (&str, &str) {
+        (&self.raw_price, &self.raw_size)
+    }
+}
+
+// --- Local Book ---
+
+/// L2 book for one venue/pair, maintained by its connector from snapshot + diffs
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Price, Level>,
+    asks: BTreeMap<Price, Level>,
+    pub sequence: Option<u64>, // Last applied venue update id
+    pub timestamp: u64,        // Unix timestamp (ms) of the last applied update
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.sequence = None;
+    }
+
+    /// Set a level from the venue's price/size strings; a zero size removes it
+    pub fn apply(&mut self, side: Side, price: &str, size: &str) -> anyhow::Result<()> {
+        let key = Price(price.parse()?);
+        let parsed_size: f64 = size.parse()?;
+
+        let levels = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+
+        if parsed_size == 0.0 {
+            levels.remove(&key);
+        } else {
+            levels.insert(
+                key,
+                Level {
+                    size: parsed_size,
+                    raw_price: price.to_string(),
+                    raw_size: size.to_string(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Drop everything beyond `depth` levels per side (venues that stream a fixed depth)
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_first();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// (price, size) pairs from the best level outwards
+    pub fn levels(&self, side: Side) -> Box<dyn Iterator<Item = (f64, f64)> + '_> {
+        match side {
+            Side::Bid => Box::new(self.bids.iter().rev().map(|(p, l)| (p.0, l.size))),
+            Side::Ask => Box::new(self.asks.iter().map(|(p, l)| (p.0, l.size))),
+        }
+    }
+
+    /// Venue strings (price, size) from the best level outwards, for checksums
+    pub fn raw_levels(&self, side: Side) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        match side {
+            Side::Bid => Box::new(self.bids.values().rev().map(Level::raw)),
+            Side::Ask => Box::new(self.asks.values().map(Level::raw)),
+        }
+    }
+
+    /// Top of book as a quote, `None` until both sides have a level
+    pub fn quote(&self, source: &str, pair: &str) -> Option<PriceUpdate> {
+        let (bid, bid_size) = self.levels(Side::Bid).next()?;
+        let (ask, ask_size) = self.levels(Side::Ask).next()?;
+        Some(PriceUpdate::quote(
+            source,
+            pair,
+            bid,
+            bid_size,
+            ask,
+            ask_size,
+            self.timestamp,
+        ))
+    }
+
+    pub fn snapshot(&self, venue: &str, pair: &str, depth: usize) -> BookSnapshot {
+        BookSnapshot {
+            venue: venue.to_string(),
+            pair: pair.to_string(),
+            bids: self.levels(Side::Bid).take(depth).collect(),
+            asks: self.levels(Side::Ask).take(depth).collect(),
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+// --- Shared Snapshots ---
+
+/// Read-only copy of a venue book, as served to the engine and the API
+#[derive(Debug, Clone, Serialize)]
+pub struct BookSnapshot {
+    pub venue: String,
+    pub pair: String,
+    pub bids: Vec<(f64, f64)>, // [price, size], best first
+    pub asks: Vec<(f64, f64)>,
+    pub sequence: Option<u64>,
+    pub timestamp: u64,
+}
+
+impl BookSnapshot {
+    pub fn levels(&self, side: Side) -> &[(f64, f64)] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    /// Size resting at or better than `limit`: bids priced >= limit, asks priced <= limit
+    pub fn size_within(&self, side: Side, limit: f64) -> f64 {
+        self.levels(side)
+            .iter()
+            .take_while(|(price, _)| match side {
+                Side::Bid => *price >= limit,
+                Side::Ask => *price <= limit,
+            })
+            .map(|(_, size)| size)
+            .sum()
+    }
+
+    /// Size within `bps` basis points of the best price on `side`
+    pub fn size_within_bps(&self, side: Side, bps: f64) -> f64 {
+        let Some((best, _)) = self.levels(side).first() else {
+            return 0.0;
+        };
+        let limit = match side {
+            Side::Bid => best * (1.0 - bps / 10_000.0),
+            Side::Ask => best * (1.0 + bps / 10_000.0),
+        };
+        self.size_within(side, limit)
+    }
+
+    /// Synthetic book for a constant-product pool (x * y = k). Each level is the
+    /// average price of one slice of the curve, so walking the levels reproduces the
+    /// swap amounts (before pool fees).
+    pub fn constant_product(
+        venue: &str,
+        pair: &str,
+        base_reserve: f64,
+        quote_reserve: f64,
+        timestamp: u64,
+    ) -> Self {
+        let k = base_reserve * quote_reserve;
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+
+        let mut prev = 0.0;
+        let mut fraction = CURVE_FIRST_SLICE;
+        while fraction <= CURVE_MAX_FRACTION {
+            let size = (fraction - prev) * base_reserve;
+
+            // Buying `size` base out of the pool
+            let cost = k / (base_reserve * (1.0 - fraction)) - k / (base_reserve * (1.0 - prev));
+            asks.push((cost / size, size));
+
+            // Selling `size` base into the pool
+            let proceeds =
+                k / (base_reserve * (1.0 + prev)) - k / (base_reserve * (1.0 + fraction));
+            bids.push((proceeds / size, size));
+
+            prev = fraction;
+            fraction *= CURVE_SLICE_GROWTH;
+        }
+
+        Self {
+            venue: venue.to_string(),
+            pair: pair.to_string(),
+            bids,
+            asks,
+            sequence: None,
+            timestamp,
+        }
+    }
+
+    /// One book over several pools of a venue, their levels in price order as a
+    /// router splitting the order across them would fill it
+    pub fn merged(
+        venue: &str,
+        pair: &str,
+        books: impl IntoIterator<Item = BookSnapshot>,
+        timestamp: u64,
+    ) -> Self {
+        let (mut bids, mut asks) = (Vec::new(), Vec::new());
+        for book in books {
+            bids.extend(book.bids);
+            asks.extend(book.asks);
+        }
+        bids.sort_by(|a, b| b.0.total_cmp(&a.0));
+        asks.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self {
+            venue: venue.to_string(),
+            pair: pair.to_string(),
+            bids,
+            asks,
+            sequence: None,
+            timestamp,
+        }
+    }
+
+    /// Synthetic book for a pool whose curve is only known through swap quotes, e.g.
+    /// concentrated liquidity. `sell_base` / `sell_quote` answer (input used, output)
+    /// for an input amount; the inputs are sliced like `constant_product` slices the
+    /// base reserve, over the given reserves. A side ends where the pool stops taking
+    /// input (no liquidity past that price).
+    pub fn from_swaps(
+        venue: &str,
+        pair: &str,
+        (base_reserve, quote_reserve): (f64, f64),
+        sell_base: impl Fn(f64) -> (f64, f64),
+        sell_quote: impl Fn(f64) -> (f64, f64),
+        timestamp: u64,
+    ) -> Self {
+        // Levels between consecutive cumulative swaps, as (price, base size)
+        let levels = |reserve: f64, swap: &dyn Fn(f64) -> (f64, f64), base_in: bool| {
+            let mut levels = Vec::new();
+            let (mut prev_in, mut prev_out) = (0.0, 0.0);
+            let mut fraction = CURVE_FIRST_SLICE;
+            while fraction <= CURVE_MAX_FRACTION {
+                let (used, out) = swap(fraction * reserve);
+                let (spent, got) = (used - prev_in, out - prev_out);
+                if spent <= 0.0 || got <= 0.0 {
+                    break;
+                }
+                levels.push(if base_in {
+                    (got / spent, spent)
+                } else {
+                    (spent / got, got)
+                });
+                (prev_in, prev_out) = (used, out);
+                fraction *= CURVE_SLICE_GROWTH;
+            }
+            levels
+        };
+
+        Self {
+            venue: venue.to_string(),
+            pair: pair.to_string(),
+            bids: levels(base_reserve, &sell_base, true),
+            asks: levels(quote_reserve, &sell_quote, false),
+            sequence: None,
+            timestamp,
+        }
+    }
+
+    pub fn truncated(mut self, depth: usize) -> Self {
+        self.bids.truncate(depth);
+        self.asks.truncate(depth);
+        self
+    }
+}
+
+/// Latest book of every venue, per pair
+#[derive(Default)]
+pub struct BookStore {
+    books: Mutex<HashMap<String, HashMap<String, BookSnapshot>>>,
+}
+
+impl BookStore {
+    pub fn publish(&self, snapshot: BookSnapshot) {
+        let mut books = self.books.lock().unwrap();
+        books
+            .entry(snapshot.pair.clone())
+            .or_default()
+            .insert(snapshot.venue.clone(), snapshot);
+    }
+
+    /// Forget a venue's book (its session ended, the book can no longer be trusted)
+    pub fn remove(&self, venue: &str, pair: &str) {
+        let mut books = self.books.lock().unwrap();
+        if let Some(venues) = books.get_mut(pair) {
+            venues.remove(venue);
+            if venues.is_empty() {
+                books.remove(pair);
+            }
+        }
+    }
+
+    pub fn remove_pair(&self, pair: &str) {
+        self.books.lock().unwrap().remove(pair);
+    }
+
+    pub fn get(&self, venue: &str, pair: &str) -> Option<BookSnapshot> {
+        let books = self.books.lock().unwrap();
+        books.get(pair)?.get(venue).cloned()
+    }
+
+    pub fn for_pair(&self, pair: &str) -> Vec<BookSnapshot> {
+        let books = self.books.lock().unwrap();
+        books
+            .get(pair)
+            .map(|venues| venues.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_product_levels_add_up_to_the_swap() {
+        let (x, y) = (1_000.0, 150_000.0);
+        let book = BookSnapshot::constant_product("Raydium", "SOL/USDC", x, y, 1);
+        let k = x * y;
+
+        // Buying Δ base out of the pool costs k / (x - Δ) - y
+        let bought: f64 = book.asks.iter().map(|(_, size)| size).sum();
+        let cost: f64 = book.asks.iter().map(|(price, size)| price * size).sum();
+        assert!(bought > 0.0 && bought <= x * CURVE_MAX_FRACTION);
+        assert!((cost - (k / (x - bought) - y)).abs() < 1e-6, "{}", cost);
+
+        // Selling Δ base into it pays y - k / (x + Δ)
+        let sold: f64 = book.bids.iter().map(|(_, size)| size).sum();
+        let proceeds: f64 = book.bids.iter().map(|(price, size)| price * size).sum();
+        assert!(
+            (proceeds - (y - k / (x + sold))).abs() < 1e-6,
+            "{}",
+            proceeds
+        );
+
+        // Prices worsen level by level away from the spot price
+        assert!(book.asks[0].0 > 150.0 && book.bids[0].0 < 150.0);
+        assert!(book.asks.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(book.bids.windows(2).all(|w| w[0].0 > w[1].0));
+    }
+
+    #[test]
+    fn swap_book_ends_where_the_pool_takes_no_more_input() {
+        // Flat price of 150 with room for 2 base and 30 quote of input
+        let book = BookSnapshot::from_swaps(
+            "Orca",
+            "SOL/USDC",
+            (1_000.0, 150_000.0),
+            |base: f64| {
+                let used = base.min(2.0);
+                (used, used * 150.0)
+            },
+            |quote: f64| {
+                let used = quote.min(30.0);
+                (used, used / 150.0)
+            },
+            1,
+        );
+
+        let sold: f64 = book.bids.iter().map(|(_, size)| size).sum();
+        assert!((sold - 2.0).abs() < 1e-9, "{}", sold);
+        let bought: f64 = book.asks.iter().map(|(_, size)| size).sum();
+        assert!((bought - 0.2).abs() < 1e-9, "{}", bought);
+        assert!(
+            book.bids
+                .iter()
+                .all(|(price, _)| (price - 150.0).abs() < 1e-9)
+        );
+        assert!(
+            book.asks
+                .iter()
+                .all(|(price, _)| (price - 150.0).abs() < 1e-9)
+        );
+
+        // The slice that hit the limit is the last one
+        let slices = |reserve: f64, limit: f64| {
+            let mut fraction = CURVE_FIRST_SLICE;
+            let mut count = 1;
+            while fraction * reserve < limit {
+                fraction *= CURVE_SLICE_GROWTH;
+                count += 1;
+            }
+            count
+        };
+        assert_eq!(book.bids.len(), slices(1_000.0, 2.0));
+        assert_eq!(book.asks.len(), slices(150_000.0, 30.0));
+    }
+
+    #[test]
+    fn merged_book_walks_every_pool_in_price_order() {
+        let deep = BookSnapshot::constant_product("Raydium", "SOL/USDC", 1_000.0, 150_000.0, 1);
+        let shallow = BookSnapshot::constant_product("Raydium", "SOL/USDC", 10.0, 1_510.0, 1);
+        let levels = deep.asks.len() + shallow.asks.len();
+        let best_bid = shallow.bids[0].0.max(deep.bids[0].0);
+
+        let book = BookSnapshot::merged("Raydium", "SOL/USDC", [deep, shallow], 2);
+        assert_eq!((book.asks.len(), book.timestamp), (levels, 2));
+        // The cheaper pool fills first, then the levels interleave by price
+        assert!(book.asks[0].0 < 150.1);
+        assert!(book.asks.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(book.bids.windows(2).all(|w| w[0].0 >= w[1].0));
+        assert_eq!(book.bids[0].0, best_bid);
+    }
+}
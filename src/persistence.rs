@@ -0,0 +1,299 @@
+use crate::arbitrage_engine::ArbitrageOpportunity;
+use crate::candles::Candle;
+use crate::connectors::now_ms;
+use crate::state::{MarketCache, PriceUpdate};
+use anyhow::Context;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::{Postgres, QueryBuilder, Row};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, interval};
+
+/// Rows per table written in one INSERT
+const BATCH_SIZE: usize = 500;
+/// Partial batches are written at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Records waiting for the writer before new ones are dropped
+const QUEUE_SIZE: usize = 50_000;
+/// Most rows returned by a history query
+const HISTORY_LIMIT: i64 = 10_000;
+
+enum Record {
+    Price(PriceUpdate),
+    Candle(String, Candle),
+    Opportunity(ArbitrageOpportunity, u64),
+}
+
+#[derive(Default)]
+struct Batch {
+    prices: Vec<PriceUpdate>,
+    candles: Vec<(String, Candle)>,
+    opportunities: Vec<(ArbitrageOpportunity, u64)>,
+}
+
+impl Batch {
+    fn push(&mut self, record: Record) {
+        match record {
+            Record::Price(update) => self.prices.push(update),
+            Record::Candle(pair, candle) => self.candles.push((pair, candle)),
+            Record::Opportunity(opp, timestamp) => self.opportunities.push((opp, timestamp)),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.prices.len() >= BATCH_SIZE
+            || self.candles.len() >= BATCH_SIZE
+            || self.opportunities.len() >= BATCH_SIZE
+    }
+}
+
+/// Optional Postgres store: prices, closed candles and opportunities are queued
+/// and written in batches by a background task.
+pub struct Persistence {
+    pool: PgPool,
+    tx: mpsc::Sender<Record>,
+}
+
+impl Persistence {
+    /// Connect to `url` and run the migrations. `None` (in-memory mode) without a URL.
+    pub async fn connect(url: Option<&str>) -> anyhow::Result<Option<Arc<Self>>> {
+        let Some(url) = url else {
+            return Ok(None);
+        };
+
+        // The URL may carry a password, so errors name the setting instead
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .context("Connecting to [database] url / DATABASE_URL")?;
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .context("Running database migrations")?;
+
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(run_writer(pool.clone(), rx));
+
+        Ok(Some(Arc::new(Self { pool, tx })))
+    }
+
+    pub fn record_price(&self, update: &PriceUpdate) {
+        self.enqueue(Record::Price(update.clone()));
+    }
+
+    pub fn record_candle(&self, pair: &str, candle: Candle) {
+        self.enqueue(Record::Candle(pair.to_string(), candle));
+    }
+
+    pub fn record_opportunity(&self, opp: &ArbitrageOpportunity) {
+        self.enqueue(Record::Opportunity(opp.clone(), now_ms()));
+    }
+
+    fn enqueue(&self, record: Record) {
+        if self.tx.try_send(record).is_err() {
+            eprintln!("DB Write queue full, dropping record");
+        }
+    }
+
+    /// Stored prices of `pair` within `from..=to` (ms), the latest `HISTORY_LIMIT` of them
+    pub async fn history(
+        &self,
+        pair: &str,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> anyhow::Result<Vec<PriceUpdate>> {
+        let rows = sqlx::query(
+            "SELECT source, pair, price, bid, ask, bid_size, ask_size, timestamp_ms, \
+                    native_quote, fx_rate, trade_size, slot \
+             FROM price_updates \
+             WHERE pair = $1 AND timestamp_ms >= $2 AND timestamp_ms <= $3 \
+             ORDER BY timestamp_ms DESC LIMIT $4",
+        )
+        .bind(pair)
+        .bind(from.map_or(0, |t| t as i64))
+        .bind(to.map_or(i64::MAX, |t| t as i64))
+        .bind(HISTORY_LIMIT)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut history = rows
+            .into_iter()
+            .map(|row| {
+                Ok(PriceUpdate {
+                    source: row.try_get("source")?,
+                    pair: row.try_get("pair")?,
+                    price: row.try_get("price")?,
+                    bid: row.try_get("bid")?,
+                    ask: row.try_get("ask")?,
+                    bid_size: row.try_get("bid_size")?,
+                    ask_size: row.try_get("ask_size")?,
+                    timestamp: row.try_get::<i64, _>("timestamp_ms")? as u64,
+                    native_quote: row.try_get("native_quote")?,
+                    fx_rate: row.try_get("fx_rate")?,
+                    trade_size: row.try_get("trade_size")?,
+                    slot: row.try_get::<Option<i64>, _>("slot")?.map(|s| s as u64),
+                    pools: Vec::new(), // Only live updates carry the pool breakdown
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+        history.reverse(); // Oldest first, like the cache
+
+        Ok(history)
+    }
+}
+
+/// Whether the cache answers a history query on its own: it reaches back to `from`,
+/// or, with no `from`, it has something to return
+pub fn cache_covers(oldest: Option<u64>, from: Option<u64>, cached: &[PriceUpdate]) -> bool {
+    match from {
+        Some(from) => oldest.is_some_and(|oldest| from >= oldest),
+        None => !cached.is_empty(),
+    }
+}
+
+/// Prices of `pair` within `from..=to` (ms) from the cache, or from the database
+/// when the in-memory window doesn't go back far enough
+pub async fn load_history(
+    cache: &Mutex<MarketCache>,
+    db: Option<&Persistence>,
+    pair: &str,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> anyhow::Result<Vec<PriceUpdate>> {
+    let (cached, oldest) = {
+        let cache = cache.lock().unwrap();
+        (cache.history_between(pair, from, to), cache.oldest(pair))
+    };
+
+    match db {
+        Some(db) if !cache_covers(oldest, from, &cached) => db.history(pair, from, to).await,
+        _ => Ok(cached),
+    }
+}
+
+async fn run_writer(pool: PgPool, mut rx: mpsc::Receiver<Record>) {
+    let mut batch = Batch::default();
+    let mut ticker = interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            record = rx.recv() => {
+                let Some(record) = record else { break };
+                batch.push(record);
+                if !batch.is_full() {
+                    continue;
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+
+        flush(&pool, std::mem::take(&mut batch)).await;
+    }
+
+    flush(&pool, batch).await;
+}
+
+/// Write whatever the batch holds; a failed table is logged and its rows dropped
+async fn flush(pool: &PgPool, batch: Batch) {
+    if !batch.prices.is_empty() {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO price_updates (source, pair, price, bid, ask, bid_size, ask_size, \
+             timestamp_ms, native_quote, fx_rate, trade_size, slot) ",
+        );
+        query.push_values(&batch.prices, |mut row, u| {
+            row.push_bind(&u.source)
+                .push_bind(&u.pair)
+                .push_bind(u.price)
+                .push_bind(u.bid)
+                .push_bind(u.ask)
+                .push_bind(u.bid_size)
+                .push_bind(u.ask_size)
+                .push_bind(u.timestamp as i64)
+                .push_bind(&u.native_quote)
+                .push_bind(u.fx_rate)
+                .push_bind(u.trade_size)
+                .push_bind(u.slot.map(|s| s as i64));
+        });
+        if let Err(e) = query.build().execute(pool).await {
+            eprintln!("DB Failed to write {} prices: {}", batch.prices.len(), e);
+        }
+    }
+
+    if !batch.candles.is_empty() {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO candles (pair, venue, interval, open_time_ms, open, high, low, close, \
+             volume, ticks) ",
+        );
+        query.push_values(&batch.candles, |mut row, (pair, c)| {
+            row.push_bind(pair)
+                .push_bind(&c.venue)
+                .push_bind(c.interval.as_str())
+                .push_bind(c.open_time as i64)
+                .push_bind(c.open)
+                .push_bind(c.high)
+                .push_bind(c.low)
+                .push_bind(c.close)
+                .push_bind(c.volume)
+                .push_bind(c.ticks as i64);
+        });
+        // A restart or a replay can close the same candle again, the latest close wins
+        query.push(
+            " ON CONFLICT (pair, venue, interval, open_time_ms) DO UPDATE SET \
+             open = EXCLUDED.open, high = EXCLUDED.high, low = EXCLUDED.low, \
+             close = EXCLUDED.close, volume = EXCLUDED.volume, ticks = EXCLUDED.ticks",
+        );
+        if let Err(e) = query.build().execute(pool).await {
+            eprintln!("DB Failed to write {} candles: {}", batch.candles.len(), e);
+        }
+    }
+
+    if !batch.opportunities.is_empty() {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO opportunities (pair, timestamp_ms, buy_source, buy_price, sell_source, \
+             sell_price, spread_percent, net_spread_percent, buy_fee_bps, sell_fee_bps, \
+             executable_size, expected_profit_quote) ",
+        );
+        query.push_values(&batch.opportunities, |mut row, (o, timestamp)| {
+            row.push_bind(&o.pair)
+                .push_bind(*timestamp as i64)
+                .push_bind(&o.best_buy_source)
+                .push_bind(o.best_buy_price)
+                .push_bind(&o.best_sell_source)
+                .push_bind(o.best_sell_price)
+                .push_bind(o.spread_percent)
+                .push_bind(o.net_spread_percent)
+                .push_bind(o.buy_fee_bps)
+                .push_bind(o.sell_fee_bps)
+                .push_bind(o.executable_size)
+                .push_bind(o.expected_profit_quote);
+        });
+        if let Err(e) = query.build().execute(pool).await {
+            eprintln!(
+                "DB Failed to write {} opportunities: {}",
+                batch.opportunities.len(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_covers_queries_inside_its_window() {
+        let cached = [PriceUpdate::single("Binance", "SOL/USDC", 150.0, 5_000)];
+
+        // `from` older than the cache: the database has what came before
+        assert!(!cache_covers(Some(5_000), Some(4_999), &cached));
+        assert!(!cache_covers(None, Some(4_999), &[]));
+        // `from` inside the cache
+        assert!(cache_covers(Some(5_000), Some(5_000), &cached));
+        assert!(cache_covers(Some(5_000), Some(9_000), &[]));
+        // No `from`: the cache answers unless it has nothing
+        assert!(!cache_covers(None, None, &[]));
+        assert!(cache_covers(Some(5_000), None, &cached));
+    }
+}
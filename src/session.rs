@@ -0,0 +1,1030 @@
+use crate::arbitrage_engine::{ArbitrageFeed, ArbitrageOpportunity, EngineConfig};
+use crate::candles::{Candle, Interval};
+use crate::connectors::ConnectorRegistry;
+use crate::fees::FeeModel;
+use crate::hub::{ConnectorHub, Subscription};
+use crate::orderbook::{BookSnapshot, BookStore};
+use crate::persistence::{self, Persistence};
+use crate::state::{MarketCache, PriceUpdate, VenueStatus};
+use crate::triangular::{self, Triangle, TriangleWatch, TriangularOpportunity};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Version of the client protocol, carried as `v` on every message
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Levels per side pushed on the books channel
+const BOOK_DEPTH: usize = 20;
+
+// --- Client Messages ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Prices,
+    Opportunities,
+    Books,
+    Candles,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [
+        Channel::Prices,
+        Channel::Opportunities,
+        Channel::Books,
+        Channel::Candles,
+    ];
+}
+
+/// `{"v": 1, "action": "subscribe", "pairs": ["SOL/USDT"], "channels": ["prices"]}`.
+/// Leaving out `channels` means every channel.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Subscribe {
+        pairs: Vec<String>,
+        #[serde(default)]
+        channels: Vec<Channel>,
+        /// Intermediate currencies to watch triangular loops through (e.g. `["BTC"]`)
+        #[serde(default)]
+        via: Vec<String>,
+    },
+    Unsubscribe {
+        pairs: Vec<String>,
+        #[serde(default)]
+        channels: Vec<Channel>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientMessage {
+    pub v: u32,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Single-pair request of the original protocol (`{"token_a": "SOL", "token_b": "USDT"}`)
+#[derive(Debug, Deserialize)]
+pub struct PairRequest {
+    pub token_a: String,
+    pub token_b: String,
+    #[serde(default)]
+    pub via: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Incoming {
+    Message(ClientMessage),
+    Legacy(PairRequest),
+}
+
+impl Incoming {
+    /// Parse a client frame; messages carrying `v` use the versioned protocol
+    fn parse(text: &str) -> Result<Self, (ErrorCode, String)> {
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| (ErrorCode::InvalidJson, e.to_string()))?;
+
+        let parsed = if let Some(v) = value.get("v") {
+            if v.as_u64() != Some(PROTOCOL_VERSION as u64) {
+                return Err((
+                    ErrorCode::UnsupportedVersion,
+                    format!(
+                        "Protocol version {} is not supported, use {}",
+                        v, PROTOCOL_VERSION
+                    ),
+                ));
+            }
+            serde_json::from_value(value).map(Incoming::Message)
+        } else {
+            serde_json::from_value(value).map(Incoming::Legacy)
+        };
+        parsed.map_err(|e| (ErrorCode::InvalidMessage, e.to_string()))
+    }
+}
+
+// --- Server Messages ---
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ChannelData {
+    History(Vec<PriceUpdate>),
+    Prices {
+        prices: Vec<PriceUpdate>,
+        venues: HashMap<String, VenueStatus>,
+        stale_sources: Vec<String>,
+    },
+    Opportunity(ArbitrageOpportunity),
+    Triangular(TriangularOpportunity),
+    Book(BookSnapshot),
+    Candle(Candle),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidJson,
+    InvalidMessage, // Valid JSON that is not a known client message
+    UnsupportedVersion,
+    InvalidPair,
+    UnsupportedPair, // No enabled venue serves the pair
+    NotSubscribed,
+}
+
+/// A registered venue that will not stream the requested pair
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueRejection {
+    pub venue: &'static str,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// Subscription change applied. `channels` is what the pair is streaming now.
+    Ack {
+        action: &'static str,
+        pair: String,
+        channels: Vec<Channel>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        venues: Vec<&'static str>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        rejected: Vec<VenueRejection>,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pair: Option<String>,
+    },
+    /// Connection state of the pair's venues, sent whenever it changes
+    VenueStatus {
+        pair: String,
+        venues: HashMap<String, VenueStatus>,
+    },
+    /// Channel payload, tagged with the pair and channel it belongs to
+    Data {
+        pair: String,
+        channel: Channel,
+        data: ChannelData,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerMessage {
+    pub v: u32,
+    #[serde(flatten)]
+    pub event: ServerEvent,
+}
+
+impl ServerMessage {
+    fn new(event: ServerEvent) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            event,
+        }
+    }
+
+    fn data(pair: &str, channel: Channel, data: ChannelData) -> Self {
+        Self::new(ServerEvent::Data {
+            pair: pair.to_string(),
+            channel,
+            data,
+        })
+    }
+
+    fn error(code: ErrorCode, message: impl Into<String>, pair: Option<&str>) -> Self {
+        Self::new(ServerEvent::Error {
+            code,
+            message: message.into(),
+            pair: pair.map(str::to_string),
+        })
+    }
+}
+
+/// What goes out on the socket: versioned messages, or the bare payloads of the original
+/// single-pair protocol for clients that still speak it
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Outgoing {
+    Message(ServerMessage),
+    /// Cached prices, sent once on subscribe
+    History(Vec<PriceUpdate>),
+    /// Every engine feed of the pair
+    Feed(ArbitrageFeed),
+    /// Loops through the requested `via` currencies
+    Triangular(TriangularOpportunity),
+}
+
+impl From<ServerMessage> for Outgoing {
+    fn from(message: ServerMessage) -> Self {
+        Outgoing::Message(message)
+    }
+}
+
+/// `sol/usdt` -> `SOL/USDT`, `None` unless it is two non-empty currencies
+pub fn normalize_pair(pair: &str) -> Option<String> {
+    let (base, quote) = pair.trim().split_once('/')?;
+    if base.is_empty() || quote.is_empty() || quote.contains('/') {
+        return None;
+    }
+    Some(format!("{}/{}", base.to_uppercase(), quote.to_uppercase()))
+}
+
+// --- Per-Pair Stream ---
+
+/// Forwards one pair's hub feed to the client. Dropping it releases the pair.
+struct PairStream {
+    channels: Arc<Mutex<HashSet<Channel>>>,
+    via: Vec<String>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for PairStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Split every feed into the channels the client currently wants
+async fn forward_pair(
+    pair: String,
+    mut subscription: Subscription,
+    mut triangles: Option<TriangleWatch>,
+    channels: Arc<Mutex<HashSet<Channel>>>,
+    books: Arc<BookStore>,
+    cache: Arc<Mutex<MarketCache>>,
+    tx: mpsc::Sender<Outgoing>,
+) {
+    let mut book_times: HashMap<String, u64> = HashMap::new();
+    let mut candle_ticks: HashMap<(String, Interval), (u64, u64)> = HashMap::new();
+    let mut venue_status: HashMap<String, VenueStatus> = HashMap::new();
+
+    loop {
+        let messages = tokio::select! {
+            feed = subscription.recv() => {
+                let Some(feed) = feed else { break };
+                let mut messages = Vec::new();
+                if feed.venues != venue_status {
+                    venue_status = feed.venues.clone();
+                    messages.push(ServerMessage::new(ServerEvent::VenueStatus {
+                        pair: pair.clone(),
+                        venues: feed.venues.clone(),
+                    }));
+                }
+
+                let channels = channels.lock().unwrap().clone();
+                messages.extend(feed_messages(&pair, feed, &channels, &books, &mut book_times));
+                if channels.contains(&Channel::Candles) {
+                    messages.extend(candle_messages(&pair, &cache, &mut candle_ticks));
+                }
+                messages
+            }
+            opp = async { triangles.as_mut()?.recv().await }, if triangles.is_some() => {
+                let Some(opp) = opp else {
+                    triangles = None;
+                    continue;
+                };
+                if !channels.lock().unwrap().contains(&Channel::Opportunities) {
+                    continue;
+                }
+                let data = ChannelData::Triangular(opp);
+                vec![ServerMessage::data(&pair, Channel::Opportunities, data)]
+            }
+        };
+
+        for message in messages {
+            if tx.send(message.into()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The original protocol: every feed as is, plus any triangular loop
+async fn forward_legacy(
+    mut subscription: Subscription,
+    mut triangles: Option<TriangleWatch>,
+    tx: mpsc::Sender<Outgoing>,
+) {
+    loop {
+        let frame = tokio::select! {
+            feed = subscription.recv() => {
+                let Some(feed) = feed else { break };
+                Outgoing::Feed(feed)
+            }
+            opp = async { triangles.as_mut()?.recv().await }, if triangles.is_some() => {
+                let Some(opp) = opp else {
+                    triangles = None;
+                    continue;
+                };
+                Outgoing::Triangular(opp)
+            }
+        };
+
+        if tx.send(frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn feed_messages(
+    pair: &str,
+    feed: ArbitrageFeed,
+    channels: &HashSet<Channel>,
+    books: &BookStore,
+    book_times: &mut HashMap<String, u64>,
+) -> Vec<ServerMessage> {
+    let mut messages = Vec::new();
+
+    if channels.contains(&Channel::Prices) {
+        messages.push(ServerMessage::data(
+            pair,
+            Channel::Prices,
+            ChannelData::Prices {
+                prices: feed.prices,
+                venues: feed.venues,
+                stale_sources: feed.stale_sources,
+            },
+        ));
+    }
+
+    // Only while two venues are fresh enough to trade between
+    if channels.contains(&Channel::Opportunities)
+        && let Some(opportunity) = feed.opportunity
+    {
+        messages.push(ServerMessage::data(
+            pair,
+            Channel::Opportunities,
+            ChannelData::Opportunity(opportunity),
+        ));
+    }
+
+    // Books that changed since we last pushed them
+    if channels.contains(&Channel::Books) {
+        for book in books.for_pair(pair) {
+            if book_times.get(&book.venue) == Some(&book.timestamp) {
+                continue;
+            }
+            book_times.insert(book.venue.clone(), book.timestamp);
+            messages.push(ServerMessage::data(
+                pair,
+                Channel::Books,
+                ChannelData::Book(book.truncated(BOOK_DEPTH)),
+            ));
+        }
+    }
+
+    messages
+}
+
+fn invalid_pair(pair: &str) -> ServerMessage {
+    ServerMessage::error(
+        ErrorCode::InvalidPair,
+        format!("Invalid pair {:?}, expected BASE/QUOTE", pair),
+        None,
+    )
+}
+
+/// Channels in declaration order, for stable acks
+fn sorted(channels: &HashSet<Channel>) -> Vec<Channel> {
+    Channel::ALL
+        .into_iter()
+        .filter(|c| channels.contains(c))
+        .collect()
+}
+
+/// Current candles that moved since we last pushed them, keyed by (venue, interval)
+fn candle_messages(
+    pair: &str,
+    cache: &Mutex<MarketCache>,
+    sent: &mut HashMap<(String, Interval), (u64, u64)>,
+) -> Vec<ServerMessage> {
+    let current = cache.lock().unwrap().candles.current(pair);
+
+    current
+        .into_iter()
+        .filter(|candle| {
+            let key = (candle.venue.clone(), candle.interval);
+            let version = (candle.open_time, candle.ticks);
+            sent.insert(key, version) != Some(version)
+        })
+        .map(|candle| ServerMessage::data(pair, Channel::Candles, ChannelData::Candle(candle)))
+        .collect()
+}
+
+// --- Client Session ---
+
+/// Pairs and channels one WebSocket client is subscribed to.
+/// Messages for the client arrive on the receiver returned by `new`.
+pub struct ClientSession {
+    registry: Arc<ConnectorRegistry>,
+    cache: Arc<Mutex<MarketCache>>,
+    books: Arc<BookStore>,
+    fees: Arc<FeeModel>,
+    engine_config: EngineConfig,
+    hub: Arc<ConnectorHub>,
+    db: Option<Arc<Persistence>>, // History from before the cache window
+    tx: mpsc::Sender<Outgoing>,
+    pairs: HashMap<String, PairStream>,
+    legacy: bool, // Set by a `{"token_a", "token_b"}` request, answered in the original frames
+}
+
+impl ClientSession {
+    pub fn new(
+        registry: Arc<ConnectorRegistry>,
+        cache: Arc<Mutex<MarketCache>>,
+        books: Arc<BookStore>,
+        fees: Arc<FeeModel>,
+        engine_config: EngineConfig,
+        hub: Arc<ConnectorHub>,
+        db: Option<Arc<Persistence>>,
+    ) -> (Self, mpsc::Receiver<Outgoing>) {
+        let (tx, rx) = mpsc::channel(1000);
+        let session = Self {
+            registry,
+            cache,
+            books,
+            fees,
+            engine_config,
+            hub,
+            db,
+            tx,
+            pairs: HashMap::new(),
+            legacy: false,
+        };
+        (session, rx)
+    }
+
+    /// Apply a client frame, returning what has to be sent right away
+    /// (acks, errors and cached history)
+    pub async fn handle(&mut self, text: &str) -> Vec<Outgoing> {
+        let incoming = match Incoming::parse(text) {
+            Ok(incoming) => incoming,
+            Err((code, message)) => return vec![ServerMessage::error(code, message, None).into()],
+        };
+
+        let action = match incoming {
+            Incoming::Message(msg) => msg.action,
+            // The original protocol streamed prices and opportunities of one pair
+            Incoming::Legacy(req) => {
+                self.legacy = true;
+                Action::Subscribe {
+                    pairs: vec![format!("{}/{}", req.token_a, req.token_b)],
+                    channels: vec![Channel::Prices, Channel::Opportunities],
+                    via: req.via,
+                }
+            }
+        };
+
+        let replies: Vec<ServerMessage> = match action {
+            Action::Subscribe {
+                pairs,
+                channels,
+                via,
+            } => {
+                let channels = if channels.is_empty() {
+                    Channel::ALL.to_vec()
+                } else {
+                    channels
+                };
+                let mut replies = Vec::new();
+                for pair in &pairs {
+                    replies.extend(self.subscribe(pair, &channels, &via).await);
+                }
+                replies
+            }
+            Action::Unsubscribe { pairs, channels } => pairs
+                .iter()
+                .map(|pair| self.unsubscribe(pair, &channels))
+                .collect(),
+        };
+
+        if !self.legacy {
+            return replies.into_iter().map(Outgoing::from).collect();
+        }
+        // The original protocol had no acks or errors, only the cached history
+        replies
+            .into_iter()
+            .filter_map(|reply| match reply.event {
+                ServerEvent::Data {
+                    data: ChannelData::History(history),
+                    ..
+                } => Some(Outgoing::History(history)),
+                ServerEvent::Error { message, .. } => {
+                    eprintln!("Legacy client request failed: {}", message);
+                    None
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Add `channels` for `pair`, starting its stream if needed. Answers with an ack
+    /// (plus the cached history when the prices channel is newly added) or an error.
+    async fn subscribe(
+        &mut self,
+        pair: &str,
+        channels: &[Channel],
+        via: &[String],
+    ) -> Vec<ServerMessage> {
+        let Some(pair) = normalize_pair(pair) else {
+            return vec![invalid_pair(pair)];
+        };
+
+        // Which venues take the pair, and why the others don't
+        let (mut venues, mut rejected) = (Vec::new(), Vec::new());
+        for venue in self.registry.venues(Some(&pair)) {
+            match (venue.enabled, venue.supports_pair) {
+                (true, Some(true)) => venues.push(venue.name),
+                (false, _) => rejected.push(VenueRejection {
+                    venue: venue.name,
+                    reason: "venue disabled",
+                }),
+                _ => rejected.push(VenueRejection {
+                    venue: venue.name,
+                    reason: "pair not supported",
+                }),
+            }
+        }
+        if venues.is_empty() {
+            return vec![ServerMessage::error(
+                ErrorCode::UnsupportedPair,
+                format!("No enabled venue serves {}", pair),
+                Some(&pair),
+            )];
+        }
+        let via: Vec<String> = via.iter().map(|v| v.to_uppercase()).collect();
+
+        let existing = self.pairs.get(&pair);
+        let had_prices = existing
+            .is_some_and(|stream| stream.channels.lock().unwrap().contains(&Channel::Prices));
+
+        // A different set of triangle legs needs a fresh stream. The new one joins the hub
+        // before the old one is dropped so the pair's connectors keep running.
+        let restart = existing.is_some_and(|stream| !via.is_empty() && stream.via != via);
+        if existing.is_none() || restart {
+            let channels = existing
+                .map(|stream| stream.channels.lock().unwrap().clone())
+                .unwrap_or_default();
+            let stream = self.start(&pair, channels, via);
+            self.pairs.insert(pair.clone(), stream);
+        }
+        let active = {
+            let mut current = self.pairs[&pair].channels.lock().unwrap();
+            current.extend(channels.iter().copied());
+            sorted(&current)
+        };
+
+        let mut messages = vec![ServerMessage::new(ServerEvent::Ack {
+            action: "subscribe",
+            pair: pair.clone(),
+            channels: active,
+            venues,
+            rejected,
+        })];
+
+        // Cached prices, or the latest stored ones when the cache has none yet
+        if !had_prices && channels.contains(&Channel::Prices) {
+            let db = self.db.as_deref();
+            match persistence::load_history(&self.cache, db, &pair, None, None).await {
+                Ok(history) if !history.is_empty() => messages.push(ServerMessage::data(
+                    &pair,
+                    Channel::Prices,
+                    ChannelData::History(history),
+                )),
+                Ok(_) => {}
+                Err(e) => eprintln!("DB Failed to load history of {}: {}", pair, e),
+            }
+        }
+
+        messages
+    }
+
+    /// Drop `channels` of `pair` (all of them when empty), releasing the pair once none are left
+    fn unsubscribe(&mut self, pair: &str, channels: &[Channel]) -> ServerMessage {
+        let Some(pair) = normalize_pair(pair) else {
+            return invalid_pair(pair);
+        };
+        let Some(stream) = self.pairs.get(&pair) else {
+            return ServerMessage::error(
+                ErrorCode::NotSubscribed,
+                format!("Not subscribed to {}", pair),
+                Some(&pair),
+            );
+        };
+
+        let remaining = {
+            let mut current = stream.channels.lock().unwrap();
+            if channels.is_empty() {
+                current.clear();
+            } else {
+                current.retain(|c| !channels.contains(c));
+            }
+            sorted(&current)
+        };
+
+        if remaining.is_empty() {
+            self.pairs.remove(&pair);
+            println!("Client left: {}", pair);
+        }
+
+        ServerMessage::new(ServerEvent::Ack {
+            action: "unsubscribe",
+            pair,
+            channels: remaining,
+            venues: Vec::new(),
+            rejected: Vec::new(),
+        })
+    }
+
+    fn start(&self, pair: &str, channels: HashSet<Channel>, via: Vec<String>) -> PairStream {
+        let venues: Vec<&str> = self
+            .registry
+            .for_pair(pair)
+            .iter()
+            .map(|c| c.name())
+            .collect();
+        println!(
+            "Client subscribed to: {} (venues: {})",
+            pair,
+            venues.join(", ")
+        );
+
+        // Join the shared feed for this pair (spawns connectors on first subscriber)
+        let subscription = self.hub.subscribe(pair);
+
+        // Optional triangular loops base/quote -> base/via -> via/quote on each venue
+        let (base, quote) = pair.split_once('/').unwrap_or_default();
+        let triangles: Vec<Triangle> = via
+            .iter()
+            .filter(|v| v.as_str() != base && v.as_str() != quote)
+            .map(|v| Triangle::new(base, quote, v))
+            .collect();
+        let triangles = (!triangles.is_empty()).then(|| {
+            triangular::watch(
+                &self.hub,
+                Arc::clone(&self.fees),
+                self.engine_config.clone(),
+                triangles,
+            )
+        });
+
+        let channels = Arc::new(Mutex::new(channels));
+        if self.legacy {
+            let task = tokio::spawn(forward_legacy(subscription, triangles, self.tx.clone()));
+            return PairStream {
+                channels,
+                via,
+                task,
+            };
+        }
+        let task = tokio::spawn(forward_pair(
+            pair.to_string(),
+            subscription,
+            triangles,
+            Arc::clone(&channels),
+            Arc::clone(&self.books),
+            Arc::clone(&self.cache),
+            self.tx.clone(),
+        ));
+
+        PairStream {
+            channels,
+            via,
+            task,
+        }
+    }
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        for pair in self.pairs.keys() {
+            println!("Client left: {}", pair);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetRegistry;
+    use crate::connectors::{Connector, ConnectorContext, VenueKind, now_ms};
+    use crate::fx::FxTracker;
+    use futures_util::future::BoxFuture;
+    use serde_json::{Value, json};
+    use tokio::time::{Duration, timeout};
+
+    /// Lists its pairs, sends `price` once when set and sits idle until cancelled
+    struct IdleConnector {
+        name: &'static str,
+        pairs: &'static [&'static str],
+        price: Option<f64>,
+    }
+
+    impl Connector for IdleConnector {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn kind(&self) -> VenueKind {
+            VenueKind::Cex
+        }
+
+        fn supported_pairs(&self, _assets: &AssetRegistry) -> Option<Vec<String>> {
+            Some(self.pairs.iter().map(|p| p.to_string()).collect())
+        }
+
+        fn run(&self, ctx: ConnectorContext) -> BoxFuture<'static, ()> {
+            let (name, price) = (self.name, self.price);
+            ctx.run(move |ctx| async move {
+                if let Some(price) = price {
+                    let _ = ctx
+                        .tx
+                        .send(PriceUpdate::single(name, &ctx.pair, price, now_ms()));
+                }
+                std::future::pending().await
+            })
+        }
+    }
+
+    /// Session over "Listed" (SOL/USDC, quotes 150 once), "Elsewhere" (BTC/USDC only)
+    /// and the disabled "Off", with the receiver of its streamed frames and the cache
+    fn session() -> (
+        ClientSession,
+        mpsc::Receiver<Outgoing>,
+        Arc<Mutex<MarketCache>>,
+    ) {
+        let mut registry = ConnectorRegistry::new(Arc::new(AssetRegistry::builtin()));
+        for (name, pairs, price) in [
+            ("Listed", &["SOL/USDC"][..], Some(150.0)),
+            ("Elsewhere", &["BTC/USDC"][..], None),
+            ("Off", &["SOL/USDC"][..], None),
+        ] {
+            registry.register(IdleConnector { name, pairs, price });
+        }
+        registry.disable("Off");
+
+        let registry = Arc::new(registry);
+        let cache = Arc::new(Mutex::new(MarketCache::new(10, &EngineConfig::default())));
+        let books = Arc::new(BookStore::default());
+        let fees = Arc::new(FeeModel::empty());
+        let hub = ConnectorHub::new(
+            registry.clone(),
+            cache.clone(),
+            books.clone(),
+            fees.clone(),
+            Arc::new(FxTracker::default()),
+            EngineConfig::default(),
+            None,
+        );
+        let (session, rx) = ClientSession::new(
+            registry,
+            cache.clone(),
+            books,
+            fees,
+            EngineConfig::default(),
+            hub,
+            None,
+        );
+        (session, rx, cache)
+    }
+
+    /// The single message answering `text`
+    async fn reply(session: &mut ClientSession, text: &str) -> Value {
+        let messages = session.handle(text).await;
+        assert_eq!(messages.len(), 1, "{:?}", messages);
+        as_json(&messages[0])
+    }
+
+    /// Code and pair of the error answering `text`
+    async fn code(session: &mut ClientSession, text: &str) -> (Value, Value) {
+        let error = reply(session, text).await;
+        assert_eq!(error["type"], "error");
+        (error["code"].clone(), error["pair"].clone())
+    }
+
+    fn parse(text: &str) -> Incoming {
+        Incoming::parse(text).unwrap()
+    }
+
+    fn as_json(message: &impl Serialize) -> Value {
+        serde_json::to_value(message).unwrap()
+    }
+
+    #[test]
+    fn parses_subscribe_and_unsubscribe() {
+        let incoming = parse(
+            r#"{"v": 1, "action": "subscribe", "pairs": ["SOL/USDT", "btc/usdc"],
+                "channels": ["prices", "books"], "via": ["ETH"]}"#,
+        );
+        let Incoming::Message(ClientMessage {
+            v: 1,
+            action:
+                Action::Subscribe {
+                    pairs,
+                    channels,
+                    via,
+                },
+        }) = incoming
+        else {
+            panic!("not a subscribe: {:?}", incoming);
+        };
+        assert_eq!(pairs, ["SOL/USDT", "btc/usdc"]);
+        assert_eq!(channels, [Channel::Prices, Channel::Books]);
+        assert_eq!(via, ["ETH"]);
+
+        // No channels means all of them, resolved when applied
+        let incoming = parse(r#"{"v": 1, "action": "unsubscribe", "pairs": ["SOL/USDT"]}"#);
+        let Incoming::Message(ClientMessage {
+            action: Action::Unsubscribe { pairs, channels },
+            ..
+        }) = incoming
+        else {
+            panic!("not an unsubscribe: {:?}", incoming);
+        };
+        assert_eq!(pairs, ["SOL/USDT"]);
+        assert!(channels.is_empty());
+
+        // Frames without `v` are the original single-pair request
+        let incoming = parse(r#"{"token_a": "SOL", "token_b": "USDT"}"#);
+        assert!(
+            matches!(incoming, Incoming::Legacy(PairRequest { token_a, .. }) if token_a == "SOL")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_actions() {
+        let code = |text: &str| Incoming::parse(text).unwrap_err().0;
+        assert_eq!(
+            code(r#"{"v": 2, "action": "subscribe", "pairs": ["SOL/USDT"]}"#),
+            ErrorCode::UnsupportedVersion
+        );
+        assert_eq!(
+            code(r#"{"v": "1", "action": "subscribe", "pairs": ["SOL/USDT"]}"#),
+            ErrorCode::UnsupportedVersion
+        );
+        assert_eq!(
+            code(r#"{"v": 1, "action": "replace", "pairs": ["SOL/USDT"]}"#),
+            ErrorCode::InvalidMessage
+        );
+        assert_eq!(
+            code(r#"{"v": 1, "action": "subscribe", "channels": ["ticks"], "pairs": []}"#),
+            ErrorCode::InvalidMessage
+        );
+    }
+
+    #[test]
+    fn outbound_messages_carry_pair_and_channel() {
+        let feed = ArbitrageFeed {
+            prices: vec![PriceUpdate::single("Orca", "SOL/USDC", 150.0, 1)],
+            opportunity: None,
+            venues: HashMap::new(),
+            stale_sources: vec!["Orca".to_string()],
+        };
+        let channels = HashSet::from([Channel::Prices, Channel::Opportunities]);
+        let messages = feed_messages(
+            "SOL/USDC",
+            feed,
+            &channels,
+            &BookStore::default(),
+            &mut HashMap::new(),
+        );
+
+        // No opportunity without two fresh venues, prices go out regardless
+        assert_eq!(messages.len(), 1);
+        let message = as_json(&messages[0]);
+        assert_eq!(message["v"], PROTOCOL_VERSION);
+        assert_eq!(message["type"], "data");
+        assert_eq!(message["pair"], "SOL/USDC");
+        assert_eq!(message["channel"], "prices");
+        assert_eq!(message["data"]["stale_sources"], json!(["Orca"]));
+        assert_eq!(message["data"]["prices"][0]["source"], "Orca");
+
+        let book = BookSnapshot::constant_product("Orca", "SOL/USDC", 1.0, 150.0, 1);
+        let message = as_json(&ServerMessage::data(
+            "SOL/USDC",
+            Channel::Books,
+            ChannelData::Book(book),
+        ));
+        assert_eq!(message["channel"], "books");
+        assert_eq!(message["data"]["venue"], "Orca");
+    }
+
+    #[tokio::test]
+    async fn acks_list_the_venues_that_were_left_out() {
+        let (mut session, ..) = session();
+        let ack = reply(
+            &mut session,
+            r#"{"v": 1, "action": "subscribe", "pairs": ["sol/usdc"], "channels": ["books"]}"#,
+        )
+        .await;
+        assert_eq!(ack["type"], "ack");
+        assert_eq!(ack["action"], "subscribe");
+        assert_eq!(ack["pair"], "SOL/USDC");
+        assert_eq!(ack["channels"], json!(["books"]));
+        assert_eq!(ack["venues"], json!(["Listed"]));
+        assert_eq!(
+            ack["rejected"],
+            json!([
+                {"venue": "Elsewhere", "reason": "pair not supported"},
+                {"venue": "Off", "reason": "venue disabled"},
+            ])
+        );
+
+        // Unsubscribing drops channels until none are left
+        let ack = reply(
+            &mut session,
+            r#"{"v": 1, "action": "subscribe", "pairs": ["SOL/USDC"], "channels": ["candles"]}"#,
+        )
+        .await;
+        assert_eq!(ack["channels"], json!(["books", "candles"]));
+        let ack = reply(
+            &mut session,
+            r#"{"v": 1, "action": "unsubscribe", "pairs": ["SOL/USDC"], "channels": ["books"]}"#,
+        )
+        .await;
+        assert_eq!(ack["channels"], json!(["candles"]));
+        assert!(ack.get("rejected").is_none());
+        let ack = reply(
+            &mut session,
+            r#"{"v": 1, "action": "unsubscribe", "pairs": ["SOL/USDC"]}"#,
+        )
+        .await;
+        assert_eq!(ack["channels"], json!([]));
+        assert!(session.pairs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn errors_carry_their_code() {
+        let (mut session, ..) = session();
+        assert_eq!(code(&mut session, "{not json").await.0, "invalid_json");
+        assert_eq!(
+            code(
+                &mut session,
+                r#"{"v": 1, "action": "subscribe", "pairs": ["ETH/EUR"]}"#
+            )
+            .await,
+            (json!("unsupported_pair"), json!("ETH/EUR"))
+        );
+        assert_eq!(
+            code(
+                &mut session,
+                r#"{"v": 1, "action": "subscribe", "pairs": ["SOLUSDC"]}"#
+            )
+            .await
+            .0,
+            "invalid_pair"
+        );
+        assert_eq!(
+            code(
+                &mut session,
+                r#"{"v": 1, "action": "unsubscribe", "pairs": ["SOL/USDC"]}"#
+            )
+            .await,
+            (json!("not_subscribed"), json!("SOL/USDC"))
+        );
+        assert!(session.pairs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn legacy_requests_get_the_original_frames() {
+        let (mut session, mut rx, cache) = session();
+        let cached = PriceUpdate::single("Listed", "SOL/USDC", 149.0, 1);
+        cache.lock().unwrap().add(cached.clone());
+
+        // No ack and no envelope: the bare history array
+        let replies = session
+            .handle(r#"{"token_a": "sol", "token_b": "usdc"}"#)
+            .await;
+        assert_eq!(replies.len(), 1);
+        assert_eq!(as_json(&replies[0]), json!([as_json(&cached)]));
+
+        // Then every feed as the bare `ArbitrageFeed`
+        let frame = timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let frame = as_json(&frame);
+        let keys: Vec<&str> = frame
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        assert_eq!(keys, ["opportunity", "prices", "stale_sources", "venues"]);
+        assert_eq!(frame["prices"][0]["source"], "Listed");
+        assert_eq!(frame["prices"][0]["price"], 150.0);
+        assert_eq!(frame["opportunity"], Value::Null);
+
+        // Failures stay off the wire, the original protocol had no errors
+        assert!(
+            session
+                .handle(r#"{"token_a": "ETH", "token_b": "EUR"}"#)
+                .await
+                .is_empty()
+        );
+    }
+}
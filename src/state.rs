@@ -1,12 +1,121 @@
+use crate::arbitrage_engine::EngineConfig;
+use crate::candles::{Candle, CandleStore};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceUpdate {
     pub source: String,
     pub pair: String,
+    pub price: f64, // Mid for order books, pool/aggregator price for DEXes
+    pub bid: f64,   // Best price we can sell at
+    pub ask: f64,   // Best price we can buy at
+    pub bid_size: Option<f64>,
+    pub ask_size: Option<f64>,
+    pub timestamp: u64,       // Unix timestamp (ms)
+    pub native_quote: String, // Quote currency the venue actually priced in
+    pub fx_rate: f64,         // Requested-quote units per native-quote unit applied to the prices
+    /// Size of the trade behind this update, for venues streaming trades (candle volume)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trade_size: Option<f64>,
+    /// Solana slot the on-chain state behind this update was read at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+    /// Pools behind a DEX price taken across several of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pools: Vec<PoolPrice>,
+}
+
+/// Price of one on-chain pool, tagged with its address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolPrice {
+    pub address: String,
     pub price: f64,
-    pub timestamp: u64, // Unix timestamp (ms)
+    pub liquidity: f64, // Quote tokens on the curve at the current price
+}
+
+/// Quote currency of a canonical pair ("SOL/USDC" -> "USDC")
+pub fn quote_currency(pair: &str) -> &str {
+    pair.split_once('/').map_or(pair, |(_, quote)| quote)
+}
+
+impl PriceUpdate {
+    /// Top-of-book quote from an order-book venue
+    pub fn quote(
+        source: &str,
+        pair: &str,
+        bid: f64,
+        bid_size: f64,
+        ask: f64,
+        ask_size: f64,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            source: source.to_string(),
+            pair: pair.to_string(),
+            price: (bid + ask) / 2.0,
+            bid,
+            ask,
+            bid_size: Some(bid_size),
+            ask_size: Some(ask_size),
+            timestamp,
+            native_quote: quote_currency(pair).to_string(),
+            fx_rate: 1.0,
+            trade_size: None,
+            slot: None,
+            pools: Vec::new(),
+        }
+    }
+
+    /// Single price with no book behind it (pools, aggregators): bid == ask == price
+    pub fn single(source: &str, pair: &str, price: f64, timestamp: u64) -> Self {
+        Self {
+            source: source.to_string(),
+            pair: pair.to_string(),
+            price,
+            bid: price,
+            ask: price,
+            bid_size: None,
+            ask_size: None,
+            timestamp,
+            native_quote: quote_currency(pair).to_string(),
+            fx_rate: 1.0,
+            trade_size: None,
+            slot: None,
+            pools: Vec::new(),
+        }
+    }
+
+    /// Mark prices as quoted in `quote` rather than the pair's own quote currency
+    pub fn in_native_quote(mut self, quote: &str) -> Self {
+        self.native_quote = quote.to_string();
+        self
+    }
+
+    /// Tag the update with the slot its account data was read at
+    pub fn at_slot(mut self, slot: Option<u64>) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// Tag the update with the pools it was priced from
+    pub fn with_pools(mut self, pools: Vec<PoolPrice>) -> Self {
+        self.pools = pools;
+        self
+    }
+
+    /// Convert prices into the pair's quote currency at `rate` per native unit
+    pub fn convert(&mut self, rate: f64) {
+        self.price *= rate;
+        self.bid *= rate;
+        self.ask *= rate;
+        for pool in &mut self.pools {
+            pool.price *= rate;
+            pool.liquidity *= rate;
+        }
+        self.fx_rate = rate;
+    }
 }
 
 // --- In-Memory Cache System ---
@@ -15,17 +124,31 @@ pub struct MarketCache {
     // Stores history per pair: "SOL/USDT" -> [Price1, Price2, ...]
     pub vaults: HashMap<String, VecDeque<PriceUpdate>>,
     pub max_per_pair: usize,
+    // Last price of every venue per pair, kept however busy the other venues are
+    pub latest: HashMap<String, HashMap<String, PriceUpdate>>,
+    // 1s/1m/5m/1h OHLCV per venue + composite
+    pub candles: CandleStore,
 }
 
 impl MarketCache {
-    pub fn new(max_per_pair: usize) -> Self {
+    /// `engine_config` decides when a venue drops out of the composite candles
+    pub fn new(max_per_pair: usize, engine_config: &EngineConfig) -> Self {
         Self {
             vaults: HashMap::new(),
             max_per_pair,
+            latest: HashMap::new(),
+            candles: CandleStore::new(engine_config.clone()),
         }
     }
 
-    pub fn add(&mut self, update: PriceUpdate) {
+    /// Store an update, returning the candles it closed
+    pub fn add(&mut self, update: PriceUpdate) -> Vec<Candle> {
+        let closed = self.candles.add(&update);
+        self.latest
+            .entry(update.pair.clone())
+            .or_default()
+            .insert(update.source.clone(), update.clone());
+
         // Get or create the deque for this specific pair
         let history = self
             .vaults
@@ -37,6 +160,8 @@ impl MarketCache {
             history.pop_front(); // Remove oldest
         }
         history.push_back(update); // Add newest
+
+        closed
     }
 
     pub fn get_history(&self, pair: &str) -> Vec<PriceUpdate> {
@@ -45,5 +170,96 @@ impl MarketCache {
             None => Vec::new(),
         }
     }
+
+    /// History of `pair` with `from <= timestamp <= to` (ms), either bound optional
+    pub fn history_between(
+        &self,
+        pair: &str,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Vec<PriceUpdate> {
+        self.vaults.get(pair).map_or(Vec::new(), |deque| {
+            deque
+                .iter()
+                .filter(|u| from.is_none_or(|from| u.timestamp >= from))
+                .filter(|u| to.is_none_or(|to| u.timestamp <= to))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Timestamp of the oldest cached price of `pair`
+    pub fn oldest(&self, pair: &str) -> Option<u64> {
+        self.vaults.get(pair)?.front().map(|u| u.timestamp)
+    }
+
+    /// Latest price of each venue quoting `pair`, sorted by venue
+    pub fn latest(&self, pair: &str) -> Vec<PriceUpdate> {
+        let mut prices: Vec<PriceUpdate> = self
+            .latest
+            .get(pair)
+            .map_or(Vec::new(), |venues| venues.values().cloned().collect());
+        prices.sort_by(|a, b| a.source.cmp(&b.source));
+        prices
+    }
+
+    /// Every pair with cached prices
+    pub fn pairs(&self) -> Vec<String> {
+        self.vaults.keys().cloned().collect()
+    }
+}
+
+// --- Venue Connection Health ---
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VenueStatus {
+    pub connected: bool,
+    /// Up on a fallback path, e.g. a DEX polling RPC while its subscription is down
+    pub degraded: bool,
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+}
+
+/// Connection state of every venue feeding one pair
+#[derive(Default)]
+pub struct VenueMonitor {
+    venues: Mutex<HashMap<String, VenueStatus>>,
 }
 
+impl VenueMonitor {
+    pub fn connected(&self, venue: &str) {
+        let mut venues = self.venues.lock().unwrap();
+        let status = venues.entry(venue.to_string()).or_default();
+        status.connected = true;
+        status.degraded = false;
+        status.last_error = None;
+    }
+
+    /// Still delivering prices, but not the way it should; `reason` is kept as the error
+    pub fn degraded(&self, venue: &str, reason: &str) {
+        let mut venues = self.venues.lock().unwrap();
+        let status = venues.entry(venue.to_string()).or_default();
+        status.connected = true;
+        status.degraded = true;
+        status.last_error = Some(reason.to_string());
+    }
+
+    pub fn disconnected(&self, venue: &str, error: Option<String>) {
+        let mut venues = self.venues.lock().unwrap();
+        let status = venues.entry(venue.to_string()).or_default();
+        status.connected = false;
+        status.degraded = false;
+        if error.is_some() {
+            status.last_error = error;
+        }
+    }
+
+    pub fn reconnecting(&self, venue: &str) {
+        let mut venues = self.venues.lock().unwrap();
+        venues.entry(venue.to_string()).or_default().reconnects += 1;
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, VenueStatus> {
+        self.venues.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,335 @@
+use crate::arbitrage_engine::EngineConfig;
+use crate::connectors::now_ms;
+use crate::fees::FeeModel;
+use crate::hub::ConnectorHub;
+use crate::state::{PriceUpdate, quote_currency};
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// One trade of a loop, at the venue's top of book
+#[derive(Debug, Clone, Serialize)]
+pub struct TriangleLeg {
+    pub pair: String,
+    pub side: TradeSide,
+    pub price: f64, // Ask when buying, bid when selling
+    pub fee_bps: f64,
+}
+
+/// Profitable loop through three pairs of a single venue, starting and ending in `path[0]`
+#[derive(Debug, Clone, Serialize)]
+pub struct TriangularOpportunity {
+    pub kind: &'static str, // "triangular", next to cross-venue opportunities on the same channel
+    pub venue: String,
+    pub path: Vec<String>, // Currencies in trade order, e.g. ["USDT", "SOL", "BTC", "USDT"]
+    pub legs: Vec<TriangleLeg>,
+    pub gross_return_percent: f64,
+    pub net_return_percent: f64, // After the taker fee of every leg
+    pub timestamp: u64,
+}
+
+/// `base`/`quote` traded directly or through `via`: legs base/quote, base/via, via/quote
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    base: String,
+    quote: String,
+    via: String,
+}
+
+impl Triangle {
+    pub fn new(base: &str, quote: &str, via: &str) -> Self {
+        Self {
+            base: base.to_string(),
+            quote: quote.to_string(),
+            via: via.to_string(),
+        }
+    }
+
+    fn direct(&self) -> String {
+        format!("{}/{}", self.base, self.quote)
+    }
+
+    fn cross(&self) -> String {
+        format!("{}/{}", self.base, self.via)
+    }
+
+    fn bridge(&self) -> String {
+        format!("{}/{}", self.via, self.quote)
+    }
+
+    pub fn pairs(&self) -> [String; 3] {
+        [self.direct(), self.cross(), self.bridge()]
+    }
+
+    /// Best of the two directions around the loop on `venue`, if it returns more than it costs
+    fn evaluate(
+        &self,
+        venue: &str,
+        prices: &HashMap<(String, String), PriceUpdate>,
+        fees: &FeeModel,
+    ) -> Option<TriangularOpportunity> {
+        let leg = |pair: String| prices.get(&(venue.to_string(), pair));
+        let (direct, cross, bridge) =
+            (leg(self.direct())?, leg(self.cross())?, leg(self.bridge())?);
+        if direct.ask <= 0.0 || cross.ask <= 0.0 || bridge.ask <= 0.0 {
+            return None;
+        }
+
+        let trade = |update: &PriceUpdate, side: TradeSide| TriangleLeg {
+            pair: update.pair.clone(),
+            side,
+            price: match side {
+                TradeSide::Buy => update.ask,
+                TradeSide::Sell => update.bid,
+            },
+            fee_bps: fees.taker_bps(venue, &update.pair),
+        };
+
+        let candidates = [
+            // quote -> base -> via -> quote
+            (
+                vec![&self.quote, &self.base, &self.via, &self.quote],
+                vec![
+                    trade(direct, TradeSide::Buy),
+                    trade(cross, TradeSide::Sell),
+                    trade(bridge, TradeSide::Sell),
+                ],
+            ),
+            // quote -> via -> base -> quote
+            (
+                vec![&self.quote, &self.via, &self.base, &self.quote],
+                vec![
+                    trade(bridge, TradeSide::Buy),
+                    trade(cross, TradeSide::Buy),
+                    trade(direct, TradeSide::Sell),
+                ],
+            ),
+        ];
+
+        candidates
+            .into_iter()
+            .map(|(path, legs)| {
+                let (mut gross, mut net) = (1.0, 1.0);
+                for leg in &legs {
+                    let rate = match leg.side {
+                        TradeSide::Buy => 1.0 / leg.price,
+                        TradeSide::Sell => leg.price,
+                    };
+                    gross *= rate;
+                    net *= rate * (1.0 - leg.fee_bps / 10_000.0);
+                }
+
+                TriangularOpportunity {
+                    kind: "triangular",
+                    venue: venue.to_string(),
+                    path: path.into_iter().cloned().collect(),
+                    legs,
+                    gross_return_percent: (gross - 1.0) * 100.0,
+                    net_return_percent: (net - 1.0) * 100.0,
+                    timestamp: direct.timestamp.max(cross.timestamp).max(bridge.timestamp),
+                }
+            })
+            .filter(|opp| opp.net_return_percent > 0.0)
+            .max_by(|a, b| a.net_return_percent.total_cmp(&b.net_return_percent))
+    }
+}
+
+// --- Watcher ---
+
+/// Triangular opportunities for one client. Dropping it releases every leg pair.
+pub struct TriangleWatch {
+    rx: mpsc::Receiver<TriangularOpportunity>,
+    task: JoinHandle<()>,
+}
+
+impl TriangleWatch {
+    pub async fn recv(&mut self) -> Option<TriangularOpportunity> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for TriangleWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Follow the venue prices of every leg of `triangles` through the hub and report loops
+/// that pay after fees. Legs quoted by a single venue count, the loop never leaves it.
+pub fn watch(
+    hub: &Arc<ConnectorHub>,
+    fees: Arc<FeeModel>,
+    config: EngineConfig,
+    triangles: Vec<Triangle>,
+) -> TriangleWatch {
+    let mut pairs: Vec<String> = triangles.iter().flat_map(Triangle::pairs).collect();
+    pairs.sort();
+    pairs.dedup();
+
+    // One merged stream over the venue prices of every leg pair
+    let updates: Vec<BoxStream<'static, _>> = pairs
+        .iter()
+        .map(|pair| {
+            stream::unfold(hub.subscribe_prices(pair), |mut subscription| async move {
+                let update = subscription.recv().await?;
+                Some((update, subscription))
+            })
+            .boxed()
+        })
+        .collect();
+    let mut updates = stream::select_all(updates);
+
+    let (tx, rx) = mpsc::channel(100);
+    let task = tokio::spawn(async move {
+        let mut prices: HashMap<(String, String), PriceUpdate> = HashMap::new();
+        let mut last_sent: HashMap<(String, String), f64> = HashMap::new();
+
+        while let Some(update) = updates.next().await {
+            // Loops are traded on one venue's own markets, converted prices don't count
+            if update.native_quote != quote_currency(&update.pair) {
+                continue;
+            }
+            let venue = update.source.clone();
+            prices.insert((venue.clone(), update.pair.clone()), update);
+
+            // Forget legs too old to trade on
+            prices.retain(|(venue, _), update| {
+                now_ms().saturating_sub(update.timestamp)
+                    <= config.max_age(venue).as_millis() as u64
+            });
+
+            // Only the venue that moved can have a new loop
+            for triangle in &triangles {
+                let Some(opp) = triangle.evaluate(&venue, &prices, &fees) else {
+                    continue;
+                };
+
+                // Only report a loop again once its return has moved
+                let key = (opp.venue.clone(), opp.path.join(">"));
+                if last_sent.get(&key) == Some(&opp.net_return_percent) {
+                    continue;
+                }
+                last_sent.insert(key, opp.net_return_percent);
+
+                if tx.send(opp).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    TriangleWatch { rx, task }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::{FeeSchedule, FeeTier};
+
+    const VENUE: &str = "Binance";
+
+    /// Top of book of the three legs of SOL/USDT through BTC on one venue
+    fn prices(sol_btc: (f64, f64)) -> HashMap<(String, String), PriceUpdate> {
+        [
+            ("SOL/USDT", (99.9, 100.0)),
+            ("SOL/BTC", sol_btc),
+            ("BTC/USDT", (50_000.0, 50_010.0)),
+        ]
+        .into_iter()
+        .map(|(pair, (bid, ask))| {
+            let update = PriceUpdate::quote(VENUE, pair, bid, 1.0, ask, 1.0, 1);
+            ((VENUE.to_string(), pair.to_string()), update)
+        })
+        .collect()
+    }
+
+    fn triangle() -> Triangle {
+        Triangle::new("SOL", "USDT", "BTC")
+    }
+
+    #[test]
+    fn loop_return_compounds_the_three_legs() {
+        let opp = triangle()
+            .evaluate(VENUE, &prices((0.0021, 0.00211)), &FeeModel::empty())
+            .unwrap();
+
+        // 1 USDT buys 1/100 SOL, sold for BTC at 0.0021, sold for USDT at 50000
+        assert_eq!(opp.path, ["USDT", "SOL", "BTC", "USDT"]);
+        let gross = 0.0021 * 50_000.0 / 100.0;
+        assert!((opp.gross_return_percent - (gross - 1.0) * 100.0).abs() < 1e-9);
+        assert_eq!(opp.net_return_percent, opp.gross_return_percent);
+        assert_eq!(serde_json::to_value(&opp).unwrap()["kind"], "triangular");
+    }
+
+    #[test]
+    fn every_leg_pays_the_taker_fee() {
+        // 20 bps base tier, 15 bps at our volume
+        let schedule = |volume_30d| FeeSchedule {
+            tiers: vec![FeeTier {
+                min_volume: 1_000_000.0,
+                maker_bps: 10.0,
+                taker_bps: 15.0,
+            }],
+            volume_30d,
+            ..FeeSchedule::flat(20.0, 20.0)
+        };
+        let mut fees = FeeModel::empty();
+        fees.set_schedule(VENUE, schedule(5_000_000.0));
+        let opp = triangle()
+            .evaluate(VENUE, &prices((0.0021, 0.00211)), &fees)
+            .unwrap();
+
+        let gross = 1.0 + opp.gross_return_percent / 100.0;
+        let net = gross * (1.0f64 - 0.0015).powi(3);
+        assert!((opp.net_return_percent - (net - 1.0) * 100.0).abs() < 1e-9);
+        let fee_bps: Vec<f64> = opp.legs.iter().map(|leg| leg.fee_bps).collect();
+        assert_eq!(fee_bps, [15.0, 15.0, 15.0]);
+
+        // A 0.5% loop doesn't survive three 20 bps legs
+        fees.set_schedule(VENUE, schedule(0.0));
+        let thin = prices((0.00201, 0.00202));
+        assert!(triangle().evaluate(VENUE, &thin, &fees).is_none());
+    }
+
+    #[test]
+    fn picks_the_direction_that_pays() {
+        // SOL is cheap against BTC: buy BTC, then SOL with it, then sell SOL
+        let opp = triangle()
+            .evaluate(VENUE, &prices((0.0019, 0.00191)), &FeeModel::empty())
+            .unwrap();
+        assert_eq!(opp.path, ["USDT", "BTC", "SOL", "USDT"]);
+        let legs: Vec<(&str, TradeSide, f64)> = opp
+            .legs
+            .iter()
+            .map(|leg| (leg.pair.as_str(), leg.side, leg.price))
+            .collect();
+        assert_eq!(
+            legs,
+            [
+                ("BTC/USDT", TradeSide::Buy, 50_010.0),
+                ("SOL/BTC", TradeSide::Buy, 0.00191),
+                ("SOL/USDT", TradeSide::Sell, 99.9),
+            ]
+        );
+        let gross = 99.9 / (50_010.0 * 0.00191);
+        assert!((opp.gross_return_percent - (gross - 1.0) * 100.0).abs() < 1e-9);
+
+        // Neither direction pays between the two
+        assert!(
+            triangle()
+                .evaluate(VENUE, &prices((0.002, 0.00201)), &FeeModel::empty())
+                .is_none()
+        );
+    }
+}